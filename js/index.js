@@ -1,3 +1,8 @@
-import("../pkg/index.js").catch(console.error);
+import("../pkg/index.js")
+  .then((wasm) => {
+    const canvas = document.getElementById("glCanvas");
+    window.renderer = new wasm.Renderer(canvas, { useUrl: true, panel: true });
+  })
+  .catch(console.error);
 var spector = new SPECTOR.Spector();
 spector.displayUI();
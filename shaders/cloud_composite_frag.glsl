@@ -0,0 +1,67 @@
+#version 300 es
+precision mediump float;
+
+uniform sampler2D colorTex;
+#ifdef PACKED_DEPTH
+uniform sampler2D distTex;
+#endif
+uniform sampler2D cloudTex;
+// off while a debug view shows the scene on its own
+uniform bool showClouds;
+
+in vec2 uv;
+
+out vec4 fragColor;
+
+const float FAR_DIST = 1000.0;
+// how far apart, relative to the pixel's own distance, two samples can be before they stop blending
+const float DEPTH_SIGMA = 0.05;
+
+#include "packed_distance"
+
+float sceneDistance(ivec2 texel){
+#ifdef PACKED_DEPTH
+    float dist = unpackDistance(texelFetch(distTex, texel, 0));
+#else
+    float dist = texelFetch(colorTex, texel, 0).a;
+#endif
+    return dist < 0.0 ? FAR_DIST : dist;
+}
+
+void main () {
+    vec2 fullSize = vec2(textureSize(colorTex, 0));
+    ivec2 lowSize = textureSize(cloudTex, 0);
+
+    vec3 prevPass = texture(colorTex, uv).rgb;
+    if(!showClouds){
+        fragColor = vec4(prevPass, 1.0);
+        return;
+    }
+    float dist = sceneDistance(ivec2(uv * fullSize));
+
+    // bilinear upsample where each of the 4 low resolution samples is also weighted by how close the
+    // distance it was marched against is to this pixel's, so clouds don't bleed across silhouettes
+    vec2 lowPos = uv * vec2(lowSize) - 0.5;
+    ivec2 base = ivec2(floor(lowPos));
+    vec2 f = fract(lowPos);
+
+    vec4 cloud = vec4(0.0);
+    float weightSum = 0.0;
+    for(int i=0; i<4; ++i){
+        ivec2 offset = ivec2(i & 1, i >> 1);
+        ivec2 texel = clamp(base + offset, ivec2(0), lowSize - 1);
+
+        vec2 lowUV = (vec2(texel) + 0.5) / vec2(lowSize);
+        float lowDist = sceneDistance(ivec2(lowUV * fullSize));
+
+        vec2 bilinear = mix(1.0 - f, f, vec2(offset));
+        float depthWeight = exp(-abs(lowDist - dist) / (DEPTH_SIGMA * dist)) + 0.0001;
+        float weight = bilinear.x * bilinear.y * depthWeight;
+
+        cloud += texelFetch(cloudTex, texel, 0) * weight;
+        weightSum += weight;
+    }
+    cloud /= weightSum;
+
+    fragColor = vec4(cloud.rgb + prevPass * cloud.a, 1.0);
+}
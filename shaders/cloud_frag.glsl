@@ -2,10 +2,24 @@
 precision mediump float;
 
 uniform sampler2D colorTex;
+uniform mediump sampler3D noiseTex;
+#ifdef PACKED_DEPTH
+uniform sampler2D distTex;
+#endif
 
 uniform mat4 invProjMat;
-uniform mat4 viewProjMat;
 uniform float time;
+uniform sampler2D blueNoiseTex;
+uniform ivec2 blueNoiseOffset;
+uniform vec3 sunDir;
+uniform vec3 sunColor;
+uniform sampler2D skyTex;
+
+uniform float cloudDensityScale;
+uniform float cloudAbsorption;
+uniform float cloudScattering;
+uniform float cloudCoverage;
+uniform float cloudAnisotropy;
 
 in vec2 uv;
 in vec3 rayPosFrag;
@@ -18,153 +32,132 @@ const mat3 ROT_MAT = mat3(
     vec3(-0.28627997636795044, 0.7056889533996582, 0.6281102705001831),
     vec3(0.9133245348930359, -0.0034793566446751356, 0.3572175621986389)
 );
-const vec3 LIGHT_DIR = normalize(vec3(-1, 1, -1));
 
+#include "packed_distance"
 
-float hash(vec2 p){
-        p  = 50.0*fract( p*0.3183099  + vec2(0.71,0.113));
-        return -1.0+2.0*fract( p.x*p.y*(p.x+p.y) );
+// blue noise in [0, 1) for this pixel, shifted every frame
+float blueNoise(){
+    ivec2 size = textureSize(blueNoiseTex, 0);
+    return texelFetch(blueNoiseTex, (ivec2(gl_FragCoord.xy) + blueNoiseOffset) % size, 0).r;
 }
 
-float rand3D(vec3 co){
-    return hash(vec2(hash(co.xy), hash(co.yz)));
-}
-
-vec3 unit_vec(in vec3 xyz) {
-//        float theta = 6.28318530718*rand3D(xyz);
-        float rand1 = hash(xyz.xy * xyz.z);
-        float rand2 = hash(vec2(rand1, xyz.z));
-        float rand3 = hash(vec2(rand1, rand2));
-//        float rand2 = hash(vec2(rand1, xyz.z));
-//        float rand3 = hash(vec2(rand2, rand1));
-        return normalize(vec3(rand1, rand2, rand3));
-}
+// world units covered by one tile of the baked noise volume
+const float SHAPE_PERIOD = 320.0;
+const float DETAIL_PERIOD = 60.0;
+const float DETAIL_EROSION = 0.25;
 
-float smoothmix(float a0, float a1, float w) {
-        return (a1 - a0) * ((w * (w * 6.0 - 15.0) + 10.0) * w * w * w) + a0;
+float remap(float val, float oldMin, float oldMax, float newMin, float newMax){
+    return newMin + (val - oldMin) / (oldMax - oldMin) * (newMax - newMin);
 }
 
-const float PERIODS[] = float[](100.0, 50.0, 20.0, 5.0);
-const float WEIGHTS[] = float[](1.0,0.5,0.05,0.00135);
-
-
-
-const float PERLIN_PERIODS[] = float[](80.0, 30.0, 2.0, 0.5);
-const float PERLIN_WEIGHTS[] = float[](80.0,8.0,7.0,2.0);
-const float PERLIN_OFFSETS[] = float[](-12.0,-1.8,0.0,0.0);
+// perlin-worley cloud shapes eroded by higher frequency worley detail, in roughly [0, 1]
+float cloudNoise(vec3 pos){
+    // offset after rotating so the noise still tiles along each axis, see CLOUD_TIME_PERIOD
+    vec3 rotPos = ROT_MAT * pos + vec3(time);
 
+    vec4 shape = texture(noiseTex, rotPos / SHAPE_PERIOD);
+    float shapeWorley = dot(shape.gba, vec3(0.625, 0.25, 0.125));
+    float base = remap(shape.r, shapeWorley - 1.0, 1.0, 0.0, 1.0);
 
-float perlin3D(vec3 pos){
-
-    vec3 rotPos = ROT_MAT * (pos + vec3(10000.0)  + vec3(time ) );
-    float val = 0.0;
-    for(int i=0; i<4; ++i){
-        float weight = PERLIN_WEIGHTS[i];
-        float freq = 1.0/PERLIN_PERIODS[i];
-
-        vec3 noisePos = rotPos * freq;
-//        rotPos = ROT_MAT * rotPos;
-        vec3 floorPos = floor(noisePos);
-        vec3 fracPos = fract(noisePos);
+    vec4 detail = texture(noiseTex, rotPos / DETAIL_PERIOD);
+    float detailWorley = dot(detail.gba, vec3(0.625, 0.25, 0.125));
+    return base - (1.0 - detailWorley) * DETAIL_EROSION;
+}
 
-        vec2 sampleVec = vec2(0.0, 1.0);
 
-        float p1 = dot(unit_vec(floorPos), noisePos - floorPos);
-        float p2 = dot(unit_vec(floorPos + sampleVec.xxy), noisePos - sampleVec.xxy - floorPos);
-        float p3 = dot(unit_vec(floorPos + sampleVec.xyx), noisePos - sampleVec.xyx - floorPos);
-        float p4 = dot(unit_vec(floorPos + sampleVec.xyy), noisePos - sampleVec.xyy - floorPos);
+const int ITERATIONS = 100;
+const float STEP = 4.0;
 
-        float p5 = dot(unit_vec(floorPos + sampleVec.yxx), noisePos - sampleVec.yxx - floorPos);
-        float p6 = dot(unit_vec(floorPos + sampleVec.yxy), noisePos - sampleVec.yxy - floorPos);
-        float p7 = dot(unit_vec(floorPos + sampleVec.yyx), noisePos - sampleVec.yyx - floorPos);
-        float p8 = dot(unit_vec(floorPos + sampleVec.yyy), noisePos - sampleVec.yyy - floorPos);
+const int LIGHT_ITERATIONS = 6;
+const float LIGHT_STEP = 6.0;
+const float PI = 3.14159265359;
 
-        float s1 = smoothmix(p1, p5, fracPos.x);
-        float s2 = smoothmix(p2, p6, fracPos.x);
-        float s3 = smoothmix(p3, p7, fracPos.x);
-        float s4 = smoothmix(p4, p8, fracPos.x);
+// radiance from the sky-view lookup texture rendered by the sky pass
+vec3 skyRadiance(vec3 dir){
+    float elevation = asin(clamp(dir.y, -1.0, 1.0));
+    float v = sign(elevation) * sqrt(abs(elevation) / (PI * 0.5));
+    return texture(skyTex, vec2(atan(dir.z, dir.x) / (2.0 * PI), 0.5 + 0.5 * v)).rgb;
+}
 
-        float s5 = smoothmix(s1, s3, fracPos.y);
-        float s6 = smoothmix(s2, s4, fracPos.y);
+// only the top `cloudCoverage` of the noise range is cloud, so a coverage of 0 is clear
+float cloudDensity(vec3 pos){
+    float noise = clamp(cloudNoise(pos), 0.0, 1.0);
+    return max(noise - (1.0 - cloudCoverage), 0.0) * cloudDensityScale;
+}
 
+// https://www.oceanopticsbook.info/view/scattering/level-2/the-henyey-greenstein-phase-function
+float henyeyGreenstein(float cosTheta, float g){
+    float g2 = g * g;
+    return (1.0 - g2) / (4.0 * PI * pow(1.0 + g2 - 2.0 * g * cosTheta, 1.5));
+}
 
-        //val += weight * p2;
-        val += weight * smoothmix(s5, s6, fracPos.z) + PERLIN_OFFSETS[i];
+// transmittance from pos towards the sun through the cloud volume
+float lightMarch(vec3 pos){
+    float opticalDepth = 0.0;
+    float stepSize = LIGHT_STEP;
+    float t = stepSize * 0.5;
+    for(int i=0; i<LIGHT_ITERATIONS; ++i){
+        opticalDepth += cloudDensity(pos + sunDir * t) * stepSize;
+        stepSize *= 1.5;
+        t += stepSize;
     }
-    return val * 0.5 + 1.0;
+    return exp(-opticalDepth * (cloudAbsorption + cloudScattering));
 }
 
-const float THRESH = 0.001;
-
-const int ITERATIONS = 100;
-const float TO_ADD = 1.0/float(ITERATIONS);
-const float STEP = 4.0;
-
-float cloudMarch(vec3 rayPos, vec3 rayDir, float prevT, out float volumeFactor){
-
-    float count = 0.0;
-    float t = 0.0;
-    float dist;
-    float th;
-    float breakThresh = (count + 1.2 - hash(uv * time) * 0.4);
-    for(int i=0; i<ITERATIONS; ++i){
+// single scattering march, returns the light scattered towards the camera and the transmittance of the
+// volume between the camera and prevT
+vec4 cloudMarch(vec3 rayPos, vec3 rayDir, float prevT){
+    float phase = henyeyGreenstein(dot(rayDir, sunDir), cloudAnisotropy);
+    vec3 ambient = skyRadiance(vec3(0.0, 1.0, 0.0));
+    float extinctionCoeff = cloudAbsorption + cloudScattering;
+
+    vec3 scattered = vec3(0.0);
+    float transmittance = 1.0;
+    float t = STEP * blueNoise();
+    for(int i=0; i<ITERATIONS && t<prevT; ++i){
+        float stepSize = STEP * (2.0 + t / 40.0);
         vec3 pos = rayPos + t * rayDir;
-        th =  THRESH * (hash(vec2(dist, rayDir.x))*0.20+0.80);
 
-        float density = max(perlin3D(pos) - 0.6 + 0.1* sqrt(t), 0.0);
-        count += density * TO_ADD;
+        float density = cloudDensity(pos);
+        if(density > 0.0){
+            float sigmaS = density * cloudScattering;
+            float sigmaT = max(density * extinctionCoeff, 0.00001);
 
-        float densityFactor = clamp( (1.0 - density * 2.0 - hash(uv * time) * 0.2)
-        , 0.0, 1.0) * hash(vec2(dist * time));
+            vec3 inScatter = sigmaS * (sunColor * lightMarch(pos) * phase + ambient / (4.0 * PI));
+            float stepTransmittance = exp(-sigmaT * min(stepSize, prevT - t));
 
+            // integrates the in-scattering analytically over the step so thick steps don't gain energy
+            scattered += transmittance * (inScatter - inScatter * stepTransmittance) / sigmaT;
+            transmittance *= stepTransmittance;
+            if(transmittance < 0.01) break;
+        }
 
-        t += (STEP * t * sqrt(t) )/1000.0
-        + STEP * t / 40.0
-        + 2.0 * STEP
-        - 2.5 * STEP * densityFactor;
-        if(count > 0.3) break;
-        if(t * breakThresh > prevT) break;
+        t += stepSize;
     }
 
-    volumeFactor = clamp(exp(-count * 20.0) * 2.0, 0.0, 1.0); // * (1.0-exp(-count * 2.0));
-    return t;
+    return vec4(scattered, transmittance);
 }
 
-vec3 cloudNormal(vec3 pos){
-    const float epsilon = 0.001;
-    const vec2 delta = vec2(1.0, -1.0);
-    return normalize(vec3(
-        delta.xyy * perlin3D(pos + delta.xyy * epsilon) +
-        delta.yyx * perlin3D(pos + delta.yyx * epsilon) +
-        delta.yxy * perlin3D(pos + delta.yxy * epsilon) +
-        delta.xxx * perlin3D(pos + delta.xxx * epsilon)
-        ));
+// point samples the fractal pass distance at the center of this low resolution pixel, the
+// composite pass reads the same texel when weighting its upsample
+float sceneDistance(){
+    ivec2 texel = ivec2(uv * vec2(textureSize(colorTex, 0)));
+#ifdef PACKED_DEPTH
+    return unpackDistance(texelFetch(distTex, texel, 0));
+#else
+    return texelFetch(colorTex, texel, 0).a;
+#endif
 }
 
 void main () {
     vec3 rayDir = normalize(rayDirFrag);
     vec3 rayPos = rayPosFrag + rayDir*0.0001;
 
-    vec3 prevPass = texture(colorTex, uv).rgb;
-    float prevT = texture(colorTex, uv).a;
+    float prevT = sceneDistance();
     if(prevT < 0.0){
         prevT = 300.0;
     }
 
-    float volumeFactor;
-    float t = cloudMarch(rayPos, rayDir, prevT, volumeFactor);
-    vec3 finalRayPos = rayPos + rayDir * t;
-
-    vec4 projCoords = viewProjMat * vec4(finalRayPos, 1.0);
-    vec4 projCoordsPrev = viewProjMat * vec4(rayPos + prevT * rayDir, 1.0);
-    float depth = ((projCoords.z / projCoords.w) + 1.0) * 0.5;
-    float prevDepth = ((projCoordsPrev.z / projCoordsPrev.w) + 1.0) * 0.5;
-
-
-    gl_FragDepth = -0.999;
-    vec3 normal = cloudNormal(finalRayPos);
-    vec3 cloudCol = vec3(0.8, 0.8, 0.8) * volumeFactor;
-//    vec3 col = mix(cloudCol, prevPass, volumeFactor);
-    fragColor = vec4(cloudCol * prevPass, 1.0);
-
+    // rgb is light scattered by the cloud and alpha is how much of the scene behind it shows through
+    fragColor = cloudMarch(rayPos, rayDir, prevT);
 }
\ No newline at end of file
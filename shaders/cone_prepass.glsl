@@ -0,0 +1,55 @@
+// The cone prepass shared by the marchers, see SNIPPETS in shaders.rs. Built with CONE_PREPASS
+// defined, the shader marches cones at a fraction of the resolution and writes how far each
+// pixel's rays can skip ahead. The including shader declares uv, rayPosFrag, rayDirFrag,
+// fragColor, FAR_PLANE and `float marchDistance(vec3 pos)` first, includes "packed_distance"
+// and leaves out its own main in the prepass variant.
+
+// see MARCH_PARAMS in render_passes.rs
+uniform bool prepass;
+// ray distances from the cone prepass, at a fraction of the resolution
+uniform sampler2D prepassTex;
+
+const int PREPASS_STEPS = 64;
+// cone radius relative to the angle between neighbouring prepass pixels, enough to cover a pixel
+const float PREPASS_CONE = 0.75;
+// rays start a little short of the prepass distance, it's stored with limited precision
+const float PREPASS_MARGIN = 0.99;
+
+// Marches a cone as wide as a pixel, only stepping as far as the whole cone is known to be empty.
+// Nothing is hit before the distance it returns by any ray through the pixel.
+float coneMarch(vec3 rayPos, vec3 rayDir){
+    float coneRatio = PREPASS_CONE * max(length(dFdx(rayDir)), length(dFdy(rayDir)));
+    float t = 0.0;
+    for(int i=0; i<PREPASS_STEPS; ++i){
+        float dist = marchDistance(rayPos + t * rayDir);
+        float coneRadius = t * coneRatio;
+        if(dist < 2.0 * coneRadius || dist > FAR_PLANE) break;
+        t += (dist - coneRadius) / (1.0 + coneRatio);
+    }
+    return t;
+}
+
+// where this pixel's ray can start, from the cone prepass if it ran
+float prepassDistance(){
+    if(!prepass) return 0.0;
+    ivec2 size = textureSize(prepassTex, 0);
+    ivec2 texel = min(ivec2(uv * vec2(size)), size - 1);
+#ifdef PACKED_DEPTH
+    float dist = unpackDistance(texelFetch(prepassTex, texel, 0));
+#else
+    float dist = texelFetch(prepassTex, texel, 0).r;
+#endif
+    return max(dist, 0.0) * PREPASS_MARGIN;
+}
+
+#ifdef CONE_PREPASS
+void main () {
+    vec3 rayDir = normalize(rayDirFrag);
+    float dist = coneMarch(rayPosFrag + rayDir * 0.0001, rayDir);
+#ifdef PACKED_DEPTH
+    fragColor = packDistance(dist);
+#else
+    fragColor = vec4(dist);
+#endif
+}
+#endif
@@ -0,0 +1,14 @@
+#version 300 es
+precision mediump float;
+
+uniform sampler2D snapshotTex;
+uniform float opacity;
+
+in vec2 uv;
+
+out vec4 fragColor;
+
+// draws the last frame of the previous scene over the new one while it fades out
+void main () {
+    fragColor = vec4(texture(snapshotTex, uv).rgb, opacity);
+}
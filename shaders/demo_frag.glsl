@@ -4,6 +4,13 @@ precision mediump float;
 uniform mat4 invProjMat;
 uniform mat4 viewProjMat;
 uniform float time;
+uniform sampler2D blueNoiseTex;
+uniform ivec2 blueNoiseOffset;
+uniform vec3 sunDir;
+uniform vec3 sunColor;
+uniform sampler2D skyTex;
+// see MARCH_PARAMS, `relaxation` of 1 is plain sphere tracing
+uniform float relaxation;
 
 in vec2 uv;
 in vec3 rayPosFrag;
@@ -11,9 +18,33 @@ in vec3 rayDirFrag;
 
 out vec4 fragColor;
 
+#include "packed_distance"
+
 const float PI_2_10 = (2.0*3.14159265359)/10.0;
-const vec3 LIGHT_DIR = normalize(vec3(-1, 1, -1));
+const float PI = 3.14159265359;
 const float THRESH = 0.0001;
+const float FAR_PLANE = 200.0;
+const int MAX_STEPS = 150;
+
+#include "march_debug"
+
+// blue noise in [0, 1) for this pixel, shifted every frame
+float blueNoise(){
+    ivec2 size = textureSize(blueNoiseTex, 0);
+    return texelFetch(blueNoiseTex, (ivec2(gl_FragCoord.xy) + blueNoiseOffset) % size, 0).r;
+}
+
+// radiance from the sky-view lookup texture rendered by the sky pass
+vec3 skyRadiance(vec3 dir){
+    float elevation = asin(clamp(dir.y, -1.0, 1.0));
+    float v = sign(elevation) * sqrt(abs(elevation) / (PI * 0.5));
+    return texture(skyTex, vec2(atan(dir.z, dir.x) / (2.0 * PI), 0.5 + 0.5 * v)).rgb;
+}
+
+// sky with the sun disc on top
+vec3 skyColor(vec3 dir){
+    return skyRadiance(dir) + sunColor * 4.0 * smoothstep(0.9995, 0.9998, dot(dir, sunDir));
+}
 
 float plane(vec3 pos){
     return pos.y;
@@ -181,30 +212,56 @@ float shadow(vec3 rayPos, vec3 rayDir, float minT, float maxT, float k){
     return res;
 }
 
-float rayMarch(vec3 rayPos, vec3 rayDir, out vec3 col){
-    float t = 0.0;
+// Over-relaxed sphere tracing, see rayMarch in fractal_frag.glsl.
+float rayMarch(vec3 rayPos, vec3 rayDir, float startT, out vec3 col){
+    float t = startT;
     float dist;
     float th;
     float resy;
-    for(int i=0; i<150; ++i){
+    float omega = relaxation;
+    float prevDist = 0.0;
+    float stepLength = 0.0;
+    lastMarch.steps = 0;
+    lastMarch.result = MARCH_MAX_STEPS;
+    for(int i=0; i<MAX_STEPS; ++i){
         vec3 pos = rayPos + t * rayDir;
 
         vec2 res = sceneSDF(pos);
-        float dist = res.x;
+        dist = res.x;
         resy = res.y;
+        if(omega > 1.0 && abs(dist) + prevDist < stepLength){
+            t += prevDist - stepLength;
+            stepLength = prevDist;
+            omega = 1.0;
+            lastMarch.steps++;
+            continue;
+        }
 
         th =  t * THRESH; // * (rand(vec2(t, rayPos.x))*0.2+0.8);
-        if(dist < th || dist > 200.0) break;
-        t += dist;
+        if(dist < th){
+            lastMarch.result = MARCH_HIT;
+            break;
+        }
+        if(dist > FAR_PLANE){
+            lastMarch.result = MARCH_FAR;
+            break;
+        }
+        stepLength = dist * omega;
+        prevDist = dist;
+        t += stepLength;
+        lastMarch.steps++;
     }
     col = 0.2 + 0.2*sin( resy*2.0 + vec3(0.0,1.0,2.0) );
-    if(dist< th){
-        return t;
-    }else{
-        return -1.0;
-    }
+    lastMarch.material = resy;
+    return lastMarch.result == MARCH_HIT ? t : -1.0;
+}
+
+float marchDistance(vec3 pos){
+    return sceneSDF(pos).x;
 }
 
+#include "cone_prepass"
+
 vec3 sceneNormal(vec3 pos){
     vec3 col;
     const float epsilon = 0.0001;
@@ -217,25 +274,24 @@ vec3 sceneNormal(vec3 pos){
         ));
 }
 
-const vec3 BG = vec3(0.3, 0.7254903, 0.7254903) * 1.7;
 //const vec3 FOG = vec3(0.0, 0.37254903, 0.37254903);
 
-float rayCast(vec3 rayPos, vec3 rayDir, out vec3 col){
-    float dist = rayMarch(rayPos, rayDir, col);
+float rayCast(vec3 rayPos, vec3 rayDir, float startT, out vec3 col, out float shadowFactor){
+    float dist = rayMarch(rayPos, rayDir, startT, col);
     vec3 finalRayPos = rayPos + rayDir * dist;
 
     vec3 normal = sceneNormal(finalRayPos);
-    float shadowFactor = shadow(finalRayPos + normal * 0.01, LIGHT_DIR, 0.001, 500.0, 0.3);
+    shadowFactor = shadow(finalRayPos + normal * (0.01 + blueNoise() * 0.01), sunDir, 0.001, 500.0, 0.3);
 
 
     if(dist > 200.0 || dist < 0.0){
-        col = mix(BG, vec3(1.0, 1.0, 0.90), smoothstep( 0.999, 1.0, dot(rayDir, LIGHT_DIR)));
+        col = skyColor(rayDir);
         return -1.0;
     }else{
-        vec3 ambient = 0.3 * col;
-        vec3 diffuse = 0.5 * col * clamp(dot(normal, normalize(LIGHT_DIR)), 0.01, 1.0);
-        vec3 reflectDir = reflect(LIGHT_DIR, normal);
-        vec3 specular = vec3(0.2) * pow(max(dot(rayDir, reflectDir), 0.0), 2.05);
+        vec3 ambient = 0.3 * col * skyRadiance(vec3(0.0, 1.0, 0.0)) * 4.0;
+        vec3 diffuse = 0.5 * col * sunColor * clamp(dot(normal, sunDir), 0.01, 1.0);
+        vec3 reflectDir = reflect(sunDir, normal);
+        vec3 specular = 0.2 * sunColor * pow(max(dot(rayDir, reflectDir), 0.0), 2.05);
         col = ambient + diffuse + specular;
         col *= shadowFactor;
         col *= 1.3;
@@ -264,14 +320,23 @@ vec3 smallScatter(vec3 rayDir, vec3 seed){
     return normalize(rayDir*0.995 + rand_vec(rayDir)*0.005);
 }
 
+#ifndef CONE_PREPASS
 void main () {
     vec3 rayDir = normalize(rayDirFrag);
     vec3 rayPos = rayPosFrag + rayDir * 0.0001;
 
     vec3 col;
-    float dist = rayCast(rayPos, rayDir, col);
+    float shadowFactor;
+    float dist = rayCast(rayPos, rayDir, prepassDistance(), col, shadowFactor);
+    March primary = lastMarch;
     vec3 finalRayPos = rayPos + rayDir * dist;
 
+    if(debugView != DEBUG_OFF){
+        gl_FragDepth = 0.999999;
+        fragColor = vec4(debugColor(primary, dist, sceneNormal(finalRayPos), shadowFactor), 1.0);
+        return;
+    }
+
 
 
     if(dist > 200.0 || dist < 0.0){
@@ -283,13 +348,15 @@ void main () {
 
         vec3 normal = sceneNormal(finalRayPos);
         vec3 reflectCol;
+        float reflectShadow;
         vec3 reflectDir = reflect(rayDir, normal);
-        float reflection = rayCast(finalRayPos + normal * 0.01, smallScatter(reflectDir, finalRayPos), reflectCol);
+        float reflection = rayCast(finalRayPos + normal * 0.01, smallScatter(reflectDir, finalRayPos), 0.0, reflectCol, reflectShadow);
         vec3 reflectPos = finalRayPos + normal * 0.01 + reflection * reflectDir;
-        reflectCol = mix(reflectCol, BG, smoothstep(0.6, 1.0, clamp(reflection/100.0, 0.0, 1.0)));
+        reflectCol = mix(reflectCol, skyRadiance(reflectDir), smoothstep(0.6, 1.0, clamp(reflection/100.0, 0.0, 1.0)));
         col = col + reflectCol * 0.1;
     }
-    col = mix(col, BG, smoothstep(0.6, 1.0, clamp(dist/100.0, 0.0, 1.0)));
+    col = mix(col, skyRadiance(rayDir), smoothstep(0.6, 1.0, clamp(dist/100.0, 0.0, 1.0)));
     col = 1.0 - exp(-col * 2.0);
     fragColor = vec4(col, 1.0);
-}
\ No newline at end of file
+}
+#endif
@@ -4,24 +4,67 @@ precision mediump float;
 uniform mat4 invProjMat;
 uniform mat4 viewProjMat;
 uniform float time;
+uniform sampler2D blueNoiseTex;
+uniform ivec2 blueNoiseOffset;
+uniform vec3 sunDir;
+uniform vec3 sunColor;
+uniform sampler2D skyTex;
+
+// tunable from the params panel, see FRACTAL_PARAMS in render_passes.rs
+// the Mandelbulb's exponent
+uniform float exponent;
+uniform int iterations;
+uniform vec3 bulbPos;
+uniform float bulbScale;
+uniform int mengerIterations;
+uniform float marchThreshold;
+uniform vec3 albedo;
+uniform bool shadows;
+// see MARCH_PARAMS, `relaxation` of 1 is plain sphere tracing
+uniform float relaxation;
 
 in vec2 uv;
 in vec3 rayPosFrag;
 in vec3 rayDirFrag;
 
+#ifdef PACKED_DEPTH
+layout(location = 0) out vec4 fragColor;
+layout(location = 1) out vec4 distColor;
+#else
 out vec4 fragColor;
+#endif
+
+#include "packed_distance"
 
-const vec3 BULB_POS = vec3(11.0, 0.0, 11.0);
-const float BULB_SCALE = 10.0;
-const float THRESH = 0.003;
 const float FAR_PLANE = 1000.0;
-const vec3 LIGHT_DIR = normalize(vec3(-1, 1, -1));
+const float PI = 3.14159265359;
+const int MAX_STEPS = 150;
+
+#include "march_debug"
 
 float rand(vec2 p){
         p  = 50.0*fract( p*0.3183099  + vec2(0.71,0.113));
         return fract( p.x*p.y*(p.x+p.y) );
 }
 
+// blue noise in [0, 1) for this pixel, shifted every frame
+float blueNoise(){
+    ivec2 size = textureSize(blueNoiseTex, 0);
+    return texelFetch(blueNoiseTex, (ivec2(gl_FragCoord.xy) + blueNoiseOffset) % size, 0).r;
+}
+
+// radiance from the sky-view lookup texture rendered by the sky pass
+vec3 skyRadiance(vec3 dir){
+    float elevation = asin(clamp(dir.y, -1.0, 1.0));
+    float v = sign(elevation) * sqrt(abs(elevation) / (PI * 0.5));
+    return texture(skyTex, vec2(atan(dir.z, dir.x) / (2.0 * PI), 0.5 + 0.5 * v)).rgb;
+}
+
+// sky with the sun disc on top
+vec3 skyColor(vec3 dir){
+    return skyRadiance(dir) + sunColor * 4.0 * smoothstep(0.9995, 0.9998, dot(dir, sunDir));
+}
+
 // https://iquilezles.org/articles/intersectors/
 vec2 intersectSphere(vec3 rayPos, vec3 rayDir, vec3 spherePos, float sphereSize){
     vec3 oc = rayPos - spherePos;
@@ -49,29 +92,26 @@ vec2 intersectCube(vec3 rayPos, vec3 rayDir, float size, out vec3 normal){
     return vec2( tN, tF );
 }
 
-// https://www.shadertoy.com/view/ltfSWn
-const int ITERATIONS = 4;
-const float POWER = 3.5;
+// https://www.shadertoy.com/view/ltfSWn, with the exponent 8 generalized to `exponent`. Takes and
+// returns distances in the bulb's own space, see mengerSpongeSdf.
 float calcBulbDist(vec3 pos){
-    pos = pos - BULB_POS;
-    pos /= BULB_SCALE;
     vec3 w = pos;
     float m = dot(w,w);
     float dz = 1.0;
-	for (int i = 0; i < ITERATIONS ; i++) {
-        // dz = 8*z^7*dz
-		dz = 8.0*pow(m,POWER)*dz + 1.0;
+	for (int i = 0; i < iterations ; i++) {
+        // dz = exponent*z^(exponent-1)*dz, m is z^2
+		dz = exponent*pow(m,(exponent-1.0)*0.5)*dz + 1.0;
 
-        // z = z^8+c
+        // z = z^exponent+c
         float r = length(w);
-        float b = 8.0*acos( w.y/r);
-        float a = 8.0*atan( w.x, w.z );
-        w = pos + pow(r,8.0) * vec3( sin(b)*sin(a), cos(b), sin(b)*cos(a) );
+        float b = exponent*acos( w.y/r);
+        float a = exponent*atan( w.x, w.z );
+        w = pos + pow(r,exponent) * vec3( sin(b)*sin(a), cos(b), sin(b)*cos(a) );
         m = dot(w,w);
 		if( m > 1200.0 )
             break;
 	}
-	return BULB_SCALE * 0.25*log(m)*sqrt(m)/dz;
+	return 0.25*log(m)*sqrt(m)/dz;
 }
 
 float cubeSDF(vec3 pos){
@@ -87,16 +127,16 @@ float sdCross(vec3 pos){
   return min(da,min(db,dc));
 }
 
-const int MENGER_ITER = 8;
-float mengerSpongeSdf(vec3 pos, out vec3 col){
-    pos = pos - BULB_POS;
-    pos /= BULB_SCALE;
+// material is 0 on the bulb's surface, or the level of the sponge that carved the surface out
+float mengerSpongeSdf(vec3 pos, out float material){
+    pos = pos - bulbPos;
+    pos /= bulbScale;
     float dist = calcBulbDist(pos);
 
-    col = vec3(dist, 1.0, dist);
+    material = 0.0;
 
     float scale = 1.0;
-    for(int i=0; i<MENGER_ITER; ++i){
+    for(int i=0; i<mengerIterations; ++i){
         vec3 posScaled = mod(pos*scale, 2.0) - 1.0;
         scale *= 3.0;
         vec3 posScaledTranslated = 1.0 - 3.0*abs(posScaled);
@@ -104,25 +144,25 @@ float mengerSpongeSdf(vec3 pos, out vec3 col){
 
         float crossDist = sdCross(posScaledTranslated)/scale;
         if(crossDist > dist){
-            col *= 0.75;
+            material = float(i + 1);
         }
         dist = max(dist, crossDist);
 
     }
 
 
-    return BULB_SCALE * dist;
+    return bulbScale * dist;
 }
 
 vec3 mengerNormal(vec3 pos){
     const float epsilon = 0.0001;
     const vec2 delta = vec2(1, -1);
-    vec3 col;
+    float material;
     return normalize(vec3(
-        delta.xyy * mengerSpongeSdf(pos + delta.xyy * epsilon, col) +
-        delta.yyx * mengerSpongeSdf(pos + delta.yyx * epsilon, col) +
-        delta.yxy * mengerSpongeSdf(pos + delta.yxy * epsilon, col) +
-        delta.xxx * mengerSpongeSdf(pos + delta.xxx * epsilon, col)
+        delta.xyy * mengerSpongeSdf(pos + delta.xyy * epsilon, material) +
+        delta.yyx * mengerSpongeSdf(pos + delta.yyx * epsilon, material) +
+        delta.yxy * mengerSpongeSdf(pos + delta.yxy * epsilon, material) +
+        delta.xxx * mengerSpongeSdf(pos + delta.xxx * epsilon, material)
         ));
 }
 
@@ -142,10 +182,10 @@ float shadow(vec3 rayPos, vec3 rayDir, float minT, float maxT, float k){
     float t = minT;
     float res = 1.0;
     float prevDist = 0.00000000000000000001;
-    vec3 col;
+    float material;
     for(int i=0; i<25 && t<maxT; ++i){
         vec3 pos = rayPos + t * rayDir;
-        float dist = mengerSpongeSdf(pos, col);
+        float dist = mengerSpongeSdf(pos, material);
         if(dist < minT * 10.0 * t) return 0.0;
 
         float y = (dist*dist) /(2.0 * prevDist);
@@ -159,57 +199,94 @@ float shadow(vec3 rayPos, vec3 rayDir, float minT, float maxT, float k){
 }
 
 
-float rayMarch(vec3 rayPos, vec3 rayDir, out vec3 col){
-
-    vec2 boundingSphereDistance = intersectSphere(rayPos, rayDir, BULB_POS, BULB_SCALE*1.25);
-//    if(boundingSphereDistance.y < 0.0) return -1.0;
-    boundingSphereDistance.x = max(boundingSphereDistance.x, 0.0);
-
-    float t = 0.0;
+// Over-relaxed sphere tracing from "Enhanced Sphere Tracing" by Keinert et al. Steps are
+// stretched by `relaxation` until the bounding spheres of two steps in a row stop overlapping,
+// then the last step may have jumped over a surface so it's redone unstretched and the rest of
+// the march is a plain sphere trace.
+float rayMarch(vec3 rayPos, vec3 rayDir, float startT, out float material){
+    float t = startT;
     float dist;
     float th;
-    for(int i=0; i<150; ++i){
+    float omega = relaxation;
+    float prevDist = 0.0;
+    float stepLength = 0.0;
+    lastMarch.steps = 0;
+    lastMarch.result = MARCH_MAX_STEPS;
+    for(int i=0; i<MAX_STEPS; ++i){
         vec3 pos = rayPos + t * rayDir;
-        dist = mengerSpongeSdf(pos, col);
-        th =  t * THRESH * (rand(vec2(t, rayPos.x))*0.2+0.8);
-        if(dist < th || dist > 500.0) break;
-        t += dist;
+        dist = mengerSpongeSdf(pos, material);
+        if(omega > 1.0 && abs(dist) + prevDist < stepLength){
+            t += prevDist - stepLength;
+            stepLength = prevDist;
+            omega = 1.0;
+            lastMarch.steps++;
+            continue;
+        }
+        th =  t * marchThreshold * (rand(vec2(t, rayPos.x))*0.2+0.8);
+        if(dist < th){
+            lastMarch.result = MARCH_HIT;
+            break;
+        }
+        if(dist > 500.0){
+            lastMarch.result = MARCH_FAR;
+            break;
+        }
+        stepLength = dist * omega;
+        prevDist = dist;
+        t += stepLength;
+        lastMarch.steps++;
     }
+    lastMarch.material = material;
 
+    return lastMarch.result == MARCH_HIT ? t : -1.0;
+}
 
-    if(dist< th){
-        return t;
-    }else{
-        return -1.0;
-    }
+float marchDistance(vec3 pos){
+    float material;
+    return mengerSpongeSdf(pos, material);
 }
 
-const float BG = 0.37254903;
+#include "cone_prepass"
+
+#ifndef CONE_PREPASS
 void main () {
     vec3 rayDir = normalize(rayDirFrag);
     vec3 rayPos = rayPosFrag + rayDir*0.0001;
-    vec3 col;
-    float dist = rayMarch(rayPos, rayDir, col);
+    float material;
+    float dist = rayMarch(rayPos, rayDir, prepassDistance(), material);
+    March primary = lastMarch;
     vec3 finalRayPos = rayPos + rayDir * dist;
 //    vec3 lightDir = normalize(LIGHT_POS - finalRayPos);
     vec3 normal = mengerNormal(finalRayPos);
-    float shadowFactor = shadow(finalRayPos + normal * (0.01 + rand(vec2(uv * time)) * 0.02), LIGHT_DIR, 0.001, 500.0, 0.5);
+    float shadowFactor = shadows
+        ? shadow(finalRayPos + normal * (0.01 + blueNoise() * 0.02), sunDir, 0.001, 500.0, 0.5)
+        : 1.0;
 
 //    fragColor = vec4(float(rayDir.x > 0.0), float(rayDir.y > 0.0), float(rayDir.z > 0.0), 1.0);
 
     if(dist < 0.0){
         gl_FragDepth = 0.999999;
 //        fragColor = vec4(vec3(0.4, 0.4, 0.41), -1.0);
-        fragColor = vec4(
-            mix(vec3(0.4, 0.4, 0.41), vec3(1.0, 1.0, 0.90), smoothstep( 0.999, 1.0, dot(rayDir, LIGHT_DIR))), -1.0);
+        fragColor = vec4(skyColor(rayDir), -1.0);
     }else{
         vec4 projCoords = viewProjMat * vec4(finalRayPos, 1.0);
         float depth = ((projCoords.z / projCoords.w) + 1.0) * 0.5;
         gl_FragDepth = depth;
 
-        fragColor = vec4(vec3(1.0, 1.0, 1.0)
-        * clamp(dot(normal, normalize(LIGHT_DIR)), 0.01, 1.0)
+        fragColor = vec4(albedo * (sunColor
+        * clamp(dot(normal, sunDir), 0.01, 1.0)
         * shadowFactor
+        + skyRadiance(vec3(0.0, 1.0, 0.0)) * 0.2)
         , dist);
     }
+
+    if(debugView != DEBUG_OFF){
+        fragColor.rgb = debugColor(primary, dist, normal, shadowFactor);
+    }
+
+#ifdef PACKED_DEPTH
+    distColor = packDistance(fragColor.a);
+    fragColor.a = 1.0;
+#endif
 }
+#endif
@@ -0,0 +1,38 @@
+// Debug views shared by the marchers, see SNIPPETS in shaders.rs. A define for each view comes
+// from DebugView::shader_defines in debug_view.rs. The including shader declares MAX_STEPS,
+// FAR_PLANE, PI and sunDir first.
+
+// what to show instead of the shaded scene
+uniform int debugView;
+
+// how a march ended, for the debug views
+const int MARCH_HIT = 0;
+const int MARCH_MAX_STEPS = 1;
+const int MARCH_FAR = 2;
+
+struct March{
+    int steps;
+    int result;
+    float material;
+};
+
+// filled in by the shader's rayMarch
+March lastMarch;
+
+// blue through green to red as x goes from 0 to 1
+vec3 heatmap(float x){
+    return clamp(1.5 - abs(4.0 * clamp(x, 0.0, 1.0) - vec3(3.0, 2.0, 1.0)), 0.0, 1.0);
+}
+
+vec3 debugColor(March march, float dist, vec3 normal, float shadowFactor){
+    bool hit = march.result == MARCH_HIT;
+    if(debugView == DEBUG_STEPS) return heatmap(float(march.steps) / float(MAX_STEPS));
+    if(debugView == DEBUG_NORMALS) return hit ? normal * 0.5 + 0.5 : vec3(0.0);
+    if(debugView == DEBUG_DEPTH) return hit ? vec3(1.0 - log(1.0 + dist) / log(1.0 + FAR_PLANE)) : vec3(0.0);
+    if(debugView == DEBUG_MATERIAL) return hit ? 0.5 + 0.5 * cos(2.0 * PI * (march.material * 0.618 + vec3(0.0, 0.33, 0.67))) : vec3(0.0);
+    if(debugView == DEBUG_SHADOW) return hit ? vec3(shadowFactor) : vec3(0.0);
+    // misses, hits are shaded gray
+    if(march.result == MARCH_MAX_STEPS) return vec3(1.0, 0.0, 0.0);
+    if(march.result == MARCH_FAR) return vec3(0.0, 0.0, 1.0);
+    return vec3(0.2 + 0.5 * max(dot(normal, sunDir), 0.0));
+}
@@ -0,0 +1,19 @@
+// Ray distances packed into the 4 bytes of an RGBA8 target, for when float targets aren't
+// available. See SNIPPETS in shaders.rs.
+#ifdef PACKED_DEPTH
+const float DIST_SCALE = 1024.0;
+
+// misses and anything else not in front of the camera are stored as 0
+highp vec4 packDistance(highp float dist){
+    if(dist <= 0.0) return vec4(0.0);
+    highp float v = clamp(dist / DIST_SCALE, 0.000001, 0.999999);
+    highp vec4 enc = fract(v * vec4(1.0, 255.0, 65025.0, 16581375.0));
+    return enc - enc.yzww * vec4(1.0/255.0, 1.0/255.0, 1.0/255.0, 0.0);
+}
+
+// inverse of packDistance, misses come back as -1
+highp float unpackDistance(highp vec4 enc){
+    highp float v = dot(enc, vec4(1.0, 1.0/255.0, 1.0/65025.0, 1.0/16581375.0));
+    return v <= 0.0 ? -1.0 : v * DIST_SCALE;
+}
+#endif
@@ -0,0 +1,95 @@
+#version 300 es
+precision highp float;
+
+uniform vec3 sunDir;
+
+in vec2 uv;
+
+out vec4 fragColor;
+
+// Renders a sky-view lookup texture: u is the azimuth and v is the elevation, squashed towards the
+// horizon where the sky changes the fastest. Single scattering of Rayleigh and Mie particles
+// through an exponential atmosphere, see https://sebh.github.io/publications/egsr2020.pdf
+
+const float PI = 3.14159265359;
+const float PLANET_RADIUS = 6360e3;
+const float ATMOSPHERE_RADIUS = 6420e3;
+const float VIEW_HEIGHT = 200.0;
+
+const vec3 RAYLEIGH_SCATTERING = vec3(5.802e-6, 13.558e-6, 33.1e-6);
+const float RAYLEIGH_HEIGHT = 8e3;
+const float MIE_SCATTERING = 3.996e-6;
+const float MIE_EXTINCTION = 4.44e-6;
+const float MIE_HEIGHT = 1.2e3;
+const float MIE_G = 0.8;
+
+const float SUN_INTENSITY = 20.0;
+const int VIEW_SAMPLES = 16;
+const int LIGHT_SAMPLES = 8;
+
+// distances to the two intersections of the ray with a sphere at the origin, or -1 if it misses
+vec2 intersectSphere(vec3 pos, vec3 dir, float radius){
+    float b = dot(pos, dir);
+    float c = dot(pos, pos) - radius * radius;
+    float h = b * b - c;
+    if(h < 0.0) return vec2(-1.0);
+    h = sqrt(h);
+    return vec2(-b - h, -b + h);
+}
+
+vec3 directionFromUV(vec2 texCoord){
+    float azimuth = texCoord.x * 2.0 * PI;
+    float v = texCoord.y * 2.0 - 1.0;
+    float elevation = sign(v) * v * v * PI * 0.5;
+    return vec3(cos(elevation) * cos(azimuth), sin(elevation), cos(elevation) * sin(azimuth));
+}
+
+// rayleigh and mie optical depth from pos to the top of the atmosphere towards the sun
+vec2 lightOpticalDepth(vec3 pos){
+    float tMax = intersectSphere(pos, sunDir, ATMOSPHERE_RADIUS).y;
+    float ds = tMax / float(LIGHT_SAMPLES);
+    vec2 depth = vec2(0.0);
+    for(int i=0; i<LIGHT_SAMPLES; ++i){
+        float height = length(pos + sunDir * (float(i) + 0.5) * ds) - PLANET_RADIUS;
+        depth += exp(-height / vec2(RAYLEIGH_HEIGHT, MIE_HEIGHT)) * ds;
+    }
+    return depth;
+}
+
+void main () {
+    vec3 dir = directionFromUV(uv);
+    vec3 pos = vec3(0.0, PLANET_RADIUS + VIEW_HEIGHT, 0.0);
+
+    float tMax = intersectSphere(pos, dir, ATMOSPHERE_RADIUS).y;
+    float tGround = intersectSphere(pos, dir, PLANET_RADIUS).x;
+    if(tGround > 0.0) tMax = tGround;
+
+    float ds = tMax / float(VIEW_SAMPLES);
+    vec2 viewDepth = vec2(0.0);
+    vec3 rayleigh = vec3(0.0);
+    vec3 mie = vec3(0.0);
+    for(int i=0; i<VIEW_SAMPLES; ++i){
+        vec3 samplePos = pos + dir * (float(i) + 0.5) * ds;
+        float height = length(samplePos) - PLANET_RADIUS;
+        vec2 density = exp(-height / vec2(RAYLEIGH_HEIGHT, MIE_HEIGHT)) * ds;
+        viewDepth += density;
+
+        // points in the planet's shadow get no direct sunlight
+        if(intersectSphere(samplePos, sunDir, PLANET_RADIUS).x > 0.0) continue;
+
+        vec2 depth = viewDepth + lightOpticalDepth(samplePos);
+        vec3 transmittance = exp(-(RAYLEIGH_SCATTERING * depth.x + MIE_EXTINCTION * depth.y));
+        rayleigh += density.x * transmittance;
+        mie += density.y * transmittance;
+    }
+
+    float mu = dot(dir, sunDir);
+    float rayleighPhase = 3.0 / (16.0 * PI) * (1.0 + mu * mu);
+    // Cornette-Shanks
+    float g2 = MIE_G * MIE_G;
+    float miePhase = 3.0 / (8.0 * PI) * ((1.0 - g2) * (1.0 + mu * mu))
+        / ((2.0 + g2) * pow(1.0 + g2 - 2.0 * MIE_G * mu, 1.5));
+
+    vec3 col = SUN_INTENSITY * (rayleigh * RAYLEIGH_SCATTERING * rayleighPhase + mie * MIE_SCATTERING * miePhase);
+    fragColor = vec4(col, 1.0);
+}
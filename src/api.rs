@@ -0,0 +1,11 @@
+use wasm_bindgen::prelude::*;
+
+// This is like the `main` function, except for JavaScript. The page creates a `Renderer` for
+// its canvas once the module has loaded.
+#[wasm_bindgen(start)]
+pub fn main_js(){
+    // This provides better error messages in debug mode.
+    // It's disabled in release mode so it doesn't bloat up the file size.
+    #[cfg(debug_assertions)]
+    console_error_panic_hook::set_once();
+}
@@ -1,52 +1,314 @@
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
 use web_sys::{WebGl2RenderingContext, HtmlCanvasElement};
+use crate::clock::Clock;
+use crate::error::{show_overlay, RenderError};
+use crate::camera_path::CameraPath;
 use crate::input::InputManager;
-use crate::log;
-use crate::render_passes::{CloudRenderPass, DemoRenderPass, FractalRenderPass, RasterRenderPass};
+use crate::{log, log_warn};
+use crate::param_panel::ParamPanel;
+use crate::params::{self, ParamStore, Tunable};
+use crate::recording::Recording;
+use crate::clouds::CloudParams;
+use crate::debug_view::DebugView;
+use crate::fractal::FRACTAL_PARAMS;
+use crate::render_passes::{BlueNoise, SkyRenderPass, MARCH_PARAMS};
+use crate::utils::Listeners;
+use crate::scene_list::{scene_from_query, scene_index, SCENE_NAMES};
+use crate::scenes::{SceneContext, SceneRegistry};
 use crate::vec_lib::vec3::Vec3f;
+use crate::view_state::ViewState;
+use crate::webgl_utils::capabilities::Capabilities;
 
+/// Seconds between updates of the view in the URL hash.
+const HASH_UPDATE_INTERVAL: f64 = 0.5;
+const PARAMS_STORAGE_KEY: &str = "params";
+
+// Shows a message in the page's warning box, if the page has one.
+fn show_warning(window: &web_sys::Window, message: &str){
+    let element = window.document()
+        .and_then(|document| document.get_element_by_id("glWarning"));
+    if let Some(element) = element {
+        element.set_text_content(Some(message));
+        element.set_class_name("card warning");
+    }
+}
+
+/// How the app is set up by whoever embeds it.
+#[derive(Default)]
+pub struct AppOptions{
+    /// Scene to start with, unless the URL says otherwise.
+    pub scene: Option<String>,
+    /// Read the scene and view from the page's URL and keep the view in its hash.
+    pub use_url: bool,
+    /// Show a panel with controls for the params of the passes next to the canvas.
+    pub panel: bool,
+}
+
+// Params saved on an earlier visit, see `params.rs` for the format.
+fn load_params(window: &web_sys::Window, tunables: &[Rc<dyn Tunable>]){
+    let stored = window.local_storage().ok().flatten()
+        .and_then(|storage| storage.get_item(PARAMS_STORAGE_KEY).ok().flatten());
+    if let Some(text) = stored {
+        for err in params::apply_text(tunables, &text){
+            log_warn!("Ignoring saved param: {}", err);
+        }
+    }
+}
 
 pub struct TestApp{
     ctx: WebGl2RenderingContext,
     canvas: HtmlCanvasElement,
     window: web_sys::Window,
-    raster_pass: RasterRenderPass,
-    demo_pass: DemoRenderPass,
-    fractal_pass: FractalRenderPass,
-    cloud_pass: CloudRenderPass,
+    sky_pass: Rc<SkyRenderPass>,
     input_manager: Rc<InputManager>,
+    clock: Rc<Clock>,
+    scenes: SceneRegistry,
+    cloud_params: Rc<Cell<CloudParams>>,
+    fractal_params: Rc<ParamStore>,
+    march_params: Rc<ParamStore>,
+    debug_view: Rc<Cell<DebugView>>,
+    panel: Option<ParamPanel>,
+    // last hash written to or read from the URL, and when it was last written
+    hash: RefCell<String>,
+    hash_written_at: Cell<f64>,
+    // set when the hash is changed from outside, like following a link to another view
+    hash_changed: Rc<Cell<bool>>,
+    // fixed step to go back to once an input replay is over
+    step_before_replay: Cell<Option<Option<f64>>>,
+    use_url: bool,
+    listeners: RefCell<Listeners>,
 }
 
 impl TestApp {
-    pub fn new(ctx: WebGl2RenderingContext, canvas: HtmlCanvasElement, window: web_sys::Window)
-        -> Result<Self, String>{
+    pub fn new(ctx: WebGl2RenderingContext, canvas: HtmlCanvasElement, window: web_sys::Window,
+        options: &AppOptions) -> Result<Self, RenderError>{
 
-        ctx.get_extension("EXT_color_buffer_float")
-            .or(Err("EXT_color_buffer_float extension not supported"))?;
-        ctx.get_extension("OES_texture_float_linear")
-            .or(Err("OES_texture_float_linear extension not supported"))?;
+        let color_format = Capabilities::detect(&ctx).color_format();
+        if let Some(warning) = color_format.warning() {
+            log_warn!("{}", warning);
+            show_warning(&window, warning);
+        }
 
-        let input_manager_res = InputManager::new(&canvas, &window)?;
+        let clock = Rc::new(Clock::new());
+        let input_manager_res = InputManager::new(&canvas, &window, clock.clone())?;
         let input_manager = Rc::new(input_manager_res);
 
-        let raster_pass = RasterRenderPass::new(ctx.clone(), input_manager.clone())?;
-        let demo_pass = DemoRenderPass::new(ctx.clone(), input_manager.clone())?;
-        let fractal_pass: FractalRenderPass = FractalRenderPass::new(ctx.clone(), input_manager.clone(),
-            raster_pass.color_texture(), raster_pass.depth_buffer()
-        )?;
-        let cloud_pass = CloudRenderPass::new(ctx.clone(), input_manager.clone(),
-        raster_pass.color_texture())?;
+        let blue_noise = Rc::new(BlueNoise::new(&ctx, clock.clone())?);
+        let sky_pass = Rc::new(SkyRenderPass::new(ctx.clone(), color_format)?);
+        let cloud_params = Rc::new(Cell::new(CloudParams::default()));
+        let fractal_params = Rc::new(ParamStore::new("fractal", &FRACTAL_PARAMS));
+        let march_params = Rc::new(ParamStore::new("march", &MARCH_PARAMS));
+        let debug_view = Rc::new(Cell::new(DebugView::Off));
+        let tunables: Vec<Rc<dyn Tunable>> = vec![sky_pass.clone(), cloud_params.clone(), fractal_params.clone(),
+            march_params.clone()];
+        load_params(&window, &tunables);
+
+        let query = if options.use_url { window.location().search().unwrap_or_default() } else { String::new() };
+        let initial_scene = match scene_from_query(&query).or(options.scene.as_deref()) {
+            Some(name) => scene_index(name).unwrap_or_else(|| {
+                log_warn!("Unknown scene '{}'", name);
+                0
+            }),
+            None => 0,
+        };
+
+        // a view in the hash overrides the scene parameter
+        let (position, forward, up) = input_manager.pose();
+        let mut view = ViewState{
+            scene: String::from(SCENE_NAMES[initial_scene]),
+            position,
+            forward,
+            up,
+            fov: input_manager.fov(),
+            time_of_day: sky_pass.time_of_day(),
+            clouds: cloud_params.get(),
+            fractal: (0..FRACTAL_PARAMS.len()).map(|index| fractal_params.get(index)).collect(),
+        };
+        let hash = if options.use_url { window.location().hash().unwrap_or_default() } else { String::new() };
+        if !hash.is_empty() {
+            match ViewState::decode(&hash, &view) {
+                Ok(state) => view = state,
+                Err(err) => {
+                    log_warn!("Ignoring the view in the URL: {}", err);
+                }
+            }
+        }
+        let initial_scene = scene_index(&view.scene).unwrap_or(initial_scene);
+
+        let scenes = SceneRegistry::new(SceneContext{
+            ctx: ctx.clone(),
+            input_manager: input_manager.clone(),
+            clock: clock.clone(),
+            color_format,
+            blue_noise,
+            sky_pass: sky_pass.clone(),
+            cloud_params: cloud_params.clone(),
+            fractal_params: fractal_params.clone(),
+            march_params: march_params.clone(),
+            debug_view: debug_view.clone(),
+        }, initial_scene)?;
+
+        let panel = match (options.panel, canvas.parent_element()) {
+            (true, Some(anchor)) => Some(ParamPanel::new(&anchor)?),
+            (true, None) => Some(ParamPanel::new(&canvas)?),
+            (false, _) => None,
+        };
+
+        let mut listeners = Listeners::new();
+        let hash_changed = Rc::new(Cell::new(false));
+        if options.use_url {
+            let hash_changed = hash_changed.clone();
+            listeners.add(&window, "hashchange", Closure::<dyn FnMut()>::new(move || {
+                hash_changed.set(true);
+            }))?;
+        }
 
-        Ok(TestApp{
+        let app = TestApp{
             ctx,
             canvas,
             window,
-            raster_pass,
-            demo_pass,
-            fractal_pass,
-            cloud_pass,
+            sky_pass,
             input_manager,
-        })
+            clock,
+            scenes,
+            cloud_params,
+            fractal_params,
+            march_params,
+            debug_view,
+            panel,
+            hash: RefCell::new(hash),
+            hash_written_at: Cell::new(0.0),
+            hash_changed,
+            step_before_replay: Cell::new(None),
+            use_url: options.use_url,
+            listeners: RefCell::new(listeners),
+        };
+        app.apply_view(&view);
+        Ok(app)
+    }
+
+    pub fn view_state(&self) -> ViewState{
+        let (position, forward, up) = self.input_manager.pose();
+        ViewState{
+            scene: String::from(self.scenes.active_name()),
+            position,
+            forward,
+            up,
+            fov: self.input_manager.fov(),
+            time_of_day: self.sky_pass.time_of_day(),
+            clouds: self.cloud_params.get(),
+            fractal: (0..FRACTAL_PARAMS.len()).map(|index| self.fractal_params.get(index)).collect(),
+        }
+    }
+
+    pub fn apply_view(&self, view: &ViewState){
+        self.scenes.activate_named(&view.scene);
+        self.input_manager.set_pose(view.position, view.forward, view.up);
+        self.input_manager.set_fov(view.fov);
+        self.sky_pass.set_time_of_day(view.time_of_day);
+        self.cloud_params.set(view.clouds);
+        for (index, value) in view.fractal.iter().enumerate(){
+            self.fractal_params.set(index, *value);
+        }
+    }
+
+    /// Restores a view linked to from the hash, and keeps the hash following the current view
+    /// so it can be shared. The history entry is replaced rather than added to.
+    fn sync_hash(&self){
+        if !self.use_url {
+            return;
+        }
+        if self.hash_changed.replace(false) {
+            let hash = self.window.location().hash().unwrap_or_default();
+            if hash != *self.hash.borrow() {
+                match ViewState::decode(&hash, &self.view_state()) {
+                    Ok(view) => self.apply_view(&view),
+                    Err(err) => {
+                        log_warn!("Ignoring the view in the URL: {}", err);
+                    }
+                }
+                *self.hash.borrow_mut() = hash;
+            }
+        }
+
+        let now = self.clock.elapsed();
+        if now - self.hash_written_at.get() < HASH_UPDATE_INTERVAL {
+            return;
+        }
+        self.hash_written_at.set(now);
+        let hash = format!("#{}", self.view_state().encode());
+        if hash != *self.hash.borrow() {
+            if let Ok(history) = self.window.history() {
+                let _ = history.replace_state_with_url(&JsValue::NULL, "", Some(&hash));
+            }
+            *self.hash.borrow_mut() = hash;
+        }
+    }
+
+    /// Records input from the current view on, see `recording.rs`.
+    pub fn start_recording(&self){
+        self.input_manager.start_recording(self.view_state().encode());
+    }
+
+    pub fn stop_recording(&self){
+        self.input_manager.stop_recording();
+    }
+
+    /// The last finished input recording as text.
+    pub fn last_recording(&self) -> String{
+        self.input_manager.last_recording()
+    }
+
+    /// Restores the recorded view and scene time and starts feeding in the recorded input.
+    /// Live input is ignored until the replay ends.
+    pub fn replay(&self, recording: &Recording) -> Result<(), String>{
+        let view = ViewState::decode(&recording.view, &self.view_state())?;
+        self.input_manager.start_replay(recording);
+        self.apply_view(&view);
+        self.clock.set_time(recording.time);
+        if self.step_before_replay.get().is_none() {
+            self.step_before_replay.set(Some(self.clock.fixed_step()));
+        }
+        Ok(())
+    }
+
+    /// Steps the clock by the recorded frame times while a replay runs, so the session plays
+    /// out exactly.
+    fn step_replay(&self){
+        if let Some(previous) = self.step_before_replay.get() {
+            match self.input_manager.replay_frame() {
+                Some(step) => self.clock.set_fixed_step(Some(step)),
+                None =>{
+                    self.clock.set_fixed_step(previous);
+                    self.step_before_replay.set(None);
+                }
+            }
+        }
+    }
+
+    /// Replaces the key bindings and saves them for the next visit, see `bindings.rs`.
+    pub fn set_bindings(&self, config: &str) -> Result<(), String>{
+        self.input_manager.set_bindings(&self.window, config)
+    }
+
+    pub fn set_camera_path(&self, path: CameraPath){
+        self.input_manager.set_path(path);
+    }
+
+    /// The camera path as text, see `camera_path.rs`.
+    pub fn camera_path(&self) -> String{
+        self.input_manager.path_text()
+    }
+
+    pub fn play_camera_path(&self){
+        self.input_manager.play_path();
+    }
+
+    pub fn stop_camera_path(&self){
+        self.input_manager.stop_path();
     }
 
     // fn mat_to_str(mat : &Mat4f) -> String{
@@ -55,24 +317,116 @@ impl TestApp {
     //     format!("{:#?}", vals)
     // }
 
-    pub fn draw(&self){
-        self.ctx.clear_color(0.0, 0.37254903, 0.37254903, 1.0);
-        self.ctx.enable(WebGl2RenderingContext::DEPTH_TEST);
+    pub fn set_scene(&self, name: &str) -> Result<(), String>{
+        let index = scene_index(name).ok_or(format!("Unknown scene '{}'", name))?;
+        self.scenes.activate(index);
+        Ok(())
+    }
+
+    /// How long scene switches fade for in seconds, 0 switches instantly.
+    pub fn set_crossfade(&self, seconds: f64){
+        self.scenes.set_crossfade(seconds);
+    }
+
+    pub fn set_debug_view(&self, name: &str) -> Result<(), String>{
+        let view = DebugView::from_name(name).ok_or(format!("Unknown debug view '{}'", name))?;
+        self.debug_view.set(view);
+        Ok(())
+    }
+
+    /// Cycles the debug view when it's asked for from the keyboard.
+    fn handle_debug_view(&self){
+        let mut view = self.debug_view.get();
+        for _ in 0..self.input_manager.take_debug_view_cycles(){
+            view = view.next();
+        }
+        if view != self.debug_view.get() {
+            log!("Debug view: {}", view.name());
+            self.debug_view.set(view);
+        }
+    }
 
+    /// Wall clock milliseconds per frame, smoothed, to compare settings by.
+    pub fn frame_time(&self) -> f64{
+        self.clock.frame_time()
+    }
 
+    /// Every param of every pass, whether or not its scene is shown.
+    fn tunables(&self) -> Vec<Rc<dyn Tunable>>{
+        vec![self.sky_pass.clone(), self.cloud_params.clone(), self.fractal_params.clone(), self.march_params.clone()]
+    }
 
-        match self.input_manager.mode() {
-            1 =>{
-                self.demo_pass.draw();
-            },
+    /// Sets a value by name from text. Pass params are named like `clouds.coverage`, see
+    /// `params.rs`, and are saved for the next visit. `fov` and `time_scale` are also accepted.
+    pub fn set_param(&self, name: &str, value: &str) -> Result<(), String>{
+        let number = || value.trim().parse::<f64>().ok().filter(|number| number.is_finite())
+            .ok_or(format!("Invalid value '{}' for '{}'", value, name));
+        match name {
+            "fov" => self.input_manager.set_fov((number()? as f32).clamp(1.0, 179.0)),
+            "time_scale" => self.clock.set_time_scale(number()?),
             _ =>{
-                // self.raster_pass.draw();
+                params::set_param(&self.tunables(), name, value)?;
+                self.save_params();
+            }
+        }
+        Ok(())
+    }
+
+    fn save_params(&self){
+        let saved = self.window.local_storage().ok().flatten()
+            .map(|storage| storage.set_item(PARAMS_STORAGE_KEY, &params::to_text(&self.tunables())).is_ok());
+        if saved != Some(true) {
+            log_warn!("Failed to save the params.");
+        }
+    }
 
-                self.fractal_pass.draw();
-                // self.ctx.disable(WebGl2RenderingContext::DEPTH_TEST);
-                self.cloud_pass.draw();
+    fn update_panel(&self){
+        if let Some(panel) = &self.panel {
+            if let Err(err) = panel.update(&self.scenes.tunables()) {
+                log_warn!("Failed to update the params panel: {}", err);
             }
-        };
+            if panel.take_changed() {
+                self.save_params();
+            }
+        }
+    }
+
+    /// Advances time and input and draws the next frame.
+    pub fn draw(&self){
+        self.step_replay();
+        self.clock.tick(js_sys::Date::now());
+        self.sync_hash();
+        self.input_manager.update();
+        self.handle_debug_view();
+        self.render();
+        self.update_panel();
+    }
+
+    /// Draws the current frame again without advancing anything.
+    pub fn render(&self){
+        self.ctx.clear_color(0.0, 0.37254903, 0.37254903, 1.0);
+        self.ctx.enable(WebGl2RenderingContext::DEPTH_TEST);
+        self.sky_pass.draw();
+
+        self.scenes.draw();
+        if let Some(err) = self.scenes.take_error() {
+            show_overlay(&self.canvas, &err);
+        }
+    }
+
+    /// Stops listening for events and gives up the GL context along with everything in it.
+    pub fn destroy(&self){
+        self.input_manager.detach();
+        self.listeners.borrow_mut().remove_all();
+        if let Some(panel) = &self.panel {
+            panel.remove();
+        }
+        if let Ok(Some(extension)) = self.ctx.get_extension("WEBGL_lose_context") {
+            let lose_context = js_sys::Reflect::get(&extension, &JsValue::from_str("loseContext"));
+            if let Ok(lose_context) = lose_context.and_then(|function| function.dyn_into::<js_sys::Function>()) {
+                let _ = lose_context.call0(&extension);
+            }
+        }
     }
 
 
@@ -0,0 +1,482 @@
+use std::collections::HashMap;
+use std::fmt;
+use crate::gamepad::StickResponse;
+
+// Maps keys to actions. Keys are `KeyboardEvent.code` values, which name the physical key, so
+// the same bindings work on any keyboard layout.
+//
+// Bindings are written one action per line, with any number of chords after the `=`:
+//
+//     move_forward = KeyW, ArrowUp
+//     switch_scene_1 = Digit1, Ctrl+Numpad1
+//     look.sensitivity = 0.5
+//     zoom.sensitivity = 2
+//     look.invert_y = true
+//     look.pointer_lock = true
+//     look.free_flight = true
+//     gamepad.dead_zone = 0.2
+//     gamepad.exponent = 1.5
+//     touch.joystick = false
+//
+// Lines starting with `#` are comments. Actions that aren't mentioned keep their defaults.
+// `look.pointer_lock` captures the mouse on click instead of looking around while dragging.
+// `look.free_flight` lets the roll keys tilt the horizon.
+// `touch.joystick` shows an on-screen joystick for moving once the canvas is touched.
+
+pub const DEFAULT_BINDINGS: &str = "\
+move_forward = KeyW
+move_backward = KeyS
+strafe_left = KeyA
+strafe_right = KeyD
+ascend = Space
+descend = ShiftLeft, ShiftRight
+roll_left = KeyQ
+roll_right = KeyE
+toggle_orbit = KeyO
+add_keyframe = KeyK
+play_path = KeyP
+cycle_debug_view = KeyV
+switch_scene_1 = Digit1
+switch_scene_2 = Digit2
+switch_scene_3 = Digit3
+switch_scene_4 = Digit4
+switch_scene_5 = Digit5
+switch_scene_6 = Digit6
+switch_scene_7 = Digit7
+switch_scene_8 = Digit8
+switch_scene_9 = Digit9
+";
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Action{
+    MoveForward,
+    MoveBackward,
+    StrafeLeft,
+    StrafeRight,
+    Ascend,
+    Descend,
+    /// Mouse look, it has no keys but its sensitivity scales the rotation speed.
+    Look,
+    /// Only rolls in free flight, see `look.free_flight`.
+    RollLeft,
+    RollRight,
+    /// Scroll wheel and pinch zoom, it has no keys but its sensitivity scales the zoom speed.
+    Zoom,
+    /// Switches between flying around and orbiting the point in front of the camera.
+    ToggleOrbit,
+    /// Adds the current pose to the end of the camera path.
+    AddKeyframe,
+    /// Starts or stops playing back the camera path.
+    PlayPath,
+    /// Shows the next of the marchers' debug views, see `debug_view.rs`.
+    CycleDebugView,
+    /// Switches to the scene with this index.
+    SwitchScene(usize),
+}
+
+impl Action{
+    pub fn from_name(name: &str) -> Option<Action>{
+        let action = match name {
+            "move_forward" => Action::MoveForward,
+            "move_backward" => Action::MoveBackward,
+            "strafe_left" => Action::StrafeLeft,
+            "strafe_right" => Action::StrafeRight,
+            "ascend" => Action::Ascend,
+            "descend" => Action::Descend,
+            "look" => Action::Look,
+            "roll_left" => Action::RollLeft,
+            "roll_right" => Action::RollRight,
+            "zoom" => Action::Zoom,
+            "toggle_orbit" => Action::ToggleOrbit,
+            "add_keyframe" => Action::AddKeyframe,
+            "play_path" => Action::PlayPath,
+            "cycle_debug_view" => Action::CycleDebugView,
+            _ => {
+                let number = name.strip_prefix("switch_scene_")?.parse::<usize>().ok()?;
+                Action::SwitchScene(number.checked_sub(1)?)
+            }
+        };
+        Some(action)
+    }
+
+    pub fn name(&self) -> String{
+        let name = match self {
+            Action::MoveForward => "move_forward",
+            Action::MoveBackward => "move_backward",
+            Action::StrafeLeft => "strafe_left",
+            Action::StrafeRight => "strafe_right",
+            Action::Ascend => "ascend",
+            Action::Descend => "descend",
+            Action::Look => "look",
+            Action::RollLeft => "roll_left",
+            Action::RollRight => "roll_right",
+            Action::Zoom => "zoom",
+            Action::ToggleOrbit => "toggle_orbit",
+            Action::AddKeyframe => "add_keyframe",
+            Action::PlayPath => "play_path",
+            Action::CycleDebugView => "cycle_debug_view",
+            Action::SwitchScene(index) => return format!("switch_scene_{}", index + 1),
+        };
+        String::from(name)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct Modifiers{
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub meta: bool,
+}
+
+impl Modifiers{
+    /// Whether every modifier in `self` is also held in `held`.
+    fn held_in(&self, held: &Modifiers) -> bool{
+        (!self.ctrl || held.ctrl) && (!self.alt || held.alt)
+            && (!self.shift || held.shift) && (!self.meta || held.meta)
+    }
+
+    fn count(&self) -> usize{
+        [self.ctrl, self.alt, self.shift, self.meta].iter().filter(|held| **held).count()
+    }
+}
+
+/// A key code with the modifiers that have to be held along with it, like `Ctrl+KeyS`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Chord{
+    pub code: String,
+    pub modifiers: Modifiers,
+}
+
+impl Chord{
+    pub fn parse(text: &str) -> Result<Chord, String>{
+        let mut parts: Vec<&str> = text.split('+').map(|part| part.trim()).collect();
+        let code = parts.pop().filter(|code| !code.is_empty())
+            .ok_or(format!("Missing key in chord '{}'", text))?;
+
+        let mut modifiers = Modifiers::default();
+        for part in parts{
+            match part.to_lowercase().as_str() {
+                "ctrl" | "control" => modifiers.ctrl = true,
+                "alt" => modifiers.alt = true,
+                "shift" => modifiers.shift = true,
+                "meta" | "cmd" => modifiers.meta = true,
+                _ => return Err(format!("Unknown modifier '{}' in chord '{}'", part, text)),
+            }
+        }
+        Ok(Chord{code: String::from(code), modifiers})
+    }
+}
+
+impl fmt::Display for Chord{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        let names = [(self.modifiers.ctrl, "Ctrl+"), (self.modifiers.alt, "Alt+"),
+            (self.modifiers.shift, "Shift+"), (self.modifiers.meta, "Meta+")];
+        for (_, name) in names.iter().filter(|(held, _)| *held){
+            write!(f, "{}", name)?;
+        }
+        write!(f, "{}", self.code)
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Bindings{
+    chords: Vec<(Chord, Action)>,
+    sensitivity: HashMap<Action, f32>,
+    invert_y: bool,
+    pointer_lock: bool,
+    free_flight: bool,
+    gamepad: StickResponse,
+    touch_joystick: bool,
+}
+
+impl Default for Bindings{
+    fn default() -> Self{
+        Bindings::parse(DEFAULT_BINDINGS).expect("default bindings should parse")
+    }
+}
+
+impl Bindings{
+    /// Parses a full set of bindings, see the top of this file for the format.
+    pub fn parse(config: &str) -> Result<Bindings, String>{
+        let mut bindings = Bindings{
+            chords: Vec::new(),
+            sensitivity: HashMap::new(),
+            invert_y: false,
+            pointer_lock: false,
+            free_flight: false,
+            gamepad: StickResponse::default(),
+            touch_joystick: true,
+        };
+        bindings.apply(config)?;
+        Ok(bindings)
+    }
+
+    /// Defaults overridden by `config`.
+    pub fn with_overrides(config: &str) -> Result<Bindings, String>{
+        let mut bindings = Bindings::default();
+        bindings.apply(config)?;
+        Ok(bindings)
+    }
+
+    fn apply(&mut self, config: &str) -> Result<(), String>{
+        for (number, line) in config.lines().enumerate(){
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) = line.split_once('=')
+                .ok_or(format!("Line {}: expected 'action = keys'", number + 1))?;
+            let (name, value) = (name.trim(), value.trim());
+
+            if name == "touch.joystick" {
+                self.touch_joystick = value.parse::<bool>()
+                    .map_err(|_| format!("Line {}: invalid joystick '{}'", number + 1, value))?;
+                continue;
+            }
+
+            if let Some(option) = name.strip_prefix("gamepad.") {
+                let value = value.parse::<f32>()
+                    .map_err(|_| format!("Line {}: invalid {} '{}'", number + 1, option, value))?;
+                match option {
+                    "dead_zone" if (0.0..1.0).contains(&value) => self.gamepad.dead_zone = value,
+                    "exponent" if value > 0.0 => self.gamepad.exponent = value,
+                    _ => return Err(format!("Line {}: invalid gamepad option '{}'", number + 1, line)),
+                }
+                continue;
+            }
+
+            if let Some((name, option)) = name.split_once('.') {
+                let action = Action::from_name(name)
+                    .ok_or(format!("Line {}: unknown action '{}'", number + 1, name))?;
+                let invalid = || format!("Line {}: invalid {} '{}'", number + 1, option, value);
+                match option {
+                    "sensitivity" => {
+                        let sensitivity = value.parse::<f32>().ok()
+                            .filter(|sensitivity| sensitivity.is_finite() && *sensitivity > 0.0)
+                            .ok_or_else(invalid)?;
+                        self.sensitivity.insert(action, sensitivity);
+                    }
+                    "invert_y" if action == Action::Look => {
+                        self.invert_y = value.parse::<bool>().map_err(|_| invalid())?;
+                    }
+                    "pointer_lock" if action == Action::Look => {
+                        self.pointer_lock = value.parse::<bool>().map_err(|_| invalid())?;
+                    }
+                    "free_flight" if action == Action::Look => {
+                        self.free_flight = value.parse::<bool>().map_err(|_| invalid())?;
+                    }
+                    _ => return Err(format!("Line {}: unknown option '{}.{}'", number + 1, name, option)),
+                }
+                continue;
+            }
+
+            let action = Action::from_name(name)
+                .ok_or(format!("Line {}: unknown action '{}'", number + 1, name))?;
+            self.chords.retain(|(_, bound)| *bound != action);
+            for chord in value.split(',').map(|chord| chord.trim()).filter(|chord| !chord.is_empty()){
+                let chord = Chord::parse(chord).map_err(|err| format!("Line {}: {}", number + 1, err))?;
+                self.chords.push((chord, action));
+            }
+        }
+        Ok(())
+    }
+
+    /// The full set of bindings in the format `parse` reads.
+    pub fn to_text(&self) -> String{
+        let mut text = String::new();
+        let mut actions: Vec<Action> = Vec::new();
+        for (_, action) in &self.chords{
+            if !actions.contains(action) {
+                actions.push(*action);
+            }
+        }
+        for action in actions{
+            let chords: Vec<String> = self.chords.iter()
+                .filter(|(_, bound)| *bound == action)
+                .map(|(chord, _)| chord.to_string())
+                .collect();
+            text.push_str(&format!("{} = {}\n", action.name(), chords.join(", ")));
+        }
+        let mut sensitivity: Vec<(String, f32)> = self.sensitivity.iter()
+            .map(|(action, sensitivity)| (action.name(), *sensitivity))
+            .collect();
+        sensitivity.sort_by(|a, b| a.0.cmp(&b.0));
+        for (name, sensitivity) in sensitivity{
+            text.push_str(&format!("{}.sensitivity = {}\n", name, sensitivity));
+        }
+        text.push_str(&format!("look.invert_y = {}\nlook.pointer_lock = {}\nlook.free_flight = {}\n",
+            self.invert_y, self.pointer_lock, self.free_flight));
+        text.push_str(&format!("gamepad.dead_zone = {}\ngamepad.exponent = {}\ntouch.joystick = {}\n",
+            self.gamepad.dead_zone, self.gamepad.exponent, self.touch_joystick));
+        text
+    }
+
+    /// The action bound to a key, preferring the chord with the most modifiers when several match.
+    pub fn resolve(&self, code: &str, held: &Modifiers) -> Option<Action>{
+        self.chords.iter()
+            .filter(|(chord, _)| chord.code == code && chord.modifiers.held_in(held))
+            .max_by_key(|(chord, _)| chord.modifiers.count())
+            .map(|(_, action)| *action)
+    }
+
+    pub fn sensitivity(&self, action: Action) -> f32{
+        self.sensitivity.get(&action).copied().unwrap_or(1.0)
+    }
+
+    pub fn invert_y(&self) -> bool{
+        self.invert_y
+    }
+
+    pub fn pointer_lock(&self) -> bool{
+        self.pointer_lock
+    }
+
+    pub fn free_flight(&self) -> bool{
+        self.free_flight
+    }
+
+    pub fn gamepad(&self) -> &StickResponse{
+        &self.gamepad
+    }
+
+    pub fn touch_joystick(&self) -> bool{
+        self.touch_joystick
+    }
+}
+
+/// Tracks which actions are held from the key events seen so far.
+pub struct ActionState{
+    bindings: Bindings,
+    // action each held key started, so releasing it ends the same action even if the modifiers changed
+    pressed: HashMap<String, Action>,
+}
+
+impl ActionState{
+    pub fn new(bindings: Bindings) -> Self{
+        ActionState{bindings, pressed: HashMap::new()}
+    }
+
+    pub fn bindings(&self) -> &Bindings{
+        &self.bindings
+    }
+
+    pub fn set_bindings(&mut self, bindings: Bindings){
+        self.bindings = bindings;
+        self.pressed.clear();
+    }
+
+    /// Returns the action the key triggers, if it's bound.
+    pub fn press(&mut self, code: &str, modifiers: &Modifiers) -> Option<Action>{
+        let action = self.bindings.resolve(code, modifiers)?;
+        self.pressed.insert(String::from(code), action);
+        Some(action)
+    }
+
+    pub fn release(&mut self, code: &str){
+        self.pressed.remove(code);
+    }
+
+    pub fn release_all(&mut self){
+        self.pressed.clear();
+    }
+
+    pub fn held(&self, action: Action) -> bool{
+        self.pressed.values().any(|pressed| *pressed == action)
+    }
+
+    /// Sensitivity of the action if it's held, otherwise 0.
+    pub fn amount(&self, action: Action) -> f32{
+        if self.held(action) { self.bindings.sensitivity(action) } else { 0.0 }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    const NONE: Modifiers = Modifiers{ctrl: false, alt: false, shift: false, meta: false};
+    const CTRL: Modifiers = Modifiers{ctrl: true, alt: false, shift: false, meta: false};
+
+    #[test]
+    fn defaults_use_physical_keys(){
+        let bindings = Bindings::default();
+        assert_eq!(bindings.resolve("KeyW", &NONE), Some(Action::MoveForward));
+        assert_eq!(bindings.resolve("Digit2", &NONE), Some(Action::SwitchScene(1)));
+        assert_eq!(bindings.resolve("KeyZ", &NONE), None);
+    }
+
+    #[test]
+    fn overrides_replace_only_named_actions(){
+        let bindings = Bindings::with_overrides("# azerty\nmove_forward = KeyZ, ArrowUp\nlook.sensitivity = 0.5")
+            .unwrap();
+        assert_eq!(bindings.resolve("KeyZ", &NONE), Some(Action::MoveForward));
+        assert_eq!(bindings.resolve("ArrowUp", &NONE), Some(Action::MoveForward));
+        assert_eq!(bindings.resolve("KeyW", &NONE), None);
+        assert_eq!(bindings.resolve("KeyS", &NONE), Some(Action::MoveBackward));
+        assert_eq!(bindings.sensitivity(Action::Look), 0.5);
+        assert_eq!(bindings.sensitivity(Action::MoveForward), 1.0);
+    }
+
+    #[test]
+    fn parses_look_options(){
+        let bindings = Bindings::with_overrides("look.invert_y = true\nlook.pointer_lock = true").unwrap();
+        assert!(bindings.invert_y());
+        assert!(bindings.pointer_lock());
+        assert!(!Bindings::default().pointer_lock());
+        assert!(Bindings::with_overrides("look.free_flight = true").unwrap().free_flight());
+        assert!(Bindings::parse("ascend.invert_y = true").is_err());
+        assert!(Bindings::parse("look.invert_y = maybe").is_err());
+    }
+
+    #[test]
+    fn parses_sensitivity(){
+        let bindings = Bindings::with_overrides("look.sensitivity = 0.5").unwrap();
+        assert_eq!(bindings.sensitivity(Action::Look), 0.5);
+        for value in ["NaN", "inf", "0", "-2", "fast"]{
+            assert!(Bindings::parse(&format!("look.sensitivity = {}", value)).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn parses_gamepad_response(){
+        let bindings = Bindings::with_overrides("gamepad.dead_zone = 0.25\ngamepad.exponent = 3").unwrap();
+        assert_eq!(*bindings.gamepad(), StickResponse{dead_zone: 0.25, exponent: 3.0});
+        assert!(Bindings::parse("gamepad.dead_zone = 1.5").is_err());
+        assert!(Bindings::parse("gamepad.rumble = 1").is_err());
+    }
+
+    #[test]
+    fn most_specific_chord_wins(){
+        let bindings = Bindings::with_overrides("switch_scene_2 = Ctrl+Digit1").unwrap();
+        assert_eq!(bindings.resolve("Digit1", &NONE), Some(Action::SwitchScene(0)));
+        assert_eq!(bindings.resolve("Digit1", &CTRL), Some(Action::SwitchScene(1)));
+    }
+
+    #[test]
+    fn rejects_bad_lines(){
+        assert!(Bindings::parse("fly = KeyF").is_err());
+        assert!(Bindings::parse("ascend = Hyper+Space").is_err());
+        assert!(Bindings::parse("ascend Space").is_err());
+        assert!(Bindings::parse("switch_scene_0 = Digit0").is_err());
+    }
+
+    #[test]
+    fn text_round_trips(){
+        let bindings = Bindings::with_overrides("switch_scene_2 = Ctrl+Shift+Digit1, KeyX\nzoom.sensitivity = 2\n\
+            look.sensitivity = 0.5\nlook.invert_y = true\ngamepad.exponent = 1.5\nascend =").unwrap();
+        assert_eq!(Bindings::parse(&bindings.to_text()), Ok(bindings));
+        assert_eq!(Bindings::parse(&Bindings::default().to_text()), Ok(Bindings::default()));
+    }
+
+    #[test]
+    fn release_ends_the_pressed_action(){
+        let mut state = ActionState::new(Bindings::default());
+        assert_eq!(state.press("ShiftLeft", &NONE), Some(Action::Descend));
+        state.press("KeyW", &Modifiers{shift: true, ..NONE});
+        assert_eq!(state.amount(Action::MoveForward), 1.0);
+        state.release("KeyW");
+        assert!(!state.held(Action::MoveForward));
+        assert!(state.held(Action::Descend));
+    }
+}
@@ -0,0 +1,192 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::noise::hash;
+
+// Void-and-cluster blue noise (Ulichney 1993). Every pixel gets a unique rank in the order it
+// would be switched on, so thresholding the texture at any level gives evenly spread points.
+// All distances wrap around the edges, so the result tiles.
+
+const SIGMA: f32 = 1.5;
+const INITIAL_DENSITY: f32 = 0.1;
+
+/// Toroidal gaussian energy of a set of points, updated incrementally as points are toggled.
+struct EnergyField{
+    size: usize,
+    kernel: Vec<f32>,
+    energy: Vec<f32>,
+    points: Vec<bool>,
+}
+
+impl EnergyField{
+    fn new(size: usize) -> Self{
+        let mut kernel = vec![0.0; size * size];
+        for y in 0..size{
+            for x in 0..size{
+                let dx = usize::min(x, size - x) as f32;
+                let dy = usize::min(y, size - y) as f32;
+                kernel[y * size + x] = f32::exp(-(dx * dx + dy * dy) / (2.0 * SIGMA * SIGMA));
+            }
+        }
+        EnergyField{
+            size,
+            kernel,
+            energy: vec![0.0; size * size],
+            points: vec![false; size * size],
+        }
+    }
+
+    fn toggle(&mut self, index: usize){
+        let sign = if self.points[index] { -1.0 } else { 1.0 };
+        self.points[index] = !self.points[index];
+
+        let (px, py) = (index % self.size, index / self.size);
+        for y in 0..self.size{
+            let ky = (y + self.size - py) % self.size;
+            for x in 0..self.size{
+                let kx = (x + self.size - px) % self.size;
+                self.energy[y * self.size + x] += sign * self.kernel[ky * self.size + kx];
+            }
+        }
+    }
+
+    /// The set point with the most energy around it.
+    fn tightest_cluster(&self) -> usize{
+        self.extreme(true, |a, b| a > b)
+    }
+
+    /// The unset point with the least energy around it.
+    fn largest_void(&self) -> usize{
+        self.extreme(false, |a, b| a < b)
+    }
+
+    fn extreme<F: Fn(f32, f32) -> bool>(&self, set: bool, better: F) -> usize{
+        let mut best: Option<usize> = None;
+        for (index, energy) in self.energy.iter().enumerate(){
+            if self.points[index] != set{
+                continue;
+            }
+            if best.is_none_or(|best| better(*energy, self.energy[best])){
+                best = Some(index);
+            }
+        }
+        best.expect("energy field has no points of the requested kind")
+    }
+}
+
+/// Generates a tileable `size`×`size` blue noise texture with one byte per pixel, where the
+/// ranks are spread evenly over [0, 255].
+pub fn void_and_cluster(size: usize, seed: u32) -> Vec<u8>{
+    let count = size * size;
+    let mut field = EnergyField::new(size);
+
+    // random initial pattern
+    let initial = usize::max((count as f32 * INITIAL_DENSITY) as usize, 1);
+    let mut placed = 0;
+    let mut attempt = 0;
+    while placed < initial{
+        let index = hash(attempt, 0, 0, seed) as usize % count;
+        attempt += 1;
+        if !field.points[index]{
+            field.toggle(index);
+            placed += 1;
+        }
+    }
+
+    // move points from the tightest clusters to the largest voids until they're evenly spread
+    loop{
+        let cluster = field.tightest_cluster();
+        field.toggle(cluster);
+        let void = field.largest_void();
+        field.toggle(void);
+        if void == cluster{
+            break;
+        }
+    }
+    let prototype = field.points.clone();
+    let prototype_energy = field.energy.clone();
+
+    let mut ranks = vec![0usize; count];
+
+    // ranks below the prototype, found by removing its points from the tightest clusters first
+    for rank in (0..initial).rev(){
+        let cluster = field.tightest_cluster();
+        field.toggle(cluster);
+        ranks[cluster] = rank;
+    }
+
+    // ranks above the prototype, found by filling the largest voids first
+    field.points = prototype;
+    field.energy = prototype_energy;
+    for rank in initial..count{
+        let void = field.largest_void();
+        field.toggle(void);
+        ranks[void] = rank;
+    }
+
+    ranks.iter().map(|rank| (rank * 256 / count) as u8).collect()
+}
+
+type BlueNoiseCache = HashMap<(usize, u32), Rc<Vec<u8>>>;
+
+thread_local! {
+    static CACHE: RefCell<BlueNoiseCache> = RefCell::new(HashMap::new());
+}
+
+/// Cached [void_and_cluster] result, the same size and seed always give the same texture.
+pub fn blue_noise(size: usize, seed: u32) -> Rc<Vec<u8>>{
+    CACHE.with(|cache| {
+        cache.borrow_mut()
+            .entry((size, seed))
+            .or_insert_with(|| Rc::new(void_and_cluster(size, seed)))
+            .clone()
+    })
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    const SIZE: usize = 32;
+
+    #[test]
+    fn is_deterministic_and_cached(){
+        let a = blue_noise(SIZE, 1);
+        let b = blue_noise(SIZE, 1);
+        assert!(Rc::ptr_eq(&a, &b));
+        assert_eq!(*a, void_and_cluster(SIZE, 1));
+        assert_ne!(*a, *blue_noise(SIZE, 2));
+    }
+
+    #[test]
+    fn histogram_is_flat(){
+        let noise = void_and_cluster(SIZE, 3);
+        let mut histogram = [0usize; 256];
+        for val in noise.iter(){
+            histogram[*val as usize] += 1;
+        }
+        let per_bucket = SIZE * SIZE / 256;
+        assert!(histogram.iter().all(|count| *count == per_bucket));
+    }
+
+    #[test]
+    fn thresholded_points_are_spread_out(){
+        let noise = void_and_cluster(SIZE, 4);
+        let points: Vec<(i32, i32)> = (0..SIZE * SIZE)
+            .filter(|index| noise[*index] < 26)
+            .map(|index| ((index % SIZE) as i32, (index / SIZE) as i32))
+            .collect();
+
+        // white noise at this density almost always has adjacent points, blue noise shouldn't,
+        // including across the wrapped edges
+        for (i, a) in points.iter().enumerate(){
+            for b in points.iter().skip(i + 1){
+                let dx = (a.0 - b.0).rem_euclid(SIZE as i32);
+                let dy = (a.1 - b.1).rem_euclid(SIZE as i32);
+                let dx = i32::min(dx, SIZE as i32 - dx);
+                let dy = i32::min(dy, SIZE as i32 - dy);
+                assert!(dx * dx + dy * dy > 2);
+            }
+        }
+    }
+}
@@ -0,0 +1,284 @@
+use crate::vec_lib::quat::Quat;
+use crate::vec_lib::vec3::Vec3f;
+
+// Camera flythroughs. A path is a list of keyframes, each a camera pose at a time in seconds.
+// Positions follow a Catmull-Rom spline through the keyframes and orientations slerp between
+// them. Playback runs off the clock's scene time, so with a fixed step the same path renders the
+// same frames every time.
+//
+// Paths are saved as text, one setting or keyframe per line:
+//
+//     easing = smooth
+//     key = 0 0 1 0 0 0 0 1
+//     key = 2.5 4 1.5 -3 0 0.38268343 0 0.9238795
+//
+// A key is its time followed by the position and the orientation quaternion as x y z w.
+// Lines starting with `#` are comments.
+
+/// How playback time is spread over the path.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Easing{
+    Linear,
+    /// Starts and stops gently instead of jumping straight to full speed.
+    Smooth,
+}
+
+impl Easing{
+    pub fn from_name(name: &str) -> Option<Easing>{
+        match name {
+            "linear" => Some(Easing::Linear),
+            "smooth" => Some(Easing::Smooth),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str{
+        match self {
+            Easing::Linear => "linear",
+            Easing::Smooth => "smooth",
+        }
+    }
+
+    /// Maps progress in [0, 1] to eased progress in [0, 1].
+    fn apply(&self, t: f64) -> f64{
+        match self {
+            Easing::Linear => t,
+            Easing::Smooth => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+/// A camera pose. The orientation turns +z into the view direction and +y into the up vector.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Keyframe{
+    pub time: f64,
+    pub position: Vec3f,
+    pub orientation: Quat,
+}
+
+impl Keyframe{
+    pub fn from_pose(time: f64, position: Vec3f, forward: &Vec3f, up: &Vec3f) -> Self{
+        let z = forward.normalize();
+        let x = up.cross(&z).normalize();
+        let y = z.cross(&x);
+        Keyframe{
+            time,
+            position,
+            orientation: Quat::from_basis(&x, &y, &z),
+        }
+    }
+
+    pub fn forward(&self) -> Vec3f{
+        self.orientation.mult_vec3(&Vec3f::new(0.0, 0.0, 1.0))
+    }
+
+    pub fn up(&self) -> Vec3f{
+        self.orientation.mult_vec3(&Vec3f::new(0.0, 1.0, 0.0))
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct CameraPath{
+    // sorted by time
+    keyframes: Vec<Keyframe>,
+    easing: Easing,
+}
+
+impl Default for CameraPath{
+    fn default() -> Self{
+        CameraPath{
+            keyframes: Vec::new(),
+            easing: Easing::Smooth,
+        }
+    }
+}
+
+fn catmull_rom(p0: &Vec3f, p1: &Vec3f, p2: &Vec3f, p3: &Vec3f, t: f32) -> Vec3f{
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (p1.scale(2.0)
+        + (p2 - p0).scale(t)
+        + (p0.scale(2.0) - p1.scale(5.0) + p2.scale(4.0) - p3).scale(t2)
+        + (p1.scale(3.0) - p0 - p2.scale(3.0) + p3).scale(t3)
+    ).scale(0.5)
+}
+
+impl CameraPath{
+    pub fn new() -> Self{
+        CameraPath::default()
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe]{
+        &self.keyframes
+    }
+
+    /// Adds a keyframe, after any others at the same time.
+    pub fn push(&mut self, keyframe: Keyframe){
+        let index = self.keyframes.iter().position(|other| other.time > keyframe.time)
+            .unwrap_or(self.keyframes.len());
+        self.keyframes.insert(index, keyframe);
+    }
+
+    /// Time from the first keyframe to the last in seconds.
+    pub fn duration(&self) -> f64{
+        match (self.keyframes.first(), self.keyframes.last()) {
+            (Some(first), Some(last)) => last.time - first.time,
+            _ => 0.0,
+        }
+    }
+
+    /// Pose `time` seconds after the path starts, holding the ends outside of it.
+    pub fn sample(&self, time: f64) -> Option<Keyframe>{
+        let first = self.keyframes.first()?;
+        let duration = self.duration();
+        if duration <= 0.0 {
+            return Some(*first);
+        }
+
+        let progress = self.easing.apply((time / duration).clamp(0.0, 1.0));
+        let time = first.time + progress * duration;
+        let last_segment = self.keyframes.len() - 2;
+        let index = self.keyframes.iter().rposition(|keyframe| keyframe.time <= time)
+            .unwrap_or(0)
+            .min(last_segment);
+
+        let key = |offset: isize| {
+            let clamped = (index as isize + offset).clamp(0, self.keyframes.len() as isize - 1);
+            &self.keyframes[clamped as usize]
+        };
+        let (start, end) = (key(0), key(1));
+        let span = end.time - start.time;
+        let t = if span > 0.0 { ((time - start.time) / span) as f32 } else { 1.0 };
+
+        Some(Keyframe{
+            time,
+            position: catmull_rom(&key(-1).position, &start.position, &end.position, &key(2).position, t),
+            orientation: start.orientation.slerp(&end.orientation, t),
+        })
+    }
+
+    /// Parses the text format described at the top of this file.
+    pub fn parse(text: &str) -> Result<CameraPath, String>{
+        let mut path = CameraPath::new();
+        for (number, line) in text.lines().enumerate(){
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) = line.split_once('=')
+                .ok_or(format!("Line {}: expected 'name = value'", number + 1))?;
+            let (name, value) = (name.trim(), value.trim());
+
+            match name {
+                "easing" => {
+                    path.easing = Easing::from_name(value)
+                        .ok_or(format!("Line {}: unknown easing '{}'", number + 1, value))?;
+                }
+                "key" => {
+                    let values = value.split_whitespace()
+                        .map(|part| part.parse::<f64>())
+                        .collect::<Result<Vec<f64>, _>>()
+                        .ok()
+                        .filter(|values| values.len() == 8)
+                        .ok_or(format!("Line {}: expected 'key = time x y z qx qy qz qw'", number + 1))?;
+                    let v = |index: usize| values[index] as f32;
+                    path.push(Keyframe{
+                        time: values[0],
+                        position: Vec3f::new(v(1), v(2), v(3)),
+                        orientation: Quat::new([v(4), v(5), v(6), v(7)]).normalize(),
+                    });
+                }
+                _ => return Err(format!("Line {}: unknown setting '{}'", number + 1, name)),
+            }
+        }
+        Ok(path)
+    }
+
+    pub fn to_text(&self) -> String{
+        let mut text = format!("easing = {}\n", self.easing.name());
+        for keyframe in &self.keyframes{
+            let (p, q) = (&keyframe.position, &keyframe.orientation);
+            text.push_str(&format!("key = {} {} {} {} {} {} {} {}\n",
+                keyframe.time, p.x(), p.y(), p.z(), q[0], q[1], q[2], q[3]));
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    const EPSILON: f32 = 0.0001;
+
+    fn close(a: &Vec3f, b: &Vec3f) -> bool{
+        (a - b).length() < EPSILON
+    }
+
+    fn path() -> CameraPath{
+        let mut path = CameraPath::new();
+        let up = Vec3f::new(0.0, 1.0, 0.0);
+        path.push(Keyframe::from_pose(0.0, Vec3f::new(0.0, 0.0, 0.0), &Vec3f::new(0.0, 0.0, 1.0), &up));
+        path.push(Keyframe::from_pose(2.0, Vec3f::new(4.0, 0.0, 0.0), &Vec3f::new(1.0, 0.0, 0.0), &up));
+        path.push(Keyframe::from_pose(1.0, Vec3f::new(2.0, 1.0, 0.0), &Vec3f::new(1.0, 0.0, 1.0), &up));
+        path
+    }
+
+    #[test]
+    fn pose_round_trips_through_orientation(){
+        let forward = Vec3f::new(0.3, -0.5, 0.8).normalize();
+        let keyframe = Keyframe::from_pose(0.0, Vec3f::new(0.0, 0.0, 0.0), &forward, &Vec3f::new(0.0, 1.0, 0.0));
+        assert!(close(&keyframe.forward(), &forward));
+        assert!(keyframe.up().dot(&forward).abs() < EPSILON);
+        assert!(keyframe.up().y() > 0.0);
+    }
+
+    #[test]
+    fn passes_through_keyframes(){
+        let mut path = path();
+        path.easing = Easing::Linear;
+        assert_eq!(path.duration(), 2.0);
+        for keyframe in path.keyframes().to_vec(){
+            let sample = path.sample(keyframe.time).unwrap();
+            assert!(close(&sample.position, &keyframe.position));
+            assert!(close(&sample.forward(), &keyframe.forward()));
+        }
+        // holds the ends
+        assert!(close(&path.sample(5.0).unwrap().position, &Vec3f::new(4.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn orientation_slerps_halfway(){
+        let mut path = CameraPath::new();
+        path.easing = Easing::Linear;
+        let up = Vec3f::new(0.0, 1.0, 0.0);
+        path.push(Keyframe::from_pose(0.0, Vec3f::new(0.0, 0.0, 0.0), &Vec3f::new(0.0, 0.0, 1.0), &up));
+        path.push(Keyframe::from_pose(1.0, Vec3f::new(0.0, 0.0, 0.0), &Vec3f::new(1.0, 0.0, 0.0), &up));
+        let forward = path.sample(0.5).unwrap().forward();
+        assert!(close(&forward, &Vec3f::new(1.0, 0.0, 1.0).normalize()));
+    }
+
+    #[test]
+    fn easing_starts_slowly(){
+        let mut path = path();
+        path.easing = Easing::Linear;
+        let linear = path.sample(0.2).unwrap().position;
+        path.easing = Easing::Smooth;
+        let smooth = path.sample(0.2).unwrap().position;
+        assert!(smooth.length() < linear.length());
+    }
+
+    #[test]
+    fn text_round_trips(){
+        let path = path();
+        let parsed = CameraPath::parse(&path.to_text()).unwrap();
+        assert_eq!(parsed.easing, path.easing);
+        for (a, b) in parsed.keyframes().iter().zip(path.keyframes()){
+            assert_eq!(a.time, b.time);
+            assert!(close(&a.position, &b.position));
+            assert!((1.0 - a.orientation.dot(&b.orientation)).abs() < EPSILON);
+        }
+        assert!(CameraPath::parse("key = 1 2 3").is_err());
+        assert!(CameraPath::parse("easing = bouncy").is_err());
+    }
+}
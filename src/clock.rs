@@ -0,0 +1,218 @@
+use std::cell::Cell;
+
+/// Longest step a single frame can take, so a hidden tab doesn't make everything jump on return.
+const MAX_STEP: f64 = 0.1;
+/// Weight of the latest frame in the smoothed frame time.
+const FRAME_TIME_SMOOTHING: f64 = 0.1;
+
+/// The one source of time for rendering and input.
+///
+/// It's advanced once per frame with [Clock::tick]. Scene time is what animations read, it can
+/// be paused and scaled. Elapsed time always advances by the unscaled step and is what input is
+/// integrated over, so the camera still moves while the animation is paused. In fixed-step mode
+/// every frame advances by exactly the same amount, which makes renders reproducible.
+pub struct Clock{
+    time: Cell<f64>,
+    elapsed: Cell<f64>,
+    delta: Cell<f64>,
+    step: Cell<f64>,
+    frame: Cell<u64>,
+    last_wall_ms: Cell<Option<f64>>,
+    frame_time: Cell<f64>,
+    paused: Cell<bool>,
+    time_scale: Cell<f64>,
+    fixed_step: Cell<Option<f64>>,
+}
+
+impl Default for Clock{
+    fn default() -> Self{
+        Clock{
+            time: Cell::new(0.0),
+            elapsed: Cell::new(0.0),
+            delta: Cell::new(0.0),
+            step: Cell::new(0.0),
+            frame: Cell::new(0),
+            last_wall_ms: Cell::new(None),
+            frame_time: Cell::new(0.0),
+            paused: Cell::new(false),
+            time_scale: Cell::new(1.0),
+            fixed_step: Cell::new(None),
+        }
+    }
+}
+
+impl Clock{
+    pub fn new() -> Self{
+        Clock::default()
+    }
+
+    /// Starts a new frame, `wall_ms` is the current wall clock time in milliseconds.
+    pub fn tick(&self, wall_ms: f64){
+        let wall_step = match self.last_wall_ms.get() {
+            Some(last) => ((wall_ms - last) / 1000.0).clamp(0.0, MAX_STEP),
+            None => 0.0,
+        };
+        if let Some(last) = self.last_wall_ms.get() {
+            let frame_time = self.frame_time.get();
+            self.frame_time.set(if frame_time == 0.0 {
+                wall_ms - last
+            }else{
+                frame_time + (wall_ms - last - frame_time) * FRAME_TIME_SMOOTHING
+            });
+        }
+        self.last_wall_ms.set(Some(wall_ms));
+
+        let step = self.fixed_step.get().unwrap_or(wall_step);
+        let delta = if self.paused.get() { 0.0 } else { step * self.time_scale.get() };
+
+        self.elapsed.set(self.elapsed.get() + step);
+        self.time.set(self.time.get() + delta);
+        self.delta.set(delta);
+        self.step.set(step);
+        self.frame.set(self.frame.get() + 1);
+    }
+
+    /// Scene time in seconds.
+    pub fn time(&self) -> f64{
+        self.time.get()
+    }
+
+    /// Scene time wrapped to `period` and narrowed for a shader uniform. Pick a period that the
+    /// animation repeats on, then the wrap isn't visible and the value never gets large enough
+    /// to lose precision as an f32.
+    pub fn wrapped(&self, period: f64) -> f32{
+        self.time.get().rem_euclid(period) as f32
+    }
+
+    /// Scene time that passed during the current frame.
+    pub fn delta(&self) -> f64{
+        self.delta.get()
+    }
+
+    /// Unscaled time that passed during the current frame, even while paused.
+    pub fn step(&self) -> f64{
+        self.step.get()
+    }
+
+    /// Unscaled time since the clock started, advancing even while paused.
+    pub fn elapsed(&self) -> f64{
+        self.elapsed.get()
+    }
+
+    /// Number of frames started so far.
+    pub fn frame(&self) -> u64{
+        self.frame.get()
+    }
+
+    /// Wall clock milliseconds per frame averaged over the last few frames. Unlike `step` it's
+    /// never clamped or fixed, it's how long frames really take.
+    pub fn frame_time(&self) -> f64{
+        self.frame_time.get()
+    }
+
+    pub fn paused(&self) -> bool{
+        self.paused.get()
+    }
+
+    pub fn set_paused(&self, paused: bool){
+        self.paused.set(paused);
+    }
+
+    pub fn time_scale(&self) -> f64{
+        self.time_scale.get()
+    }
+
+    pub fn set_time_scale(&self, time_scale: f64){
+        self.time_scale.set(time_scale);
+    }
+
+    pub fn fixed_step(&self) -> Option<f64>{
+        self.fixed_step.get()
+    }
+
+    /// Advances every frame by `step` seconds instead of the wall clock, `None` goes back to real time.
+    pub fn set_fixed_step(&self, step: Option<f64>){
+        self.fixed_step.set(step);
+    }
+
+    /// Jumps scene time, for example to restore a recording.
+    pub fn set_time(&self, time: f64){
+        self.time.set(time);
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    const EPSILON: f64 = 1e-9;
+
+    #[test]
+    fn follows_wall_clock(){
+        let clock = Clock::new();
+        clock.tick(1000.0);
+        assert_eq!(clock.time(), 0.0);
+        clock.tick(1016.0);
+        clock.tick(1032.0);
+        assert!((clock.time() - 0.032).abs() < EPSILON);
+        assert!((clock.delta() - 0.016).abs() < EPSILON);
+        assert!((clock.step() - 0.016).abs() < EPSILON);
+        assert_eq!(clock.frame(), 3);
+    }
+
+    #[test]
+    fn long_gaps_are_clamped(){
+        let clock = Clock::new();
+        clock.tick(0.0);
+        clock.tick(60_000.0);
+        assert!((clock.time() - MAX_STEP).abs() < EPSILON);
+    }
+
+    #[test]
+    fn pause_and_scale_only_affect_scene_time(){
+        let clock = Clock::new();
+        clock.tick(0.0);
+        clock.set_time_scale(2.0);
+        clock.tick(50.0);
+        assert!((clock.time() - 0.1).abs() < EPSILON);
+
+        clock.set_paused(true);
+        clock.tick(100.0);
+        assert!((clock.time() - 0.1).abs() < EPSILON);
+        assert_eq!(clock.delta(), 0.0);
+        assert!((clock.elapsed() - 0.1).abs() < EPSILON);
+    }
+
+    #[test]
+    fn fixed_step_ignores_wall_clock(){
+        let clock = Clock::new();
+        clock.set_fixed_step(Some(0.25));
+        for wall_ms in [5.0, 7.0, 900.0, 901.0].iter(){
+            clock.tick(*wall_ms);
+        }
+        assert!((clock.time() - 1.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn frame_time_is_smoothed_wall_time(){
+        let clock = Clock::new();
+        clock.set_fixed_step(Some(0.5));
+        clock.tick(0.0);
+        assert_eq!(clock.frame_time(), 0.0);
+        clock.tick(20.0);
+        assert!((clock.frame_time() - 20.0).abs() < EPSILON);
+        for frame in 1..=100{
+            clock.tick(20.0 + frame as f64 * 10.0);
+        }
+        assert!((clock.frame_time() - 10.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn wrapped_time_keeps_precision(){
+        let clock = Clock::new();
+        clock.set_time(1e7 + 0.125);
+        assert_eq!(clock.wrapped(1000.0), 0.125);
+        clock.set_time(-0.5);
+        assert_eq!(clock.wrapped(1000.0), 999.5);
+    }
+}
@@ -0,0 +1,84 @@
+use std::cell::Cell;
+use crate::params::{ParamInfo, ParamKind, ParamValue, Tunable};
+
+/// Coefficients of the cloud volume's single scattering model, all per unit of world distance.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CloudParams{
+    /// Scales the noise density into the extinction of the medium.
+    pub density: f32,
+    pub absorption: f32,
+    pub scattering: f32,
+    /// Fraction of the noise range that is treated as cloud, from 0 (clear) to 1 (overcast).
+    pub coverage: f32,
+    /// Henyey-Greenstein asymmetry, positive values scatter forward towards the sun.
+    pub anisotropy: f32,
+}
+
+impl Default for CloudParams{
+    fn default() -> Self{
+        CloudParams{
+            density: 0.03,
+            absorption: 0.1,
+            scattering: 0.9,
+            coverage: 0.4,
+            anisotropy: 0.5,
+        }
+    }
+}
+
+/// The fields of `CloudParams` in order, for the params panel and JS API.
+pub static CLOUD_PARAMS: [ParamInfo; 5] = [
+    ParamInfo{name: "density", label: "Density", kind: ParamKind::Float{min: 0.0, max: 0.2}, default: ParamValue::Float(0.03)},
+    ParamInfo{name: "absorption", label: "Absorption", kind: ParamKind::Float{min: 0.0, max: 1.0}, default: ParamValue::Float(0.1)},
+    ParamInfo{name: "scattering", label: "Scattering", kind: ParamKind::Float{min: 0.0, max: 2.0}, default: ParamValue::Float(0.9)},
+    ParamInfo{name: "coverage", label: "Coverage", kind: ParamKind::Float{min: 0.0, max: 1.0}, default: ParamValue::Float(0.4)},
+    ParamInfo{name: "anisotropy", label: "Anisotropy", kind: ParamKind::Float{min: -0.95, max: 0.95}, default: ParamValue::Float(0.5)},
+];
+
+impl Tunable for Cell<CloudParams>{
+    fn group(&self) -> &'static str{
+        "clouds"
+    }
+
+    fn params(&self) -> &'static [ParamInfo]{
+        &CLOUD_PARAMS
+    }
+
+    fn get(&self, index: usize) -> ParamValue{
+        let params = Cell::get(self);
+        ParamValue::Float(match index {
+            0 => params.density,
+            1 => params.absorption,
+            2 => params.scattering,
+            3 => params.coverage,
+            _ => params.anisotropy,
+        })
+    }
+
+    fn set(&self, index: usize, value: ParamValue){
+        if let ParamValue::Float(value) = value {
+            let mut params = Cell::get(self);
+            match index {
+                0 => params.density = value,
+                1 => params.absorption = value,
+                2 => params.scattering = value,
+                3 => params.coverage = value,
+                _ => params.anisotropy = value,
+            }
+            Cell::set(self, params);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn param_defaults_match_the_struct(){
+        let clouds = Cell::new(CloudParams::default());
+        for (index, param) in CLOUD_PARAMS.iter().enumerate(){
+            assert_eq!(Tunable::get(&clouds, index), param.default, "clouds.{}", param.name);
+        }
+    }
+}
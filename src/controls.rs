@@ -0,0 +1,620 @@
+use std::collections::VecDeque;
+use std::rc::Rc;
+use crate::bindings::{Action, ActionState, Bindings, Modifiers};
+use crate::camera_path::{CameraPath, Keyframe};
+use crate::clock::Clock;
+use crate::gamepad::{GamepadReading, GamepadState, SceneStep};
+use crate::motion::Motion;
+use crate::recording::{InputEvent, Recording};
+use crate::touch::{JoystickZone, TouchTracker};
+use crate::{log, log_warn};
+use crate::vec_lib::mat4::Mat4f;
+use crate::vec_lib::vec3::Vec3f;
+use crate::webgl_utils::camera::{Camera, FPSCamera, OrbitCamera, Projection};
+
+// Turns input events into camera movement. The browser side in `input.rs` only translates DOM
+// events into `InputEvent`s, everything they do to the camera happens here, so live input,
+// replays and tests all go through the same code.
+
+/// Units per second, and the range the scroll wheel can change it in.
+const MOVE_SPEED : f32 = 8.0;
+const MIN_MOVE_SPEED : f32 = 0.05;
+const MAX_MOVE_SPEED : f32 = 500.0;
+/// Speed change per CSS pixel of scrolling while flying, a notch of the wheel is about a fifth.
+const WHEEL_SPEED_STEP : f32 = 0.002;
+const ROT_SPEED : f32 = 0.01;
+/// Radians per second at full stick deflection.
+const GAMEPAD_LOOK_SPEED : f32 = 2.0;
+/// Radians per second while a roll key is held.
+const ROLL_SPEED : f32 = 1.5;
+/// Stops just short of looking straight up or down, where the view would flip over.
+const MAX_PITCH : f32 = 89.0 * std::f32::consts::PI / 180.0;
+/// Units moved per CSS pixel of a pan or pinch, at a focus distance of 1 when orbiting.
+const PAN_SPEED : f32 = 0.02;
+const TOUCH_PINCH_SPEED : f32 = 0.05;
+/// Zoom per CSS pixel of scrolling, a notch of the wheel is usually 100 pixels.
+const WHEEL_ZOOM_SPEED : f32 = 0.002;
+const DEFAULT_ORBIT_DISTANCE : f32 = 4.0;
+/// Seconds between keyframes added from the keyboard.
+const KEYFRAME_SPACING : f64 = 2.0;
+/// Size and inset of the virtual joystick, in CSS pixels. Keep in sync with `.joystick` in main.css.
+const JOYSTICK_RADIUS : f32 = 60.0;
+const JOYSTICK_MARGIN : f32 = 20.0;
+
+/// Scene change asked for by the user, handled by the scene registry on the next frame.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SceneRequest{
+    Index(usize),
+    Step(SceneStep),
+}
+
+/// Mouse, wheel and touch input since the last frame. The events only add up here, the camera
+/// is moved once per frame in `update`.
+#[derive(Default)]
+struct QueuedInput{
+    /// Radians, already scaled by the look sensitivity.
+    look: (f32, f32),
+    /// Amounts for `Camera::pan`.
+    pan: (f32, f32),
+    /// Amount for `Camera::zoom`.
+    zoom: f32,
+    /// Scrolled CSS pixels, zooming while orbiting and changing the speed while flying.
+    wheel: f32,
+}
+
+pub struct Controls{
+    camera: Box<dyn Camera>,
+    orbiting: bool,
+    // distance of the last orbit, used again the next time orbiting starts
+    orbit_distance: f32,
+    clock: Rc<Clock>,
+    queued: QueuedInput,
+    motion: Motion,
+    // fly speed in units per second, set with the scroll wheel
+    speed: f32,
+    actions: ActionState,
+    pointer_locked: bool,
+    gamepad: GamepadState,
+    touch: TouchTracker,
+    // height of the canvas in CSS pixels if the page has an on-screen joystick, which sits in
+    // its bottom left corner
+    joystick_canvas_height: Option<f32>,
+    scene_request: Option<SceneRequest>,
+    // debug view key presses since the last frame
+    debug_view_cycles: u32,
+    path: CameraPath,
+    // scene time the path started playing at
+    playback_start: Option<f64>,
+    recording: Option<Recording>,
+    // events still to replay, live input is ignored while there are any
+    replay: Option<VecDeque<InputEvent>>,
+    replay_gamepad: Option<GamepadReading>,
+    // bindings and clock settings to go back to once a replay is over
+    before_replay: Option<(Bindings, bool, f64)>,
+}
+
+impl Controls{
+    pub fn new(clock: Rc<Clock>, bindings: Bindings) -> Self{
+        let camera = FPSCamera::new(
+            Vec3f::new(0.0, 1.0, 0.0),
+            Vec3f::new(0.0, 0.0, 1.0),
+            Vec3f::new(0.0, 1.0, 0.0),
+            45.0,
+            1280.0 / 960.0,
+            0.1,
+            1000.0,
+        );
+        Controls{
+            camera: Box::new(camera),
+            orbiting: false,
+            orbit_distance: DEFAULT_ORBIT_DISTANCE,
+            clock,
+            queued: QueuedInput::default(),
+            motion: Motion::new(),
+            speed: MOVE_SPEED,
+            actions: ActionState::new(bindings),
+            pointer_locked: false,
+            gamepad: GamepadState::new(),
+            touch: TouchTracker::new(),
+            joystick_canvas_height: None,
+            scene_request: None,
+            debug_view_cycles: 0,
+            path: CameraPath::new(),
+            playback_start: None,
+            recording: None,
+            replay: None,
+            replay_gamepad: None,
+            before_replay: None,
+        }
+    }
+
+    pub fn proj_matrix(&self) -> Mat4f{
+        self.camera.proj_matrix()
+    }
+
+    pub fn view_matrix(&self) -> Mat4f{
+        self.camera.view_matrix()
+    }
+
+    /// Position, view direction and up vector of the camera.
+    pub fn pose(&self) -> (Vec3f, Vec3f, Vec3f){
+        (self.camera.position(), self.camera.forward(), self.camera.up())
+    }
+
+    /// Jumps the camera to a pose, stopping any path playback and leftover motion.
+    pub fn set_pose(&mut self, position: Vec3f, forward: Vec3f, up: Vec3f){
+        self.stop_path();
+        self.motion.stop();
+        self.camera.set_pose(position, forward, up);
+    }
+
+    pub fn projection(&self) -> Projection{
+        self.camera.projection()
+    }
+
+    pub fn set_projection(&mut self, projection: Projection){
+        self.camera.set_projection(projection);
+    }
+
+    pub fn bindings(&self) -> &Bindings{
+        self.actions.bindings()
+    }
+
+    pub fn set_bindings(&mut self, bindings: Bindings){
+        self.actions.set_bindings(bindings);
+    }
+
+    pub fn pointer_locked(&self) -> bool{
+        self.pointer_locked
+    }
+
+    /// Enables the on-screen joystick for a canvas this many CSS pixels high, `None` if the page
+    /// has no joystick to show.
+    pub fn set_joystick_canvas_height(&mut self, height: Option<f32>){
+        self.joystick_canvas_height = height;
+    }
+
+    /// Where the on-screen joystick is, if it's shown.
+    pub fn joystick_zone(&self) -> Option<JoystickZone>{
+        if !self.actions.bindings().touch_joystick() {
+            return None;
+        }
+        let offset = JOYSTICK_MARGIN + JOYSTICK_RADIUS;
+        Some(JoystickZone{
+            x: offset,
+            y: self.joystick_canvas_height? - offset,
+            radius: JOYSTICK_RADIUS,
+        })
+    }
+
+    /// How far the joystick knob is pushed from the center, in CSS pixels.
+    pub fn joystick_offset(&self) -> (f32, f32){
+        self.touch.joystick_offset()
+    }
+
+    pub fn replaying(&self) -> bool{
+        self.replay.is_some()
+    }
+
+    /// Handles an event from the page, unless a replay is feeding in its own.
+    pub fn live(&mut self, event: InputEvent) -> bool{
+        if self.replay.is_some() {
+            return false;
+        }
+        self.dispatch(event)
+    }
+
+    /// Handles an event from the page or a replay, adding it to the recording if one is
+    /// running. Returns whether the event did something, so the browser's default can be skipped.
+    pub fn dispatch(&mut self, event: InputEvent) -> bool{
+        if let Some(recording) = &mut self.recording {
+            recording.events.push(event.clone());
+        }
+        match event {
+            InputEvent::KeyDown(code, modifiers) => return self.press_key(&code, &modifiers),
+            InputEvent::KeyUp(code) => self.actions.release(&code),
+            InputEvent::MouseMove{dx, dy, buttons} => self.drag(dx, dy, buttons),
+            InputEvent::Wheel(pixels) => self.queued.wheel += pixels,
+            InputEvent::TouchDown{id, x, y} => self.touch_down(id, x, y),
+            InputEvent::TouchMove{id, x, y} => self.touch_move(id, x, y),
+            InputEvent::TouchUp(id) => self.touch.up(id),
+            InputEvent::PointerLock(locked) => self.pointer_locked = locked,
+            InputEvent::Gamepad(reading) => self.replay_gamepad = Some(reading),
+            InputEvent::Frame(_) => (),
+        }
+        true
+    }
+
+    /// Records input from here on. `view` is the encoded view the recording starts from.
+    pub fn start_recording(&mut self, view: String){
+        self.reset_for_recording();
+        let mut recording = Recording::new(view, self.clock.time(), self.speed);
+        recording.paused = self.clock.paused();
+        recording.time_scale = self.clock.time_scale();
+        recording.bindings = Some(self.actions.bindings().clone());
+        let locked = self.pointer_locked;
+        self.recording = Some(recording);
+        self.dispatch(InputEvent::PointerLock(locked));
+    }
+
+    pub fn stop_recording(&mut self) -> Option<Recording>{
+        self.recording.take()
+    }
+
+    /// Starts feeding a recording's events in place of live input, with the bindings and clock
+    /// settings it was made with until it ends. The caller restores the recording's view and
+    /// scene time.
+    pub fn start_replay(&mut self, recording: &Recording){
+        self.reset_for_recording();
+        self.before_replay = Some((self.actions.bindings().clone(), self.clock.paused(), self.clock.time_scale()));
+        if let Some(bindings) = &recording.bindings {
+            self.actions.set_bindings(bindings.clone());
+        }
+        self.clock.set_paused(recording.paused);
+        self.clock.set_time_scale(recording.time_scale);
+        self.speed = recording.speed;
+        self.replay = Some(recording.events.iter().cloned().collect());
+    }
+
+    /// Replays the events that arrived before the next recorded frame and returns how long
+    /// that frame was, to step the clock by. `None` once the replay is over.
+    pub fn replay_frame(&mut self) -> Option<f64>{
+        loop {
+            let event = self.replay.as_mut()?.pop_front();
+            match event {
+                Some(InputEvent::Frame(step)) =>{
+                    let events = self.replay.as_mut()?;
+                    if let Some(InputEvent::Gamepad(_)) = events.front() {
+                        if let Some(InputEvent::Gamepad(reading)) = events.pop_front() {
+                            self.replay_gamepad = Some(reading);
+                        }
+                    }
+                    return Some(step);
+                }
+                Some(event) =>{
+                    self.dispatch(event);
+                }
+                None =>{
+                    log!("Input replay finished");
+                    self.replay = None;
+                    self.restore_after_replay();
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Scene picked with the keyboard or gamepad since the last call.
+    pub fn take_scene_request(&mut self) -> Option<SceneRequest>{
+        self.scene_request.take()
+    }
+
+    /// How many times the debug view key was pressed since the last call.
+    pub fn take_debug_view_cycles(&mut self) -> u32{
+        std::mem::take(&mut self.debug_view_cycles)
+    }
+
+    /// Starts recordings and replays from rest in fly mode, so both begin in the same state.
+    fn reset_for_recording(&mut self){
+        self.stop_path();
+        self.motion.stop();
+        self.actions.release_all();
+        self.queued = QueuedInput::default();
+        self.replay = None;
+        self.replay_gamepad = None;
+        self.restore_after_replay();
+        if self.orbiting {
+            self.toggle_orbit();
+        }
+        self.orbit_distance = DEFAULT_ORBIT_DISTANCE;
+    }
+
+    fn restore_after_replay(&mut self){
+        if let Some((bindings, paused, time_scale)) = self.before_replay.take() {
+            self.actions.set_bindings(bindings);
+            self.clock.set_paused(paused);
+            self.clock.set_time_scale(time_scale);
+        }
+    }
+
+    fn drag(&mut self, dx: f32, dy: f32, buttons: u16){
+        // while locked the movement isn't bounded by the canvas, so no button has to be held
+        let looking = if self.actions.bindings().pointer_lock() {
+            self.pointer_locked
+        }else{
+            (buttons & 0x1) == 1
+        };
+        if looking{
+            let bindings = self.actions.bindings();
+            let speed = ROT_SPEED * bindings.sensitivity(Action::Look);
+            let dy = if bindings.invert_y() { -dy } else { dy };
+            self.queued.look.0 -= dx * speed;
+            self.queued.look.1 -= dy * speed;
+        }else if !self.pointer_locked && (buttons & 0x6) != 0 {
+            // right or middle button drags the view along with the mouse
+            self.queued.pan.0 -= dx * PAN_SPEED;
+            self.queued.pan.1 += dy * PAN_SPEED;
+        }
+    }
+
+    /// Swaps between flying and orbiting without moving the view. Orbiting circles the point
+    /// the last orbit's distance ahead of the camera.
+    fn toggle_orbit(&mut self){
+        let projection = self.camera.projection();
+        let eye = self.camera.position();
+        self.camera = if self.orbiting {
+            let target = self.camera.target();
+            self.orbit_distance = (target - eye).length();
+            Box::new(FPSCamera::new(eye, target, Vec3f::new(0.0, 1.0, 0.0),
+                projection.fov, projection.aspect, projection.near, projection.far))
+        }else{
+            Box::new(OrbitCamera::from_view(eye, self.camera.forward(), self.orbit_distance, projection))
+        };
+        self.orbiting = !self.orbiting;
+    }
+
+    fn touch_down(&mut self, id: i32, x: f32, y: f32){
+        let zone = self.joystick_zone();
+        self.touch.down(id, x, y, zone.as_ref());
+    }
+
+    fn touch_move(&mut self, id: i32, x: f32, y: f32){
+        let zone = self.joystick_zone();
+        let gesture = self.touch.moved(id, x, y, zone.as_ref());
+
+        let bindings = self.actions.bindings();
+        let speed = ROT_SPEED * bindings.sensitivity(Action::Look);
+        let (dx, dy) = gesture.look;
+        let dy = if bindings.invert_y() { -dy } else { dy };
+        self.queued.look.0 -= dx * speed;
+        self.queued.look.1 -= dy * speed;
+
+        // dragging two fingers moves the view along with them, spreading them zooms in
+        let (pan_x, pan_y) = gesture.pan;
+        self.queued.pan.0 -= pan_x * PAN_SPEED;
+        self.queued.pan.1 += pan_y * PAN_SPEED;
+        self.queued.zoom += gesture.pinch * TOUCH_PINCH_SPEED * bindings.sensitivity(Action::Zoom);
+    }
+
+    /// Starts the action bound to a key, returns whether there was one.
+    fn press_key(&mut self, code: &str, modifiers: &Modifiers) -> bool{
+        match self.actions.press(code, modifiers) {
+            Some(Action::SwitchScene(index)) =>{
+                self.scene_request = Some(SceneRequest::Index(index));
+            }
+            Some(Action::ToggleOrbit) =>{
+                self.toggle_orbit();
+            }
+            Some(Action::CycleDebugView) =>{
+                self.debug_view_cycles += 1;
+            }
+            Some(Action::AddKeyframe) =>{
+                self.add_keyframe();
+            }
+            Some(Action::PlayPath) =>{
+                if self.playback_start.is_some() {
+                    self.stop_path();
+                }else{
+                    self.play_path();
+                }
+            }
+            Some(_) => (),
+            None =>{
+                log!("Pressed key '{}'", code);
+                return false;
+            }
+        };
+        true
+    }
+
+    fn add_keyframe(&mut self){
+        let time = self.path.keyframes().last().map_or(0.0, |last| last.time + KEYFRAME_SPACING);
+        self.path.push(Keyframe::from_pose(time, self.camera.position(), &self.camera.forward(),
+            &self.camera.up()));
+        log!("Added camera keyframe {} at {}s", self.path.keyframes().len(), time);
+    }
+
+    pub fn path(&self) -> &CameraPath{
+        &self.path
+    }
+
+    /// Replaces the camera path, stopping it if it was playing.
+    pub fn set_path(&mut self, path: CameraPath){
+        self.stop_path();
+        self.path = path;
+    }
+
+    pub fn play_path(&mut self){
+        if self.path.keyframes().is_empty() {
+            log_warn!("The camera path has no keyframes to play");
+            return;
+        }
+        self.playback_start = Some(self.clock.time());
+        self.motion.stop();
+    }
+
+    pub fn stop_path(&mut self){
+        self.playback_start = None;
+    }
+
+    /// Moves the camera along the path, returns whether it's still playing.
+    fn follow_path(&mut self) -> bool{
+        let start = match self.playback_start {
+            Some(start) => start,
+            None => return false,
+        };
+        let time = self.clock.time() - start;
+        if let Some(pose) = self.path.sample(time) {
+            self.camera.set_pose(pose.position, pose.forward(), pose.up());
+        }
+        if time >= self.path.duration() {
+            self.stop_path();
+        }
+        true
+    }
+
+    /// Applies the queued events and integrates the held keys and sticks over the last frame.
+    /// Call once per frame, after the clock has ticked. `read_gamepad` is only called for live
+    /// input, replays use the recorded readings.
+    pub fn update(&mut self, read_gamepad: impl FnOnce() -> Option<GamepadReading>){
+        // integrates over the clock's unscaled step, not the wall clock, so movement follows the
+        // frames that were actually rendered and keeps going while the animation is paused
+        let step = self.clock.step();
+        let time_delta = step as f32;
+        if let Some(recording) = &mut self.recording {
+            recording.events.push(InputEvent::Frame(step));
+        }
+        let queued = std::mem::take(&mut self.queued);
+
+        // the path follows scene time, so it replays the same way under a fixed step
+        if self.follow_path() {
+            return;
+        }
+
+        self.camera.look(queued.look.0, queued.look.1, MAX_PITCH);
+        self.camera.pan(queued.pan.0, queued.pan.1);
+        let zoom_sensitivity = self.actions.bindings().sensitivity(Action::Zoom);
+        if self.orbiting {
+            self.camera.zoom(queued.zoom - queued.wheel * WHEEL_ZOOM_SPEED * zoom_sensitivity);
+        }else{
+            self.camera.zoom(queued.zoom);
+            self.speed = (self.speed * f32::exp(-queued.wheel * WHEEL_SPEED_STEP * zoom_sensitivity))
+                .clamp(MIN_MOVE_SPEED, MAX_MOVE_SPEED);
+        }
+
+        let mut forward = self.actions.amount(Action::MoveForward) - self.actions.amount(Action::MoveBackward);
+        let mut right = self.actions.amount(Action::StrafeRight) - self.actions.amount(Action::StrafeLeft);
+        let mut up = self.actions.amount(Action::Ascend) - self.actions.amount(Action::Descend);
+
+        if let Some(zone) = self.joystick_zone() {
+            let (joystick_right, joystick_forward) = self.touch.joystick(&zone);
+            forward += joystick_forward * self.actions.bindings().sensitivity(Action::MoveForward);
+            right += joystick_right * self.actions.bindings().sensitivity(Action::StrafeRight);
+        }
+
+        let reading = if self.replay.is_some() { self.replay_gamepad.take() } else { read_gamepad() };
+        if let Some(reading) = reading {
+            if let Some(recording) = &mut self.recording {
+                recording.events.push(InputEvent::Gamepad(reading.clone()));
+            }
+            let bindings = self.actions.bindings();
+            let (motion, step) = self.gamepad.update(reading, bindings.gamepad());
+            forward += motion.forward * bindings.sensitivity(Action::MoveForward);
+            right += motion.right * bindings.sensitivity(Action::StrafeRight);
+            up += motion.up * bindings.sensitivity(Action::Ascend);
+
+            let speed = GAMEPAD_LOOK_SPEED * time_delta * bindings.sensitivity(Action::Look);
+            let pitch = if bindings.invert_y() { -motion.pitch } else { motion.pitch };
+            self.camera.look(-motion.yaw * speed, pitch * speed, MAX_PITCH);
+
+            if let Some(step) = step {
+                self.scene_request = Some(SceneRequest::Step(step));
+            }
+        }
+
+        if self.actions.bindings().free_flight() {
+            let roll = self.actions.amount(Action::RollRight) - self.actions.amount(Action::RollLeft);
+            self.camera.roll(roll * ROLL_SPEED * time_delta);
+        }
+
+        let wish = self.camera.forward().scale(forward)
+            + self.camera.right().scale(right)
+            + self.camera.up().scale(up);
+        let movement = self.motion.step(&wish.scale(self.speed), time_delta);
+        self.camera.translate(&movement);
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn key_down(code: &str) -> InputEvent{
+        InputEvent::KeyDown(String::from(code), Modifiers::default())
+    }
+
+    #[test]
+    fn held_keys_move_the_camera(){
+        let clock = Rc::new(Clock::new());
+        clock.set_fixed_step(Some(0.1));
+        let mut controls = Controls::new(clock.clone(), Bindings::default());
+        controls.set_pose(Vec3f::new(0.0, 0.0, 0.0), Vec3f::new(0.0, 0.0, 1.0), Vec3f::new(0.0, 1.0, 0.0));
+
+        assert!(controls.live(key_down("KeyW")));
+        for frame in 0..10{
+            clock.tick(frame as f64 * 100.0);
+            controls.update(|| None);
+        }
+        let (position, _, _) = controls.pose();
+        assert!(position.z() > 1.0, "moved to z = {}", position.z());
+        assert!(position.x().abs() < 1e-4 && position.y().abs() < 1e-4);
+        assert!(!controls.live(key_down("KeyZ")));
+    }
+
+    /// Steps the clock by the recorded frame times like the app does, returns how many frames
+    /// there were.
+    fn replay(controls: &mut Controls, clock: &Clock) -> usize{
+        let mut frames = 0;
+        while let Some(step) = controls.replay_frame() {
+            clock.set_fixed_step(Some(step));
+            clock.tick(0.0);
+            controls.update(|| None);
+            frames += 1;
+        }
+        frames
+    }
+
+    #[test]
+    fn replay_ends_at_the_recorded_pose(){
+        let start = (Vec3f::new(1.0, 2.0, 3.0), Vec3f::new(0.0, 0.0, 1.0), Vec3f::new(0.0, 1.0, 0.0));
+        let bindings = Bindings::with_overrides("move_forward = KeyZ\nlook.invert_y = true\nlook.sensitivity = 2")
+            .unwrap();
+        let clock = Rc::new(Clock::new());
+        clock.set_time_scale(0.5);
+        let mut controls = Controls::new(clock.clone(), bindings);
+        controls.set_pose(start.0, start.1, start.2);
+        controls.start_recording(String::new());
+        for frame in 0..30{
+            match frame {
+                0 => controls.live(key_down("KeyZ")),
+                5 => controls.live(InputEvent::MouseMove{dx: 12.0, dy: -7.0, buttons: 1}),
+                12 => controls.live(InputEvent::Wheel(-200.0)),
+                20 => controls.live(InputEvent::KeyUp(String::from("KeyZ"))),
+                _ => false,
+            };
+            clock.set_fixed_step(Some(if frame % 3 == 0 { 0.02 } else { 1.0 / 60.0 }));
+            clock.tick(0.0);
+            controls.update(|| None);
+        }
+        let recording = Recording::parse(&controls.stop_recording().unwrap().to_text()).unwrap();
+        let recorded = controls.pose();
+
+        // a different user on a different clock, the recording brings its own settings
+        let clock = Rc::new(Clock::new());
+        clock.set_paused(true);
+        let mut controls = Controls::new(clock.clone(), Bindings::default());
+        controls.set_pose(start.0, start.1, start.2);
+        controls.start_replay(&recording);
+        assert!(!clock.paused());
+        assert_eq!(clock.time_scale(), 0.5);
+        assert_eq!(replay(&mut controls, &clock), 30);
+
+        let (position, forward, up) = controls.pose();
+        assert!(position == recorded.0 && forward == recorded.1 && up == recorded.2);
+        assert!((position - start.0).length() > 1.0);
+        assert!(clock.paused());
+        assert_eq!(clock.time_scale(), 1.0);
+        assert_eq!(*controls.bindings(), Bindings::default());
+    }
+
+    #[test]
+    fn keys_request_scenes_and_debug_views(){
+        let mut controls = Controls::new(Rc::new(Clock::new()), Bindings::default());
+        controls.live(key_down("Digit2"));
+        controls.live(key_down("KeyV"));
+        controls.live(key_down("KeyV"));
+        assert_eq!(controls.take_scene_request(), Some(SceneRequest::Index(1)));
+        assert_eq!(controls.take_scene_request(), None);
+        assert_eq!(controls.take_debug_view_cycles(), 2);
+    }
+}
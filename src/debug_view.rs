@@ -0,0 +1,94 @@
+// What the marcher passes show instead of the shaded scene, to see why something renders wrong.
+// The index of each view is the value of the `debugView` uniform, the shaders compare it to the
+// DEBUG_ defines from `shader_defines`.
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum DebugView{
+    #[default]
+    Off,
+    /// March steps taken, from blue for none to red for the step limit.
+    Steps,
+    /// Surface normals mapped from [-1, 1] to colors.
+    Normals,
+    /// Distance to the hit, white close by and fading out towards the far plane.
+    Depth,
+    /// A color for each material id.
+    Material,
+    /// How much sun reaches the hit, black in full shadow.
+    Shadow,
+    /// Why rays missed, red for running out of steps and blue for leaving the scene.
+    Misses,
+}
+
+/// Every view in the order they are cycled through.
+pub const DEBUG_VIEWS: [DebugView; 7] = [
+    DebugView::Off,
+    DebugView::Steps,
+    DebugView::Normals,
+    DebugView::Depth,
+    DebugView::Material,
+    DebugView::Shadow,
+    DebugView::Misses,
+];
+
+impl DebugView{
+    /// Name used by the JS API.
+    pub fn name(self) -> &'static str{
+        match self {
+            DebugView::Off => "off",
+            DebugView::Steps => "steps",
+            DebugView::Normals => "normals",
+            DebugView::Depth => "depth",
+            DebugView::Material => "material",
+            DebugView::Shadow => "shadow",
+            DebugView::Misses => "misses",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<DebugView>{
+        DEBUG_VIEWS.iter().copied().find(|view| view.name() == name)
+    }
+
+    /// Value of the `debugView` uniform.
+    pub fn index(self) -> i32{
+        DEBUG_VIEWS.iter().position(|view| *view == self).unwrap_or(0) as i32
+    }
+
+    /// A `DEBUG_<NAME> <index>` define for every view, to compare the `debugView` uniform to.
+    pub fn shader_defines() -> Vec<String>{
+        DEBUG_VIEWS.iter()
+            .map(|view| format!("DEBUG_{} {}", view.name().to_uppercase(), view.index()))
+            .collect()
+    }
+
+    /// The view after this one, going back to `Off` after the last.
+    pub fn next(self) -> DebugView{
+        DEBUG_VIEWS[(self.index() as usize + 1) % DEBUG_VIEWS.len()]
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn names_round_trip_and_cycling_visits_every_view(){
+        let mut view = DebugView::Off;
+        for (index, expected) in DEBUG_VIEWS.iter().enumerate(){
+            assert_eq!(view, *expected);
+            assert_eq!(view.index(), index as i32);
+            assert_eq!(DebugView::from_name(view.name()), Some(view));
+            view = view.next();
+        }
+        assert_eq!(view, DebugView::Off);
+        assert_eq!(DebugView::from_name("Steps"), None);
+    }
+
+    #[test]
+    fn shader_defines_match_the_indices(){
+        let defines = DebugView::shader_defines();
+        assert_eq!(defines.len(), DEBUG_VIEWS.len());
+        assert_eq!(defines[0], "DEBUG_OFF 0");
+        assert_eq!(defines[6], "DEBUG_MISSES 6");
+    }
+}
@@ -0,0 +1,192 @@
+use std::fmt;
+#[cfg(feature = "browser")]
+use wasm_bindgen::closure::Closure;
+#[cfg(feature = "browser")]
+use wasm_bindgen::{JsCast, JsValue};
+#[cfg(feature = "browser")]
+use web_sys::HtmlCanvasElement;
+
+/// Lines of source shown before and after each line a shader error points at.
+const EXCERPT_RADIUS: usize = 2;
+/// Most error locations shown from one shader log.
+const MAX_EXCERPTS: usize = 3;
+
+/// Something that went wrong while setting up the renderer.
+#[derive(Clone, PartialEq, Debug)]
+pub enum RenderError{
+    /// A shader failed to compile. `excerpt` holds the source around the lines the log points at.
+    ShaderCompile{stage: &'static str, log: String, excerpt: String},
+    ShaderLink{log: String},
+    /// The canvas can't give a rendering context of the named kind.
+    ContextUnavailable(String),
+    /// Creating or uploading a GL or DOM resource failed, the string says which.
+    ResourceCreation(String),
+    /// Listening for the named DOM event failed.
+    EventListener(String),
+}
+
+impl RenderError{
+    /// Error for a shader that didn't compile, quoting `source` where `log` points.
+    pub fn shader_compile(stage: &'static str, source: &str, log: &str) -> Self{
+        RenderError::ShaderCompile{
+            stage,
+            log: String::from(log.trim()),
+            excerpt: source_excerpt(source, log),
+        }
+    }
+
+    /// Name of the JS `Error` this becomes.
+    pub fn name(&self) -> &'static str{
+        match self {
+            RenderError::ShaderCompile{..} => "ShaderCompileError",
+            RenderError::ShaderLink{..} => "ShaderLinkError",
+            RenderError::ContextUnavailable(_) => "ContextUnavailableError",
+            RenderError::ResourceCreation(_) => "ResourceCreationError",
+            RenderError::EventListener(_) => "EventListenerError",
+        }
+    }
+
+    /// One line summary, the rest of the message is details.
+    pub fn title(&self) -> String{
+        match self {
+            RenderError::ShaderCompile{stage, ..} => format!("Failed to compile the {} shader", stage),
+            RenderError::ShaderLink{..} => String::from("Failed to link the shader program"),
+            RenderError::ContextUnavailable(kind) => format!("Failed to get a {} context for the canvas", kind),
+            RenderError::ResourceCreation(what) => format!("Failed to create {}", what),
+            RenderError::EventListener(event) => format!("Failed to listen for '{}' events", event),
+        }
+    }
+
+    pub fn details(&self) -> String{
+        match self {
+            RenderError::ShaderCompile{log, excerpt, ..} if excerpt.is_empty() => log.clone(),
+            RenderError::ShaderCompile{log, excerpt, ..} => format!("{}\n\n{}", log, excerpt),
+            RenderError::ShaderLink{log} => log.clone(),
+            _ => String::new(),
+        }
+    }
+}
+
+impl fmt::Display for RenderError{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        let details = self.details();
+        if details.is_empty() {
+            write!(f, "{}.", self.title())
+        }else{
+            write!(f, "{}:\n{}", self.title(), details)
+        }
+    }
+}
+
+#[cfg(feature = "browser")]
+impl From<RenderError> for JsValue{
+    fn from(error: RenderError) -> Self{
+        let js_error = js_sys::Error::new(&error.to_string());
+        js_error.set_name(error.name());
+        js_error.into()
+    }
+}
+
+#[cfg(feature = "browser")]
+/// Covers `canvas` with the error until it's clicked away. The canvas's parent has to be
+/// positioned for the overlay to line up with it.
+pub fn show_overlay(canvas: &HtmlCanvasElement, error: &RenderError){
+    let overlay = (|| -> Option<web_sys::Element>{
+        let document = canvas.owner_document()?;
+        let overlay = document.create_element("div").ok()?;
+        overlay.set_class_name("error-overlay");
+        let title = document.create_element("strong").ok()?;
+        title.set_text_content(Some(&error.title()));
+        overlay.append_child(&title).ok()?;
+        let details = error.details();
+        if !details.is_empty() {
+            let pre = document.create_element("pre").ok()?;
+            pre.set_text_content(Some(&details));
+            overlay.append_child(&pre).ok()?;
+        }
+        canvas.insert_adjacent_element("afterend", &overlay).ok()?;
+        Some(overlay)
+    })();
+
+    match overlay {
+        Some(overlay) => {
+            let closure_overlay = overlay.clone();
+            let dismiss: js_sys::Function = Closure::<dyn FnMut()>::new(move || closure_overlay.remove())
+                .into_js_value().unchecked_into();
+            let _ = overlay.add_event_listener_with_callback("click", &dismiss);
+        }
+        None => {
+            crate::log_error!("Failed to show the error overlay");
+        }
+    }
+}
+
+/// Line numbers in a GLSL info log, which look like `ERROR: 0:42: 'x' : undeclared identifier`.
+fn error_lines(log: &str) -> Vec<usize>{
+    let mut lines = Vec::new();
+    for entry in log.lines(){
+        let line = entry.trim()
+            .strip_prefix("ERROR:")
+            .and_then(|rest| rest.trim().split(':').nth(1))
+            .and_then(|line| line.trim().parse::<usize>().ok());
+        if let Some(line) = line {
+            if !lines.contains(&line) {
+                lines.push(line);
+            }
+        }
+    }
+    lines.truncate(MAX_EXCERPTS);
+    lines
+}
+
+/// The source around each line the log points at, numbered with the pointed at lines marked.
+fn source_excerpt(source: &str, log: &str) -> String{
+    let source: Vec<&str> = source.lines().collect();
+    let mut excerpts = Vec::new();
+    for line in error_lines(log){
+        if line == 0 || line > source.len() {
+            continue;
+        }
+        let first = line.saturating_sub(EXCERPT_RADIUS).max(1);
+        let last = (line + EXCERPT_RADIUS).min(source.len());
+        let excerpt: Vec<String> = (first..=last)
+            .map(|number| format!("{} {:>4} | {}", if number == line { ">" } else { " " }, number, source[number - 1]))
+            .collect();
+        excerpts.push(excerpt.join("\n"));
+    }
+    excerpts.join("\n...\n")
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    const SOURCE: &str = "#version 300 es\nprecision mediump float;\nout vec4 color;\nvoid main(){\n    color = vec4(x);\n}\n";
+
+    #[test]
+    fn quotes_the_lines_the_log_points_at(){
+        let error = RenderError::shader_compile("fragment", SOURCE,
+            "ERROR: 0:5: 'x' : undeclared identifier\nERROR: 0:5: 'constructor' : not enough data\n");
+        match &error {
+            RenderError::ShaderCompile{excerpt, ..} => {
+                assert!(excerpt.contains(">    5 |     color = vec4(x);"));
+                assert!(excerpt.contains("     3 | out vec4 color;"));
+                assert!(!excerpt.contains("precision"));
+                assert!(!excerpt.contains("..."));
+            }
+            _ => panic!("expected a compile error"),
+        }
+        assert!(error.to_string().starts_with("Failed to compile the fragment shader:\nERROR: 0:5"));
+    }
+
+    #[test]
+    fn unknown_log_formats_keep_just_the_log(){
+        let error = RenderError::shader_compile("vertex", SOURCE, "something went wrong");
+        assert_eq!(error.details(), "something went wrong");
+        assert_eq!(error_lines("ERROR: 0:99: far away\nWARNING: 0:2: ignored"), vec![99]);
+        assert_eq!(source_excerpt(SOURCE, "ERROR: 0:99: far away"), "");
+        assert_eq!(RenderError::ResourceCreation(String::from("a texture")).to_string(), "Failed to create a texture.");
+        assert_eq!(RenderError::ContextUnavailable(String::from("WebGL 2")).to_string(),
+            "Failed to get a WebGL 2 context for the canvas.");
+    }
+}
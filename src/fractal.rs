@@ -0,0 +1,40 @@
+use crate::params::{ParamInfo, ParamKind, ParamValue};
+
+// The params of the fractal scene. They live in a `ParamStore` and are bound to the uniforms of
+// fractal_frag.glsl by name, so adding one here and to the shader is all it takes.
+//
+// `bulb_pos` and `bulb_scale` move the bulb and the sponge carved into it from the origin into the
+// world. With the defaults the bulb sits at 11,0,11 with a radius of about 11, to the side of the
+// starting camera at 0,1,0.
+
+/// Uniforms of fractal_frag.glsl that can be tuned while it runs.
+pub static FRACTAL_PARAMS: [ParamInfo; 8] = [
+    ParamInfo{name: "exponent", label: "Mandelbulb exponent", kind: ParamKind::Float{min: 2.0, max: 16.0}, default: ParamValue::Float(8.0)},
+    ParamInfo{name: "iterations", label: "Mandelbulb iterations", kind: ParamKind::Int{min: 1, max: 16}, default: ParamValue::Int(4)},
+    ParamInfo{name: "bulb_pos", label: "Bulb position", kind: ParamKind::Vec3{min: -100.0, max: 100.0}, default: ParamValue::Vec3([11.0, 0.0, 11.0])},
+    ParamInfo{name: "bulb_scale", label: "Bulb scale", kind: ParamKind::Float{min: 1.0, max: 50.0}, default: ParamValue::Float(10.0)},
+    ParamInfo{name: "menger_iterations", label: "Menger iterations", kind: ParamKind::Int{min: 0, max: 10}, default: ParamValue::Int(8)},
+    ParamInfo{name: "march_threshold", label: "March threshold", kind: ParamKind::Float{min: 0.0001, max: 0.02}, default: ParamValue::Float(0.003)},
+    ParamInfo{name: "albedo", label: "Surface color", kind: ParamKind::Color, default: ParamValue::Color([1.0, 1.0, 1.0])},
+    ParamInfo{name: "shadows", label: "Shadows", kind: ParamKind::Toggle, default: ParamValue::Toggle(true)},
+];
+
+/// The params of `FRACTAL_PARAMS` that shape the fractal, rather than how it's shaded.
+pub const FRACTAL_SHAPE_PARAMS: [&str; 5] = ["exponent", "iterations", "bulb_pos", "bulb_scale", "menger_iterations"];
+
+/// Values of `FRACTAL_PARAMS` before anything is changed.
+pub fn default_fractal_params() -> Vec<ParamValue>{
+    FRACTAL_PARAMS.iter().map(|param| param.default).collect()
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn shape_params_exist(){
+        for name in FRACTAL_SHAPE_PARAMS.iter(){
+            assert!(FRACTAL_PARAMS.iter().any(|param| param.name == *name), "fractal.{}", name);
+        }
+    }
+}
@@ -0,0 +1,192 @@
+// Turns raw gamepad readings into camera motion. Uses the "standard" Gamepad API layout:
+// left stick moves, right stick looks, the triggers ascend and descend, the bumpers cycle scenes
+// and Start toggles cruise, which keeps the current motion going without holding the sticks.
+
+const AXIS_LEFT_X: usize = 0;
+const AXIS_LEFT_Y: usize = 1;
+const AXIS_RIGHT_X: usize = 2;
+const AXIS_RIGHT_Y: usize = 3;
+const BUTTON_LEFT_BUMPER: usize = 4;
+const BUTTON_RIGHT_BUMPER: usize = 5;
+const BUTTON_LEFT_TRIGGER: usize = 6;
+const BUTTON_RIGHT_TRIGGER: usize = 7;
+const BUTTON_START: usize = 9;
+
+/// How stick and trigger deflection maps to speed.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct StickResponse{
+    /// Deflection below this is ignored, so worn sticks don't drift.
+    pub dead_zone: f32,
+    /// Exponent of the curve past the dead zone, above 1 gives finer control near the center.
+    pub exponent: f32,
+}
+
+impl Default for StickResponse{
+    fn default() -> Self{
+        StickResponse{
+            dead_zone: 0.15,
+            exponent: 2.0,
+        }
+    }
+}
+
+impl StickResponse{
+    /// Applies a radial dead zone to a stick, so diagonals aren't cut off, and rescales the rest
+    /// of the range to start from 0.
+    pub fn stick(&self, x: f32, y: f32) -> (f32, f32){
+        let length = f32::sqrt(x * x + y * y);
+        if length <= self.dead_zone {
+            return (0.0, 0.0);
+        }
+        let scaled = self.axis(f32::min(length, 1.0));
+        (x / length * scaled, y / length * scaled)
+    }
+
+    /// Dead zone and curve for a single axis or trigger.
+    pub fn axis(&self, value: f32) -> f32{
+        let magnitude = ((value.abs() - self.dead_zone) / (1.0 - self.dead_zone)).clamp(0.0, 1.0);
+        magnitude.powf(self.exponent).copysign(value)
+    }
+}
+
+/// A gamepad's buttons and axes at one point in time, as reported by the browser.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct GamepadReading{
+    pub axes: Vec<f32>,
+    /// Whether each button is pressed, and how far for analog buttons.
+    pub buttons: Vec<(bool, f32)>,
+}
+
+impl GamepadReading{
+    fn axis(&self, index: usize) -> f32{
+        self.axes.get(index).copied().unwrap_or(0.0)
+    }
+
+    fn pressed(&self, index: usize) -> bool{
+        self.buttons.get(index).is_some_and(|button| button.0)
+    }
+
+    fn value(&self, index: usize) -> f32{
+        self.buttons.get(index).map_or(0.0, |button| button.1)
+    }
+}
+
+/// Camera motion from the pad, each in [-1, 1]. Positive is forward, right, up, and looking
+/// right and up.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct GamepadMotion{
+    pub forward: f32,
+    pub right: f32,
+    pub up: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+/// Scene change asked for with the bumpers.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SceneStep{
+    Previous,
+    Next,
+}
+
+#[derive(Default)]
+pub struct GamepadState{
+    previous: GamepadReading,
+    cruise: Option<GamepadMotion>,
+}
+
+impl GamepadState{
+    pub fn new() -> Self{
+        GamepadState::default()
+    }
+
+    /// Reads the motion for this poll and any scene step pressed since the last one.
+    pub fn update(&mut self, reading: GamepadReading, response: &StickResponse)
+        -> (GamepadMotion, Option<SceneStep>){
+        let previous = &self.previous;
+        let just_pressed = |index: usize| reading.pressed(index) && !previous.pressed(index);
+
+        let (right, back) = response.stick(reading.axis(AXIS_LEFT_X), reading.axis(AXIS_LEFT_Y));
+        let (yaw, down) = response.stick(reading.axis(AXIS_RIGHT_X), reading.axis(AXIS_RIGHT_Y));
+        let up = response.axis(reading.value(BUTTON_RIGHT_TRIGGER))
+            - response.axis(reading.value(BUTTON_LEFT_TRIGGER));
+        let motion = GamepadMotion{forward: -back, right, up, yaw, pitch: -down};
+
+        if just_pressed(BUTTON_START) {
+            self.cruise = match self.cruise {
+                Some(_) => None,
+                None => Some(motion),
+            };
+        }
+        let step = if just_pressed(BUTTON_LEFT_BUMPER) {
+            Some(SceneStep::Previous)
+        }else if just_pressed(BUTTON_RIGHT_BUMPER) {
+            Some(SceneStep::Next)
+        }else{
+            None
+        };
+
+        self.previous = reading;
+        (self.cruise.unwrap_or(motion), step)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    const EPSILON: f32 = 0.0001;
+
+    fn reading(axes: [f32; 4], pressed: &[usize]) -> GamepadReading{
+        GamepadReading{
+            axes: axes.to_vec(),
+            buttons: (0..17).map(|index| {
+                let down = pressed.contains(&index);
+                (down, if down { 1.0 } else { 0.0 })
+            }).collect(),
+        }
+    }
+
+    #[test]
+    fn dead_zone_and_curve(){
+        let response = StickResponse{dead_zone: 0.2, exponent: 2.0};
+        assert_eq!(response.stick(0.1, 0.1), (0.0, 0.0));
+        let (x, y) = response.stick(0.0, -1.0);
+        assert!(x.abs() < EPSILON && (y + 1.0).abs() < EPSILON);
+        // halfway through the live range is a quarter speed
+        assert!((response.axis(0.6) - 0.25).abs() < EPSILON);
+        assert!((response.axis(-0.6) + 0.25).abs() < EPSILON);
+    }
+
+    #[test]
+    fn sticks_and_triggers_map_to_motion(){
+        let mut state = GamepadState::new();
+        let response = StickResponse{dead_zone: 0.0, exponent: 1.0};
+        let (motion, step) = state.update(reading([0.0, -1.0, 1.0, 0.0], &[7]), &response);
+        assert_eq!(motion, GamepadMotion{forward: 1.0, right: 0.0, up: 1.0, yaw: 1.0, pitch: 0.0});
+        assert_eq!(step, None);
+    }
+
+    #[test]
+    fn bumpers_step_once_per_press(){
+        let mut state = GamepadState::new();
+        let response = StickResponse::default();
+        assert_eq!(state.update(reading([0.0; 4], &[5]), &response).1, Some(SceneStep::Next));
+        assert_eq!(state.update(reading([0.0; 4], &[5]), &response).1, None);
+        assert_eq!(state.update(reading([0.0; 4], &[]), &response).1, None);
+        assert_eq!(state.update(reading([0.0; 4], &[4]), &response).1, Some(SceneStep::Previous));
+    }
+
+    #[test]
+    fn cruise_keeps_motion_until_toggled(){
+        let mut state = GamepadState::new();
+        let response = StickResponse{dead_zone: 0.0, exponent: 1.0};
+        state.update(reading([0.0, -0.5, 0.0, 0.0], &[9]), &response);
+        let (motion, _) = state.update(reading([0.0; 4], &[]), &response);
+        assert!((motion.forward - 0.5).abs() < EPSILON);
+
+        state.update(reading([0.0; 4], &[9]), &response);
+        let (motion, _) = state.update(reading([0.0; 4], &[]), &response);
+        assert_eq!(motion, GamepadMotion::default());
+    }
+}
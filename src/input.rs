@@ -1,139 +1,357 @@
 use std::cell::RefCell;
 use std::rc::Rc;
 use wasm_bindgen::closure::Closure;
-use wasm_bindgen::{JsCast, JsValue};
-use web_sys::{HtmlCanvasElement, MouseEvent, KeyboardEvent};
-use crate::log;
+use wasm_bindgen::JsCast;
+use web_sys::{Element, Gamepad, GamepadButton, HtmlCanvasElement, MouseEvent, KeyboardEvent, PointerEvent,
+    WheelEvent};
+use crate::bindings::{Bindings, Modifiers};
+use crate::camera_path::CameraPath;
+use crate::clock::Clock;
+use crate::controls::{Controls, SceneRequest};
+use crate::error::RenderError;
+use crate::gamepad::GamepadReading;
+use crate::recording::{InputEvent, Recording};
+use crate::utils::Listeners;
+use crate::{log, log_warn};
 use crate::vec_lib::mat4::Mat4f;
 use crate::vec_lib::vec3::Vec3f;
-use crate::webgl_utils::camera::FPSCamera;
-
-#[derive(PartialEq, Eq, Copy, Clone)]
-enum Keys{
-    KeyW = 0,
-    KeyA,
-    KeyS,
-    KeyD,
-    KeySpace,
-    KeyLShift,
-}
+use crate::webgl_utils::camera::Projection;
+
+// Listens for input on the page and feeds it to `Controls` as `InputEvent`s.
 
 struct InputManagerContents{
-    camera: FPSCamera,
-    last_time: f64,
-    keys_down: [bool;6],
-    mode: u32,
+    controls: Controls,
+    canvas: HtmlCanvasElement,
+    // on-screen joystick overlay and its knob, shown once the canvas is touched
+    joystick_element: Option<(Element, Element)>,
 }
 
 pub struct InputManager{
-    contents: Rc<RefCell<InputManagerContents>>
+    contents: Rc<RefCell<InputManagerContents>>,
+    listeners: RefCell<Listeners>,
+    // text of the last finished recording
+    last_recording: RefCell<String>,
 }
 
-const MOVE_SPEED : f32 = 1.0;
-const ROT_SPEED : f32 = 0.01;
+/// Pixels per line for wheels that scroll by lines.
+const WHEEL_LINE_HEIGHT : f32 = 16.0;
+/// localStorage entry holding the user's key bindings, on top of the defaults.
+const BINDINGS_STORAGE_KEY: &str = "inputBindings";
 
-impl InputManager {
-    pub fn new(canvas: &HtmlCanvasElement, window: &web_sys::Window)
-        -> Result<Self, String> {
-        let time = js_sys::Date::now();
-        let camera = FPSCamera::new(
-            Vec3f::new(0.0, 1.0, 0.0),
-            Vec3f::new(0.0, 0.0, 1.0),
-            Vec3f::new(0.0, 1.0, 0.0),
-            45.0,
-            1280.0 / 960.0,
-            0.1,
-            1000.0,
-        );
+fn load_bindings(window: &web_sys::Window) -> Bindings{
+    let stored = window.local_storage().ok().flatten()
+        .and_then(|storage| storage.get_item(BINDINGS_STORAGE_KEY).ok().flatten());
+    match stored {
+        Some(config) => Bindings::with_overrides(&config).unwrap_or_else(|err| {
+            log_warn!("Ignoring saved key bindings: {}", err);
+            Bindings::default()
+        }),
+        None => Bindings::default(),
+    }
+}
+
+/// The first connected gamepad, if there is one.
+fn read_gamepad() -> Option<GamepadReading>{
+    let gamepads = web_sys::window()?.navigator().get_gamepads().ok()?;
+    let gamepad = gamepads.iter()
+        .filter_map(|gamepad| gamepad.dyn_into::<Gamepad>().ok())
+        .find(|gamepad| gamepad.connected())?;
 
+    let axes = gamepad.axes().iter()
+        .map(|axis| axis.as_f64().unwrap_or(0.0) as f32)
+        .collect();
+    let buttons = gamepad.buttons().iter()
+        .filter_map(|button| button.dyn_into::<GamepadButton>().ok())
+        .map(|button| (button.pressed(), button.value() as f32))
+        .collect();
+    Some(GamepadReading{axes, buttons})
+}
+
+fn modifiers(key_event: &KeyboardEvent) -> Modifiers{
+    Modifiers{
+        ctrl: key_event.ctrl_key(),
+        alt: key_event.alt_key(),
+        shift: key_event.shift_key(),
+        meta: key_event.meta_key(),
+    }
+}
+
+impl InputManager {
+    pub fn new(canvas: &HtmlCanvasElement, window: &web_sys::Window, clock: Rc<Clock>)
+        -> Result<Self, RenderError> {
         let cell = RefCell::new(InputManagerContents {
-            camera,
-            last_time: time,
-            keys_down: [false; 6],
-            mode: 1,
+            controls: Controls::new(clock, load_bindings(window)),
+            canvas: canvas.clone(),
+            joystick_element: window.document().and_then(|document| {
+                Some((document.get_element_by_id("touchJoystick")?,
+                      document.get_element_by_id("touchJoystickKnob")?))
+            }),
         });
         let rc = Rc::new(cell);
 
-        let input_manager = InputManager { contents: rc.clone() };
-
         let rc_closure1 = rc.clone();
         let rc_closure2 = rc.clone();
         let rc_closure3 = rc.clone();
         let rc_closure4 = rc.clone();
         let rc_closure5 = rc.clone();
+        let rc_closure6 = rc.clone();
+        let rc_closure7 = rc.clone();
+        let rc_closure8 = rc.clone();
+        let rc_closure9 = rc.clone();
+        let rc_closure10 = rc.clone();
+        let rc_closure11 = rc.clone();
 
-        let mouse_down_closure = Closure::<dyn FnMut(_)>::new(move |event: MouseEvent| {
+        let mut listeners = Listeners::new();
+        listeners.add(canvas, "mousedown", Closure::<dyn FnMut(_)>::new(move |event: MouseEvent| {
             (*rc_closure1.borrow_mut()).mouse_down(event);
-        });
-        let mouse_up_closure = Closure::<dyn FnMut(_)>::new(move |event: MouseEvent| {
+        }))?;
+        listeners.add(canvas, "mouseup", Closure::<dyn FnMut(_)>::new(move |event: MouseEvent| {
             (*rc_closure2.borrow_mut()).mouse_up(event);
-        });
-        let mouse_move_closure = Closure::<dyn FnMut(_)>::new(move |event: MouseEvent| {
+        }))?;
+        listeners.add(canvas, "mousemove", Closure::<dyn FnMut(_)>::new(move |event: MouseEvent| {
             (*rc_closure5.borrow_mut()).mouse_move(event);
-        });
-        let key_down_closure = Closure::<dyn FnMut(_)>::new(move |event: KeyboardEvent| {
+        }))?;
+        listeners.add(canvas, "pointerdown", Closure::<dyn FnMut(_)>::new(move |event: PointerEvent| {
+            (*rc_closure7.borrow_mut()).pointer_down(event);
+        }))?;
+        listeners.add(canvas, "pointermove", Closure::<dyn FnMut(_)>::new(move |event: PointerEvent| {
+            (*rc_closure8.borrow_mut()).pointer_move(event);
+        }))?;
+        listeners.add(canvas, "pointerup", Closure::<dyn FnMut(_)>::new(move |event: PointerEvent| {
+            (*rc_closure9.borrow_mut()).pointer_up(event);
+        }))?;
+        listeners.add(canvas, "pointercancel", Closure::<dyn FnMut(_)>::new(move |event: PointerEvent| {
+            (*rc_closure11.borrow_mut()).pointer_up(event);
+        }))?;
+        listeners.add(canvas, "wheel", Closure::<dyn FnMut(_)>::new(move |event: WheelEvent| {
+            (*rc_closure10.borrow_mut()).wheel(event);
+        }))?;
+        // right and middle drags pan, so the context menu would get in the way
+        listeners.add(canvas, "contextmenu", Closure::<dyn FnMut(_)>::new(move |event: MouseEvent| {
+            event.prevent_default();
+        }))?;
+        listeners.add(window, "keydown", Closure::<dyn FnMut(_)>::new(move |event: KeyboardEvent| {
             (*rc_closure3.borrow_mut()).key_down(event);
-        });
-        let key_up_closure = Closure::<dyn FnMut(_)>::new(move |event: KeyboardEvent| {
+        }))?;
+        listeners.add(window, "keyup", Closure::<dyn FnMut(_)>::new(move |event: KeyboardEvent| {
             (*rc_closure4.borrow_mut()).key_up(event);
-        });
-        let poll_input_closure = Closure::<dyn FnMut()>::new(move || {
-            (*rc.borrow_mut()).poll_keys();
-        });
-
-        (|| -> Result<(),JsValue>{
-        canvas.add_event_listener_with_callback("mousedown", mouse_down_closure.as_ref().unchecked_ref())?;
-        canvas.add_event_listener_with_callback("mouseup", mouse_up_closure.as_ref().unchecked_ref())?;
-        canvas.add_event_listener_with_callback("mousemove", mouse_move_closure.as_ref().unchecked_ref())?;
-        window.add_event_listener_with_callback("keydown", key_down_closure.as_ref().unchecked_ref())?;
-        window.add_event_listener_with_callback("keyup", key_up_closure.as_ref().unchecked_ref())
-        })().map_err(|_|->String{String::from("Add event listener failed.")})?;
-        window.set_interval_with_callback_and_timeout_and_arguments_0(
-        poll_input_closure.as_ref().unchecked_ref(), 4)
-        .map_err(|_| String::from("Input poll callback init failed."))?;
+        }))?;
+        if let Some(document) = window.document() {
+            listeners.add(&document, "pointerlockchange", Closure::<dyn FnMut()>::new(move || {
+                (*rc_closure6.borrow_mut()).pointer_lock_change();
+            }))?;
+        }
 
-        mouse_down_closure.forget();
-        mouse_up_closure.forget();
-        mouse_move_closure.forget();
-        key_down_closure.forget();
-        key_up_closure.forget();
-        poll_input_closure.forget();
+        Ok(InputManager{
+            contents: rc,
+            listeners: RefCell::new(listeners),
+            last_recording: RefCell::new(String::new()),
+        })
+    }
 
-        Ok(input_manager)
+    /// Stops listening for input, for when the renderer is torn down.
+    pub fn detach(&self){
+        self.listeners.borrow_mut().remove_all();
+        let contents = self.contents.borrow();
+        if contents.controls.pointer_locked() {
+            if let Some(document) = contents.canvas.owner_document() {
+                document.exit_pointer_lock();
+            }
+        }
     }
 
     pub fn proj_matrix(&self) -> Mat4f{
-        self.contents.borrow_mut().camera.proj_matrix()
+        self.contents.borrow().controls.proj_matrix()
     }
 
     pub fn view_matrix(&self) -> Mat4f{
-        self.contents.borrow_mut().camera.view_matrix()
+        self.contents.borrow().controls.view_matrix()
     }
 
     pub fn position(&self) -> Vec3f{
-        self.contents.borrow_mut().camera.position()
+        self.pose().0
+    }
+
+    /// Position, view direction and up vector of the camera.
+    pub fn pose(&self) -> (Vec3f, Vec3f, Vec3f){
+        self.contents.borrow().controls.pose()
+    }
+
+    /// Jumps the camera to a pose, stopping any path playback and leftover motion.
+    pub fn set_pose(&self, position: Vec3f, forward: Vec3f, up: Vec3f){
+        self.contents.borrow_mut().controls.set_pose(position, forward, up);
+    }
+
+    /// Vertical field of view in degrees.
+    pub fn fov(&self) -> f32{
+        self.contents.borrow().controls.projection().fov
+    }
+
+    pub fn set_fov(&self, fov: f32){
+        let mut contents = self.contents.borrow_mut();
+        let projection = Projection{fov, ..contents.controls.projection()};
+        contents.controls.set_projection(projection);
+    }
+
+    /// Moves the camera by the input since the last frame. Call once per frame, after the
+    /// clock has ticked.
+    pub fn update(&self){
+        let mut contents = self.contents.borrow_mut();
+        contents.update_joystick_element();
+        contents.controls.update(read_gamepad);
+    }
+
+    /// Checks the bindings, saves them for the next visit and applies them right away.
+    pub fn set_bindings(&self, window: &web_sys::Window, config: &str) -> Result<(), String>{
+        let bindings = Bindings::with_overrides(config)?;
+        let storage = window.local_storage().ok().flatten()
+            .ok_or(String::from("localStorage is unavailable."))?;
+        storage.set_item(BINDINGS_STORAGE_KEY, config)
+            .map_err(|_| String::from("Failed to save the bindings."))?;
+        self.contents.borrow_mut().controls.set_bindings(bindings);
+        Ok(())
+    }
+
+    /// Replaces the camera path, stopping it if it was playing.
+    pub fn set_path(&self, path: CameraPath){
+        self.contents.borrow_mut().controls.set_path(path);
+    }
+
+    /// The camera path in the format of `camera_path.rs`.
+    pub fn path_text(&self) -> String{
+        self.contents.borrow().controls.path().to_text()
+    }
+
+    pub fn play_path(&self){
+        self.contents.borrow_mut().controls.play_path();
+    }
+
+    pub fn stop_path(&self){
+        self.contents.borrow_mut().controls.stop_path();
+    }
+
+    /// Records input from here on. `view` is the encoded view the recording starts from.
+    pub fn start_recording(&self, view: String){
+        self.contents.borrow_mut().controls.start_recording(view);
+    }
+
+    /// Ends the recording and keeps it for `last_recording`.
+    pub fn stop_recording(&self){
+        if let Some(recording) = self.contents.borrow_mut().controls.stop_recording() {
+            log!("Recorded {} input events", recording.events.len());
+            *self.last_recording.borrow_mut() = recording.to_text();
+        }
+    }
+
+    /// The last finished input recording in the format of `recording.rs`.
+    pub fn last_recording(&self) -> String{
+        self.last_recording.borrow().clone()
     }
 
-    pub fn mode(&self) -> u32{
-        self.contents.borrow_mut().mode
+    /// Starts feeding a recording's events in place of live input. The caller restores the
+    /// recording's view and scene time.
+    pub fn start_replay(&self, recording: &Recording){
+        self.contents.borrow_mut().controls.start_replay(recording);
+    }
+
+    /// Replays the events that arrived before the next recorded frame and returns how long
+    /// that frame was, to step the clock by. `None` once the replay is over.
+    pub fn replay_frame(&self) -> Option<f64>{
+        self.contents.borrow_mut().controls.replay_frame()
+    }
+
+    /// Scene picked with the keyboard or gamepad since the last call.
+    pub fn take_scene_request(&self) -> Option<SceneRequest>{
+        self.contents.borrow_mut().controls.take_scene_request()
+    }
+
+    /// How many times the debug view key was pressed since the last call.
+    pub fn take_debug_view_cycles(&self) -> u32{
+        self.contents.borrow_mut().controls.take_debug_view_cycles()
     }
 }
 
 impl InputManagerContents{
     fn mouse_down(&mut self, mouse_event: MouseEvent){
         // log!("mouse down");
+        if self.controls.bindings().pointer_lock() && !self.controls.pointer_locked() {
+            self.canvas.request_pointer_lock();
+        }
     }
 
     fn mouse_move(&mut self, mouse_event: MouseEvent){
+        self.controls.live(InputEvent::MouseMove{
+            dx: mouse_event.movement_x() as f32,
+            dy: mouse_event.movement_y() as f32,
+            buttons: mouse_event.buttons(),
+        });
+    }
+
+    fn wheel(&mut self, wheel_event: WheelEvent){
+        wheel_event.prevent_default();
+        let pixels = match wheel_event.delta_mode() {
+            WheelEvent::DOM_DELTA_LINE => wheel_event.delta_y() as f32 * WHEEL_LINE_HEIGHT,
+            WheelEvent::DOM_DELTA_PAGE => wheel_event.delta_y() as f32 * self.canvas.client_height() as f32,
+            _ => wheel_event.delta_y() as f32,
+        };
+        self.controls.live(InputEvent::Wheel(pixels));
+    }
+
+    fn update_joystick_element(&mut self){
+        let height = self.joystick_element.as_ref().map(|_| self.canvas.client_height() as f32);
+        self.controls.set_joystick_canvas_height(height);
+        if let Some((joystick, knob)) = &self.joystick_element {
+            let class = if self.controls.joystick_zone().is_some() { "joystick visible" } else { "joystick" };
+            joystick.set_class_name(class);
+            let (x, y) = self.controls.joystick_offset();
+            let _ = knob.set_attribute("style", &format!("transform: translate({}px, {}px)", x, y));
+        }
+    }
 
-        let dx = mouse_event.movement_x() as f32;
-        let dy = mouse_event.movement_y() as f32;
+    /// Feeds a touch to the controls with the joystick where it's currently shown.
+    fn touch(&mut self, event: InputEvent){
+        self.update_joystick_element();
+        self.controls.live(event);
+        self.update_joystick_element();
+    }
 
-        if (mouse_event.buttons() & 0x1) == 1{
-            // log!("mouse move");
-            self.camera.rotate(&self.camera.up_initial(), -dx*ROT_SPEED);
-            self.camera.rotate(&self.camera.right(), -dy*ROT_SPEED);
+    // mice are handled by the mouse events, these only track touches and pens
+    fn pointer_down(&mut self, pointer_event: PointerEvent){
+        if pointer_event.pointer_type() == "mouse" {
+            return;
         }
+        let _ = self.canvas.set_pointer_capture(pointer_event.pointer_id());
+        self.touch(InputEvent::TouchDown{
+            id: pointer_event.pointer_id(),
+            x: pointer_event.offset_x() as f32,
+            y: pointer_event.offset_y() as f32,
+        });
+    }
+
+    fn pointer_move(&mut self, pointer_event: PointerEvent){
+        if pointer_event.pointer_type() == "mouse" {
+            return;
+        }
+        self.touch(InputEvent::TouchMove{
+            id: pointer_event.pointer_id(),
+            x: pointer_event.offset_x() as f32,
+            y: pointer_event.offset_y() as f32,
+        });
+    }
+
+    fn pointer_up(&mut self, pointer_event: PointerEvent){
+        if pointer_event.pointer_type() == "mouse" {
+            return;
+        }
+        self.touch(InputEvent::TouchUp(pointer_event.pointer_id()));
+    }
+
+    fn pointer_lock_change(&mut self){
+        let locked = web_sys::window()
+            .and_then(|window| window.document())
+            .and_then(|document| document.pointer_lock_element());
+        let locked = locked.is_some_and(|element| element == **self.canvas);
+        self.controls.live(InputEvent::PointerLock(locked));
     }
 
     fn mouse_up(&mut self, mouse_event: MouseEvent){
@@ -142,96 +360,26 @@ impl InputManagerContents{
 
     fn key_down(&mut self, key_event: KeyboardEvent){
         // log!("key down");
-        let key = key_event.key().to_lowercase();
-
         if key_event.repeat(){
             return;
         }
 
-        match key.as_str() {
-            "w" =>{
-                self.keys_down[Keys::KeyW as usize] = true;
-            },
-            "a" =>{
-                self.keys_down[Keys::KeyA as usize] = true;
-            },
-            "s" =>{
-                self.keys_down[Keys::KeyS as usize] = true;
-            },
-            "d" =>{
-                self.keys_down[Keys::KeyD as usize] = true;
-            },
-            "shift" =>{
-                self.keys_down[Keys::KeyLShift as usize] = true;
-            }
-            " " =>{
-                self.keys_down[Keys::KeySpace as usize] = true;
-                key_event.prevent_default();
-            },
-            "1" =>{
-                self.mode = 1;
-            }
-            "2" =>{
-                self.mode = 2;
-            }
-            _ =>{
-                log!("Pressed key '{}'", key);
+        let code = key_event.code();
+        // browsers release the lock on Esc themselves, this covers the ones that pass the key through
+        if code == "Escape" && self.controls.pointer_locked() {
+            if let Some(document) = web_sys::window().and_then(|window| window.document()) {
+                document.exit_pointer_lock();
             }
-        };
+            return;
+        }
+
+        if self.controls.live(InputEvent::KeyDown(code, modifiers(&key_event))) {
+            key_event.prevent_default();
+        }
     }
 
     fn key_up(&mut self, key_event: KeyboardEvent){
         // log!("key up");
-        let key = key_event.key().to_lowercase();
-        match key.as_str() {
-            "w" =>{
-                self.keys_down[Keys::KeyW as usize] = false;
-            },
-            "a" =>{
-                self.keys_down[Keys::KeyA as usize] = false;
-            },
-            "s" =>{
-                self.keys_down[Keys::KeyS as usize] = false;
-            },
-            "d" =>{
-                self.keys_down[Keys::KeyD as usize] = false;
-            },
-            "shift" =>{
-                self.keys_down[Keys::KeyLShift as usize] = false;
-            }
-            " " =>{
-                self.keys_down[Keys::KeySpace as usize] = false;
-            },
-            _ =>{
-                log!("Pressed key '{}'", key);
-            }
-        };
-    }
-
-    fn poll_keys(&mut self){
-        let time = js_sys::Date::now();
-        let time_delta = time - self.last_time;
-        let delta_mod = f32::min(time_delta as f32, 1.0) / 30.0;
-        self.last_time = time;
-        // log!("{}", delta_mod);
-
-        if self.keys_down[Keys::KeyW as usize]{
-            self.camera.translate(&self.camera.forward().scale(delta_mod * MOVE_SPEED));
-        }
-        if self.keys_down[Keys::KeyA as usize]{
-            self.camera.translate(&self.camera.right().scale(-delta_mod * MOVE_SPEED));
-        }
-        if self.keys_down[Keys::KeyS as usize]{
-            self.camera.translate(&self.camera.forward().scale(-delta_mod * MOVE_SPEED));
-        }
-        if self.keys_down[Keys::KeyD as usize]{
-            self.camera.translate(&self.camera.right().scale(delta_mod * MOVE_SPEED));
-        }
-        if self.keys_down[Keys::KeyLShift as usize]{
-            self.camera.translate(&self.camera.up().scale(-delta_mod * MOVE_SPEED));
-        }
-        if self.keys_down[Keys::KeySpace as usize]{
-            self.camera.translate(&self.camera.up().scale(delta_mod * MOVE_SPEED));
-        }
+        self.controls.live(InputEvent::KeyUp(key_event.code()));
     }
-}
\ No newline at end of file
+}
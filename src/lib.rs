@@ -1,20 +1,51 @@
-use std::rc::Rc;
-use js_sys::Object;
-use wasm_bindgen::prelude::*;
-use web_sys::WebGl2RenderingContext;
-use std::cell::RefCell;
-use crate::app::TestApp;
-
-mod shaders;
+// The core below is plain Rust and builds and tests natively with `--no-default-features`.
+// Everything that touches the page or WebGL is behind the `browser` feature.
+pub mod logging;
+pub mod clock;
+pub mod shaders;
+pub mod noise;
+pub mod blue_noise;
+pub mod sky;
+pub mod clouds;
+pub mod fractal;
+pub mod bindings;
+pub mod gamepad;
+pub mod touch;
+pub mod camera_path;
+pub mod motion;
+pub mod controls;
+pub mod scene_list;
+pub mod view_state;
+pub mod recording;
+pub mod error;
+pub mod params;
+pub mod debug_view;
+
+#[cfg(feature = "browser")]
 mod utils;
+#[cfg(feature = "browser")]
 mod app;
+#[cfg(feature = "browser")]
 mod input;
+#[cfg(feature = "browser")]
 mod render_passes;
-
-mod webgl_utils{
-    pub mod render_pass;
-    pub mod utils;
+#[cfg(feature = "browser")]
+mod scenes;
+#[cfg(feature = "browser")]
+mod renderer;
+#[cfg(feature = "browser")]
+mod param_panel;
+#[cfg(feature = "browser")]
+mod api;
+
+pub mod webgl_utils{
     pub mod camera;
+    #[cfg(feature = "browser")]
+    pub(crate) mod render_pass;
+    #[cfg(feature = "browser")]
+    pub(crate) mod utils;
+    #[cfg(feature = "browser")]
+    pub(crate) mod capabilities;
 }
 
 pub mod vec_lib{
@@ -38,52 +69,3 @@ pub mod vec_lib{
 #[cfg(feature = "wee_alloc")]
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
-
-fn window() -> web_sys::Window {
-    web_sys::window().expect("no global `window` exists")
-}
-
-fn request_animation_frame(f: &Closure<dyn FnMut()>) {
-    window()
-        .request_animation_frame(f.as_ref().unchecked_ref())
-        .expect("should register `requestAnimationFrame` OK");
-}
-
-// This is like the `main` function, except for JavaScript.
-#[wasm_bindgen(start)]
-pub fn main_js() -> Result<(), JsValue> {
-    // This provides better error messages in debug mode.
-    // It's disabled in release mode so it doesn't bloat up the file size.
-    #[cfg(debug_assertions)]
-    console_error_panic_hook::set_once();
-
-    let window : web_sys::Window = web_sys::window().ok_or(JsValue::null())?;
-    let document = window.document().ok_or(JsValue::null())?;
-    let canvas_element = document.get_element_by_id("glCanvas")
-        .ok_or(JsValue::null())?;
-    let canvas = canvas_element.dyn_into::<web_sys::HtmlCanvasElement>()?;
-    let context = canvas.get_context("webgl2")
-        .and_then(|res| res.ok_or(JsValue::null()))
-        .and_then(|obj: Object|
-            obj.dyn_into::<WebGl2RenderingContext>().map_err(|_| JsValue::null())
-    )?;
-
-    let f = Rc::new(RefCell::new(None));
-    let g = f.clone();
-
-    let app = TestApp::new(context, canvas, window)
-        .map_err(|err| -> JsValue{
-            log_error!("{}", err);
-            JsValue::null()
-    })?;
-
-    *g.borrow_mut() = Some(Closure::new(move || {
-        // log!("draw");
-        app.draw();
-        request_animation_frame(f.borrow().as_ref().unwrap());
-    }));
-
-    request_animation_frame(g.borrow().as_ref().unwrap());
-    // console::log_1(&JsValue::from_str("Hello world!"));
-    Ok(())
-}
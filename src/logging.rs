@@ -0,0 +1,98 @@
+use std::cell::Cell;
+
+// Backend of the `log!`, `log_warn!` and `log_error!` macros. Messages go to the browser console
+// in wasm builds with the `browser` feature and to stderr anywhere else, so the core logs the
+// same way in native tests. `set_backend` sends them somewhere else instead.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Level{
+    Info,
+    Warn,
+    Error,
+}
+
+pub type Backend = fn(Level, &str);
+
+thread_local! {
+    static BACKEND: Cell<Backend> = const { Cell::new(default_backend) };
+}
+
+#[cfg(all(feature = "browser", target_arch = "wasm32"))]
+fn default_backend(level: Level, message: &str){
+    let message = wasm_bindgen::JsValue::from_str(message);
+    match level {
+        Level::Info => web_sys::console::log_1(&message),
+        Level::Warn => web_sys::console::warn_1(&message),
+        Level::Error => web_sys::console::error_1(&message),
+    }
+}
+
+#[cfg(not(all(feature = "browser", target_arch = "wasm32")))]
+fn default_backend(level: Level, message: &str){
+    match level {
+        Level::Info => eprintln!("{}", message),
+        Level::Warn => eprintln!("warning: {}", message),
+        Level::Error => eprintln!("error: {}", message),
+    }
+}
+
+/// Replaces where messages go on this thread, returning the previous backend.
+pub fn set_backend(backend: Backend) -> Backend{
+    BACKEND.with(|current| current.replace(backend))
+}
+
+pub fn write(level: Level, message: &str){
+    BACKEND.with(|backend| backend.get()(level, message));
+}
+
+#[macro_export]
+macro_rules! log {
+    ( $( $t:tt )* ) => {
+        $crate::logging::write($crate::logging::Level::Info, &format!( $( $t )* ));
+    }
+}
+
+#[macro_export]
+macro_rules! log_warn {
+    ( $( $t:tt )* ) => {
+        $crate::logging::write($crate::logging::Level::Warn, &format!( $( $t )* ));
+    }
+}
+
+#[macro_export]
+macro_rules! log_error {
+    ( $( $t:tt )* ) => {
+        $crate::logging::write($crate::logging::Level::Error, &format!( $( $t )* ));
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::cell::RefCell;
+
+    thread_local! {
+        static CAPTURED: RefCell<Vec<(Level, String)>> = const { RefCell::new(Vec::new()) };
+    }
+
+    fn capture(level: Level, message: &str){
+        CAPTURED.with(|captured| captured.borrow_mut().push((level, String::from(message))));
+    }
+
+    #[test]
+    fn macros_go_through_the_backend(){
+        let previous = set_backend(capture);
+        crate::log!("{} frames", 3);
+        crate::log_warn!("slow");
+        crate::log_error!("failed");
+        set_backend(previous);
+        crate::log!("not captured");
+
+        let captured = CAPTURED.with(|captured| captured.borrow().clone());
+        assert_eq!(captured, vec![
+            (Level::Info, String::from("3 frames")),
+            (Level::Warn, String::from("slow")),
+            (Level::Error, String::from("failed")),
+        ]);
+    }
+}
@@ -0,0 +1,72 @@
+use crate::vec_lib::vec3::Vec3f;
+
+/// How quickly the velocity catches up with the input, per second.
+const ACCELERATION: f32 = 10.0;
+/// How quickly the velocity dies down once the input is let go, per second.
+const DAMPING: f32 = 6.0;
+
+/// Camera movement with inertia. It speeds up towards the velocity the input asks for and coasts
+/// to a stop after. The blend is exponential in time, so it feels the same at any frame rate.
+pub struct Motion{
+    velocity: Vec3f,
+}
+
+impl Default for Motion{
+    fn default() -> Self{
+        Motion{velocity: Vec3f::new(0.0, 0.0, 0.0)}
+    }
+}
+
+impl Motion{
+    pub fn new() -> Self{
+        Motion::default()
+    }
+
+    /// Moves the velocity towards `wish` in units per second over `delta` seconds and returns
+    /// the distance travelled meanwhile.
+    pub fn step(&mut self, wish: &Vec3f, delta: f32) -> Vec3f{
+        let rate = if wish.squared_length() > 0.0 { ACCELERATION } else { DAMPING };
+        let blend = 1.0 - f32::exp(-rate * delta);
+        let start = self.velocity;
+        self.velocity = start + (wish - start).scale(blend);
+        (start + self.velocity).scale(0.5 * delta)
+    }
+
+    pub fn stop(&mut self){
+        self.velocity = Vec3f::new(0.0, 0.0, 0.0);
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    const EPSILON: f32 = 0.0001;
+
+    fn run(motion: &mut Motion, wish: &Vec3f, seconds: f32, frames: usize) -> f32{
+        (0..frames).map(|_| motion.step(wish, seconds / frames as f32).length()).sum()
+    }
+
+    #[test]
+    fn accelerates_then_coasts_to_a_stop(){
+        let mut motion = Motion::new();
+        let wish = Vec3f::new(0.0, 0.0, 8.0);
+        let first = motion.step(&wish, 1.0 / 60.0).length();
+        assert!(first > 0.0 && first < 8.0 / 60.0);
+
+        run(&mut motion, &wish, 2.0, 120);
+        assert!((motion.velocity.length() - 8.0).abs() < EPSILON);
+
+        let coast = run(&mut motion, &Vec3f::new(0.0, 0.0, 0.0), 3.0, 180);
+        assert!(coast > 0.0);
+        assert!(motion.velocity.length() < 0.01);
+    }
+
+    #[test]
+    fn distance_barely_depends_on_frame_rate(){
+        let wish = Vec3f::new(3.0, 0.0, 0.0);
+        let slow = run(&mut Motion::new(), &wish, 1.0, 20);
+        let fast = run(&mut Motion::new(), &wish, 1.0, 240);
+        assert!((slow - fast).abs() / fast < 0.01);
+    }
+}
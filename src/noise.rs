@@ -0,0 +1,258 @@
+use crate::vec_lib::vec3::Vec3f;
+
+// Tileable gradient and cellular noise baked into volume textures on the CPU. Every function takes
+// a position in lattice units and a period in cells, and wraps the lattice at that period so a
+// volume sampled over [0, period) repeats seamlessly.
+
+// 12 edge midpoints of a cube, from Ken Perlin's improved noise
+const GRADIENTS: [[f32; 3]; 12] = [
+    [1.0, 1.0, 0.0], [-1.0, 1.0, 0.0], [1.0, -1.0, 0.0], [-1.0, -1.0, 0.0],
+    [1.0, 0.0, 1.0], [-1.0, 0.0, 1.0], [1.0, 0.0, -1.0], [-1.0, 0.0, -1.0],
+    [0.0, 1.0, 1.0], [0.0, -1.0, 1.0], [0.0, 1.0, -1.0], [0.0, -1.0, -1.0],
+];
+
+/// Integer hash of a lattice point, based on the lowbias32 finalizer.
+pub fn hash(x: u32, y: u32, z: u32, seed: u32) -> u32{
+    let mut h = seed
+        ^ x.wrapping_mul(0x8da6_b343)
+        ^ y.wrapping_mul(0xd816_3841)
+        ^ z.wrapping_mul(0xcb1a_b31f);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7feb_352d);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846c_a68b);
+    h ^= h >> 16;
+    h
+}
+
+fn hash_unit(x: u32, y: u32, z: u32, seed: u32) -> f32{
+    (hash(x, y, z, seed) >> 8) as f32 / (1u32 << 24) as f32
+}
+
+fn wrap(i: i32, period: u32) -> u32{
+    i.rem_euclid(period as i32) as u32
+}
+
+fn fade(t: f32) -> f32{
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32{
+    a + (b - a) * t
+}
+
+/// Gradient noise in roughly [-1, 1].
+pub fn perlin(pos: &Vec3f, period: u32, seed: u32) -> f32{
+    let cell = [pos.x().floor(), pos.y().floor(), pos.z().floor()];
+    let frac = [pos.x() - cell[0], pos.y() - cell[1], pos.z() - cell[2]];
+    let cell = [cell[0] as i32, cell[1] as i32, cell[2] as i32];
+
+    let mut corners = [0.0f32; 8];
+    for (i, corner) in corners.iter_mut().enumerate(){
+        let offset = [(i & 1) as i32, ((i >> 1) & 1) as i32, ((i >> 2) & 1) as i32];
+        let gradient = GRADIENTS[hash(
+            wrap(cell[0] + offset[0], period),
+            wrap(cell[1] + offset[1], period),
+            wrap(cell[2] + offset[2], period),
+            seed
+        ) as usize % GRADIENTS.len()];
+        *corner = gradient[0] * (frac[0] - offset[0] as f32)
+            + gradient[1] * (frac[1] - offset[1] as f32)
+            + gradient[2] * (frac[2] - offset[2] as f32);
+    }
+
+    let (u, v, w) = (fade(frac[0]), fade(frac[1]), fade(frac[2]));
+    let x0 = lerp(corners[0], corners[1], u);
+    let x1 = lerp(corners[2], corners[3], u);
+    let x2 = lerp(corners[4], corners[5], u);
+    let x3 = lerp(corners[6], corners[7], u);
+    lerp(lerp(x0, x1, v), lerp(x2, x3, v), w)
+}
+
+/// One jittered feature point per cell, precomputed so sampling only has to measure distances.
+pub struct WorleyGrid{
+    period: u32,
+    points: Vec<Vec3f>,
+}
+
+impl WorleyGrid{
+    pub fn new(period: u32, seed: u32) -> Self{
+        let mut points = Vec::with_capacity((period * period * period) as usize);
+        for z in 0..period{
+            for y in 0..period{
+                for x in 0..period{
+                    points.push(Vec3f::new(
+                        hash_unit(x, y, z, seed),
+                        hash_unit(x, y, z, seed ^ 0x68e3_1da4),
+                        hash_unit(x, y, z, seed ^ 0xb529_7a4d),
+                    ));
+                }
+            }
+        }
+        WorleyGrid{period, points}
+    }
+
+    /// Distance to the closest feature point in cell units, clamped to [0, 1].
+    pub fn sample(&self, pos: &Vec3f) -> f32{
+        let cell = [pos.x().floor() as i32, pos.y().floor() as i32, pos.z().floor() as i32];
+        let mut min_dist = f32::MAX;
+        for dz in -1..=1{
+            for dy in -1..=1{
+                for dx in -1..=1{
+                    let neighbour = [cell[0] + dx, cell[1] + dy, cell[2] + dz];
+                    let index = wrap(neighbour[0], self.period)
+                        + wrap(neighbour[1], self.period) * self.period
+                        + wrap(neighbour[2], self.period) * self.period * self.period;
+                    let point = self.points[index as usize]
+                        + Vec3f::new(neighbour[0] as f32, neighbour[1] as f32, neighbour[2] as f32);
+                    min_dist = f32::min(min_dist, (point - pos).squared_length());
+                }
+            }
+        }
+        f32::min(min_dist.sqrt(), 1.0)
+    }
+}
+
+/// Sums octaves of `noise`, doubling the frequency and period and halving the amplitude each
+/// octave so the result tiles at the base period. The result is normalized by the total amplitude.
+pub fn fbm<F: Fn(&Vec3f, u32, u32) -> f32>(noise: F, pos: &Vec3f, period: u32, octaves: u32, seed: u32)
+    -> f32{
+    let mut sum = 0.0;
+    let mut amplitude = 1.0;
+    let mut total = 0.0;
+    for octave in 0..octaves{
+        let scale = (1u32 << octave) as f32;
+        sum += amplitude * noise(&pos.scale(scale), period << octave, seed.wrapping_add(octave));
+        total += amplitude;
+        amplitude *= 0.5;
+    }
+    sum / total
+}
+
+/// Bakes a tileable `size`³ volume with one byte per channel. Each channel function receives the
+/// voxel center in [0, 1)³ and should return a value in [0, 1].
+pub fn bake_volume(size: usize, channels: &[&dyn Fn(&Vec3f) -> f32]) -> Vec<u8>{
+    let mut data = Vec::with_capacity(size * size * size * channels.len());
+    let inv_size = 1.0 / size as f32;
+    for z in 0..size{
+        for y in 0..size{
+            for x in 0..size{
+                let pos = Vec3f::new(
+                    (x as f32 + 0.5) * inv_size,
+                    (y as f32 + 0.5) * inv_size,
+                    (z as f32 + 0.5) * inv_size,
+                );
+                for channel in channels{
+                    data.push((channel(&pos).clamp(0.0, 1.0) * 255.0).round() as u8);
+                }
+            }
+        }
+    }
+    data
+}
+
+/// Size of the cloud noise volume on each axis.
+pub const CLOUD_NOISE_SIZE: usize = 64;
+const CLOUD_NOISE_SEED: u32 = 0x2c1b_3c6d;
+
+/// RGBA cloud volume: red is Perlin fbm for the cloud shapes, green to alpha are inverted Worley
+/// noise at 4, 8 and 16 cells per tile, which the shader combines into an fbm to erode the shapes.
+pub fn bake_cloud_noise(size: usize) -> Vec<u8>{
+    let seed = CLOUD_NOISE_SEED;
+    let worley_low = WorleyGrid::new(4, seed + 1);
+    let worley_mid = WorleyGrid::new(8, seed + 2);
+    let worley_high = WorleyGrid::new(16, seed + 3);
+
+    bake_volume(size, &[
+        &|pos| fbm(perlin, &pos.scale(4.0), 4, 4, seed) * 0.5 + 0.5,
+        &|pos| 1.0 - worley_low.sample(&pos.scale(4.0)),
+        &|pos| 1.0 - worley_mid.sample(&pos.scale(8.0)),
+        &|pos| 1.0 - worley_high.sample(&pos.scale(16.0)),
+    ])
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    const EPSILON: f32 = 0.00001;
+
+    #[test]
+    fn hash_is_deterministic(){
+        assert_eq!(hash(1, 2, 3, 4), hash(1, 2, 3, 4));
+        assert_ne!(hash(1, 2, 3, 4), hash(1, 2, 3, 5));
+        assert_ne!(hash(1, 2, 3, 4), hash(2, 1, 3, 4));
+    }
+
+    #[test]
+    fn perlin_is_zero_on_lattice(){
+        for i in 0..8{
+            let pos = Vec3f::new(i as f32, (i * 3) as f32, (i * 5) as f32);
+            assert!(perlin(&pos, 8, 7).abs() < EPSILON);
+        }
+    }
+
+    #[test]
+    fn perlin_tiles(){
+        let period = 5;
+        for i in 0..50{
+            let pos = Vec3f::new(i as f32 * 0.37, i as f32 * 0.61, i as f32 * 0.13);
+            let shifted = pos + Vec3f::new(period as f32, -(period as f32), 2.0 * period as f32);
+            assert!((perlin(&pos, period, 1) - perlin(&shifted, period, 1)).abs() < 0.0001);
+        }
+    }
+
+    #[test]
+    fn perlin_in_range(){
+        for i in 0..500{
+            let pos = Vec3f::new(i as f32 * 0.173, i as f32 * 0.071, i as f32 * 0.293);
+            let val = perlin(&pos, 16, 3);
+            assert!((-1.0..=1.0).contains(&val));
+        }
+    }
+
+    #[test]
+    fn worley_tiles_and_is_in_range(){
+        let period = 4;
+        let grid = WorleyGrid::new(period, 9);
+        for i in 0..50{
+            let pos = Vec3f::new(i as f32 * 0.29, i as f32 * 0.53, i as f32 * 0.11);
+            let shifted = pos + Vec3f::new(period as f32, period as f32, -(period as f32));
+            let val = grid.sample(&pos);
+            assert!((0.0..=1.0).contains(&val));
+            assert!((val - grid.sample(&shifted)).abs() < 0.0001);
+        }
+    }
+
+    #[test]
+    fn worley_is_zero_at_feature_points(){
+        let grid = WorleyGrid::new(4, 2);
+        let cell = Vec3f::new(1.0, 2.0, 3.0);
+        let point = grid.points[1 + 2 * 4 + 3 * 16] + cell;
+        assert!(grid.sample(&point) < EPSILON);
+    }
+
+    #[test]
+    fn fbm_tiles_at_base_period(){
+        for i in 0..20{
+            let pos = Vec3f::new(i as f32 * 0.41, i as f32 * 0.23, i as f32 * 0.67);
+            let shifted = pos + Vec3f::new(3.0, 0.0, 3.0);
+            let val = fbm(perlin, &pos, 3, 4, 11);
+            assert!((val - fbm(perlin, &shifted, 3, 4, 11)).abs() < 0.0001);
+        }
+    }
+
+    #[test]
+    fn baked_volume_is_deterministic(){
+        let size = 8;
+        let volume = bake_cloud_noise(size);
+        assert_eq!(volume.len(), size * size * size * 4);
+        assert_eq!(volume, bake_cloud_noise(size));
+    }
+
+    #[test]
+    fn bake_volume_orders_channels_per_voxel(){
+        let volume = bake_volume(2, &[&|pos| pos.x(), &|_| 1.0]);
+        assert_eq!(volume, vec![64, 255, 191, 255, 64, 255, 191, 255, 64, 255, 191, 255, 64, 255, 191, 255]);
+    }
+}
@@ -0,0 +1,161 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::JsCast;
+use web_sys::{Document, Element, HtmlInputElement};
+use crate::error::RenderError;
+use crate::params::{ParamKind, ParamValue, Tunable};
+use crate::utils::Listeners;
+
+/// Steps a float slider is divided into.
+const FLOAT_SLIDER_STEPS: f32 = 200.0;
+
+struct Control{
+    tunable: Rc<dyn Tunable>,
+    index: usize,
+    input: HtmlInputElement,
+    output: Element,
+    // value the control shows, so changes made elsewhere can be picked up
+    shown: Option<ParamValue>,
+}
+
+/// A panel next to the canvas with a control for each param of the passes being drawn. It's
+/// rebuilt when the passes change and follows changes made from JS or the URL.
+pub struct ParamPanel{
+    document: Document,
+    root: Element,
+    groups: RefCell<Vec<&'static str>>,
+    controls: RefCell<Vec<Control>>,
+    listeners: RefCell<Listeners>,
+    changed: Rc<Cell<bool>>,
+}
+
+fn create(document: &Document, tag: &str, parent: &Element) -> Result<Element, RenderError>{
+    let element = document.create_element(tag)
+        .map_err(|_| RenderError::ResourceCreation(format!("a '{}' element", tag)))?;
+    parent.append_child(&element)
+        .map_err(|_| RenderError::ResourceCreation(format!("a '{}' element in the page", tag)))?;
+    Ok(element)
+}
+
+fn set_attributes(element: &Element, attributes: &[(&str, String)]) -> Result<(), RenderError>{
+    for (name, value) in attributes{
+        element.set_attribute(name, value)
+            .map_err(|_| RenderError::ResourceCreation(format!("attribute '{}'", name)))?;
+    }
+    Ok(())
+}
+
+impl ParamPanel{
+    /// Adds an empty panel after `anchor`.
+    pub fn new(anchor: &Element) -> Result<Self, RenderError>{
+        let document = anchor.owner_document()
+            .ok_or(RenderError::ResourceCreation(String::from("the params panel outside of a document")))?;
+        let root = document.create_element("div")
+            .map_err(|_| RenderError::ResourceCreation(String::from("the params panel")))?;
+        root.set_class_name("card param-panel");
+        anchor.insert_adjacent_element("afterend", &root)
+            .map_err(|_| RenderError::ResourceCreation(String::from("the params panel in the page")))?;
+        Ok(ParamPanel{
+            document,
+            root,
+            groups: RefCell::new(Vec::new()),
+            controls: RefCell::new(Vec::new()),
+            listeners: RefCell::new(Listeners::new()),
+            changed: Rc::new(Cell::new(false)),
+        })
+    }
+
+    /// Whether a param was changed from the panel since the last call.
+    pub fn take_changed(&self) -> bool{
+        self.changed.replace(false)
+    }
+
+    /// Shows the params of `tunables`, rebuilding the controls if they belong to other passes.
+    pub fn update(&self, tunables: &[Rc<dyn Tunable>]) -> Result<(), RenderError>{
+        let groups: Vec<&'static str> = tunables.iter().map(|tunable| tunable.group()).collect();
+        if groups != *self.groups.borrow() {
+            self.rebuild(tunables)?;
+            *self.groups.borrow_mut() = groups;
+        }
+
+        for control in self.controls.borrow_mut().iter_mut(){
+            let value = control.tunable.get(control.index);
+            if control.shown == Some(value) {
+                continue;
+            }
+            match value {
+                ParamValue::Toggle(checked) => control.input.set_checked(checked),
+                _ => control.input.set_value(&value.to_text()),
+            }
+            control.output.set_text_content(Some(&value.to_text()));
+            control.shown = Some(value);
+        }
+        Ok(())
+    }
+
+    fn rebuild(&self, tunables: &[Rc<dyn Tunable>]) -> Result<(), RenderError>{
+        self.listeners.borrow_mut().remove_all();
+        self.controls.borrow_mut().clear();
+        self.root.set_inner_html("");
+
+        for tunable in tunables{
+            let fieldset = create(&self.document, "fieldset", &self.root)?;
+            create(&self.document, "legend", &fieldset)?.set_text_content(Some(tunable.group()));
+            for (index, param) in tunable.params().iter().enumerate(){
+                let label = create(&self.document, "label", &fieldset)?;
+                create(&self.document, "span", &label)?.set_text_content(Some(param.label));
+                let input = create(&self.document, "input", &label)?;
+                let attributes = match param.kind {
+                    ParamKind::Float{min, max} => vec![("type", String::from("range")),
+                        ("min", min.to_string()), ("max", max.to_string()),
+                        ("step", ((max - min) / FLOAT_SLIDER_STEPS).to_string())],
+                    ParamKind::Int{min, max} => vec![("type", String::from("range")),
+                        ("min", min.to_string()), ("max", max.to_string()), ("step", String::from("1"))],
+                    ParamKind::Color => vec![("type", String::from("color"))],
+                    ParamKind::Toggle => vec![("type", String::from("checkbox"))],
+                    ParamKind::Vec3{..} => vec![("type", String::from("text")), ("size", String::from("12"))],
+                };
+                set_attributes(&input, &attributes)?;
+                let output = create(&self.document, "output", &label)?;
+                let input: HtmlInputElement = input.dyn_into()
+                    .map_err(|_| RenderError::ResourceCreation(String::from("an input element")))?;
+
+                // text fields apply once they're committed, applying each keystroke would rewrite
+                // the text under the caret whenever it parses
+                let event = match param.kind {
+                    ParamKind::Vec3{..} => "change",
+                    _ => "input",
+                };
+                let (closure_tunable, closure_input) = (tunable.clone(), input.clone());
+                let changed = self.changed.clone();
+                self.listeners.borrow_mut().add(&input, event, Closure::<dyn FnMut()>::new(move || {
+                    let param = &closure_tunable.params()[index];
+                    let text = match param.kind {
+                        ParamKind::Toggle => closure_input.checked().to_string(),
+                        _ => closure_input.value(),
+                    };
+                    if let Some(value) = param.parse(&text) {
+                        closure_tunable.set(index, value);
+                        changed.set(true);
+                    }
+                }))?;
+
+                self.controls.borrow_mut().push(Control{
+                    tunable: tunable.clone(),
+                    index,
+                    input,
+                    output,
+                    shown: None,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes the panel out of the page.
+    pub fn remove(&self){
+        self.listeners.borrow_mut().remove_all();
+        self.root.remove();
+    }
+}
@@ -0,0 +1,262 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// Tunable parameters of the passes. Each pass declares a list of `ParamInfo` next to its
+// uniforms, which is enough to build controls for them, check values coming from JS and save
+// them. Values are addressed as `group.name` in snake_case, like `fractal.menger_iterations` or
+// `sun.day_of_year`, and saved as text, one per line:
+//
+//     fractal.exponent = 8
+//     fractal.albedo = #ffcc88
+//     fractal.shadows = false
+//     fractal.bulb_pos = 11, 0, 11
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ParamKind{
+    Float{min: f32, max: f32},
+    Int{min: i32, max: i32},
+    /// Linear RGB in [0, 1], written as `#rrggbb`.
+    Color,
+    Toggle,
+    /// Three floats each clamped to the range, written as `x, y, z`.
+    Vec3{min: f32, max: f32},
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ParamValue{
+    Float(f32),
+    Int(i32),
+    Color([f32; 3]),
+    Toggle(bool),
+    Vec3([f32; 3]),
+}
+
+pub struct ParamInfo{
+    /// Name within the group in snake_case. Params stored in a `ParamStore` are bound to the
+    /// uniform of the same name in camelCase, see `uniform_name`.
+    pub name: &'static str,
+    pub label: &'static str,
+    pub kind: ParamKind,
+    pub default: ParamValue,
+}
+
+fn color_to_hex(color: &[f32; 3]) -> String{
+    let byte = |channel: f32| (channel.clamp(0.0, 1.0) * 255.0).round() as u8;
+    format!("#{:02x}{:02x}{:02x}", byte(color[0]), byte(color[1]), byte(color[2]))
+}
+
+fn color_from_hex(text: &str) -> Option<[f32; 3]>{
+    let digits = text.strip_prefix('#')?;
+    if digits.len() != 6 || !digits.is_ascii() {
+        return None;
+    }
+    let channel = |index: usize| u8::from_str_radix(&digits[index * 2..index * 2 + 2], 16).ok()
+        .map(|byte| byte as f32 / 255.0);
+    Some([channel(0)?, channel(1)?, channel(2)?])
+}
+
+impl ParamValue{
+    pub fn to_text(self) -> String{
+        match self {
+            ParamValue::Float(value) => value.to_string(),
+            ParamValue::Int(value) => value.to_string(),
+            ParamValue::Color(color) => color_to_hex(&color),
+            ParamValue::Toggle(value) => value.to_string(),
+            ParamValue::Vec3([x, y, z]) => format!("{}, {}, {}", x, y, z),
+        }
+    }
+}
+
+impl ParamInfo{
+    /// The name in camelCase like the shaders' uniforms, `menger_iterations` is `mengerIterations`.
+    pub fn uniform_name(&self) -> String{
+        let mut words = self.name.split('_');
+        let mut uniform = String::from(words.next().unwrap_or_default());
+        for word in words{
+            let mut chars = word.chars();
+            if let Some(first) = chars.next() {
+                uniform.extend(first.to_uppercase());
+                uniform.push_str(chars.as_str());
+            }
+        }
+        uniform
+    }
+
+    /// Reads a value of this param's kind, clamping it into range. Toggles also take 0 and 1, so
+    /// any number can be passed from a JS slider or checkbox.
+    pub fn parse(&self, text: &str) -> Option<ParamValue>{
+        let text = text.trim();
+        let value = match self.kind {
+            ParamKind::Float{min, max} => ParamValue::Float(
+                text.parse::<f32>().ok().filter(|value| value.is_finite())?.clamp(min, max)),
+            ParamKind::Int{min, max} => ParamValue::Int(
+                text.parse::<f64>().ok().filter(|value| value.is_finite())?.round().clamp(min as f64, max as f64) as i32),
+            ParamKind::Color => ParamValue::Color(color_from_hex(text)?),
+            ParamKind::Toggle => ParamValue::Toggle(match text {
+                "true" | "1" => true,
+                "false" | "0" => false,
+                _ => return None,
+            }),
+            ParamKind::Vec3{min, max} =>{
+                let components: Vec<f32> = text.split(',')
+                    .map(|component| component.trim().parse::<f32>().ok().filter(|value| value.is_finite()))
+                    .collect::<Option<_>>()?;
+                match components[..] {
+                    [x, y, z] => ParamValue::Vec3([x.clamp(min, max), y.clamp(min, max), z.clamp(min, max)]),
+                    _ => return None,
+                }
+            }
+        };
+        Some(value)
+    }
+}
+
+/// Something with params that can be read and changed by index into `params()`.
+pub trait Tunable{
+    fn group(&self) -> &'static str;
+    fn params(&self) -> &'static [ParamInfo];
+    fn get(&self, index: usize) -> ParamValue;
+    fn set(&self, index: usize, value: ParamValue);
+}
+
+/// Finds a param by its full `group.name`.
+pub fn find_param(tunables: &[Rc<dyn Tunable>], id: &str) -> Option<(Rc<dyn Tunable>, usize)>{
+    let (group, name) = id.split_once('.')?;
+    let tunable = tunables.iter().find(|tunable| tunable.group() == group)?;
+    let index = tunable.params().iter().position(|param| param.name == name)?;
+    Some((tunable.clone(), index))
+}
+
+/// Sets a param by its full `group.name` from text.
+pub fn set_param(tunables: &[Rc<dyn Tunable>], id: &str, text: &str) -> Result<(), String>{
+    let (tunable, index) = find_param(tunables, id).ok_or(format!("Unknown parameter '{}'", id))?;
+    let value = tunable.params()[index].parse(text)
+        .ok_or(format!("Invalid value '{}' for parameter '{}'", text, id))?;
+    tunable.set(index, value);
+    Ok(())
+}
+
+/// Every param that differs from its default, in the format at the top of this file.
+pub fn to_text(tunables: &[Rc<dyn Tunable>]) -> String{
+    let mut text = String::new();
+    for tunable in tunables{
+        for (index, param) in tunable.params().iter().enumerate(){
+            let value = tunable.get(index);
+            if value != param.default {
+                text.push_str(&format!("{}.{} = {}\n", tunable.group(), param.name, value.to_text()));
+            }
+        }
+    }
+    text
+}
+
+/// Applies saved params, skipping lines that don't apply anymore. Returns the skipped lines'
+/// errors, so they can be reported.
+pub fn apply_text(tunables: &[Rc<dyn Tunable>], text: &str) -> Vec<String>{
+    let mut errors = Vec::new();
+    for (number, line) in text.lines().enumerate(){
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let result = line.split_once('=')
+            .ok_or(String::from("expected 'name = value'"))
+            .and_then(|(id, value)| set_param(tunables, id.trim(), value.trim()));
+        if let Err(err) = result {
+            errors.push(format!("Line {}: {}", number + 1, err));
+        }
+    }
+    errors
+}
+
+/// Params kept in one place and handed to the shader as uniforms of the same names.
+pub struct ParamStore{
+    group: &'static str,
+    params: &'static [ParamInfo],
+    values: RefCell<Vec<ParamValue>>,
+}
+
+impl ParamStore{
+    pub fn new(group: &'static str, params: &'static [ParamInfo]) -> Self{
+        ParamStore{
+            group,
+            params,
+            values: RefCell::new(params.iter().map(|param| param.default).collect()),
+        }
+    }
+}
+
+impl Tunable for ParamStore{
+    fn group(&self) -> &'static str{
+        self.group
+    }
+
+    fn params(&self) -> &'static [ParamInfo]{
+        self.params
+    }
+
+    fn get(&self, index: usize) -> ParamValue{
+        self.values.borrow()[index]
+    }
+
+    fn set(&self, index: usize, value: ParamValue){
+        self.values.borrow_mut()[index] = value;
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    static PARAMS: [ParamInfo; 5] = [
+        ParamInfo{name: "exponent", label: "Exponent", kind: ParamKind::Float{min: 1.0, max: 16.0}, default: ParamValue::Float(8.0)},
+        ParamInfo{name: "steps", label: "Steps", kind: ParamKind::Int{min: 1, max: 10}, default: ParamValue::Int(4)},
+        ParamInfo{name: "tint", label: "Tint", kind: ParamKind::Color, default: ParamValue::Color([1.0, 1.0, 1.0])},
+        ParamInfo{name: "shadows", label: "Shadows", kind: ParamKind::Toggle, default: ParamValue::Toggle(true)},
+        ParamInfo{name: "offset", label: "Offset", kind: ParamKind::Vec3{min: -10.0, max: 10.0}, default: ParamValue::Vec3([0.0, 0.0, 0.0])},
+    ];
+
+    fn tunables() -> Vec<Rc<dyn Tunable>>{
+        vec![Rc::new(ParamStore::new("test", &PARAMS))]
+    }
+
+    #[test]
+    fn parses_and_clamps_each_kind(){
+        assert_eq!(PARAMS[0].parse("20"), Some(ParamValue::Float(16.0)));
+        assert_eq!(PARAMS[1].parse("2.6"), Some(ParamValue::Int(3)));
+        assert_eq!(PARAMS[2].parse("#ff8000"), Some(ParamValue::Color([1.0, 128.0 / 255.0, 0.0])));
+        assert_eq!(PARAMS[3].parse("0"), Some(ParamValue::Toggle(false)));
+        assert_eq!(PARAMS[0].parse("NaN"), None);
+        assert_eq!(PARAMS[2].parse("ff8000"), None);
+        assert_eq!(PARAMS[3].parse("yes"), None);
+        assert_eq!(PARAMS[4].parse(" 1.5,-20, 3 "), Some(ParamValue::Vec3([1.5, -10.0, 3.0])));
+        assert_eq!(PARAMS[4].parse("1, 2"), None);
+        assert_eq!(PARAMS[4].parse("1, 2, inf"), None);
+    }
+
+    #[test]
+    fn uniform_names_are_camel_case(){
+        assert_eq!(PARAMS[0].uniform_name(), "exponent");
+        let param = ParamInfo{name: "menger_iterations", label: "", kind: ParamKind::Toggle, default: ParamValue::Toggle(true)};
+        assert_eq!(param.uniform_name(), "mengerIterations");
+    }
+
+    #[test]
+    fn saved_text_round_trips(){
+        let saved = tunables();
+        set_param(&saved, "test.exponent", "3.5").unwrap();
+        set_param(&saved, "test.tint", "#336699").unwrap();
+        set_param(&saved, "test.shadows", "false").unwrap();
+        set_param(&saved, "test.offset", "0.25, -1, 7").unwrap();
+        assert!(set_param(&saved, "test.missing", "1").is_err());
+        assert!(set_param(&saved, "exponent", "1").is_err());
+
+        let text = to_text(&saved);
+        assert!(!text.contains("test.steps"));
+        let loaded = tunables();
+        assert!(apply_text(&loaded, &format!("{}other.thing = 1\n", text)).len() == 1);
+        for index in 0..PARAMS.len(){
+            assert_eq!(loaded[0].get(index), saved[0].get(index));
+        }
+    }
+}
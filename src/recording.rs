@@ -0,0 +1,217 @@
+use crate::bindings::{Bindings, Chord, Modifiers};
+use crate::gamepad::GamepadReading;
+
+// Raw input recorded with the frame it arrived in, so a session can be replayed exactly.
+// Replaying feeds the events through the same handlers as live input and steps the clock by the
+// recorded frame times, so the camera ends up in the same place down to the last bit.
+//
+// Recordings are saved as text. A header with the starting state is followed by one event per
+// line, and `frame` lines mark where each rendered frame started and how long it was:
+//
+//     view = v=1&scene=fractal&pos=0,1,0&fwd=0,0,1&up=0,1,0&fov=45&sun=15,40,80&clouds=...
+//     time = 12.5
+//     speed = 8
+//     paused = false
+//     time_scale = 1
+//     bindings.move_forward = KeyW, ArrowUp
+//     bindings.look.invert_y = false
+//     key_down = Shift+KeyW
+//     frame = 0.016666668
+//     gamepad = 0,0.5,0,0 | 0:0,1:0.75
+//     mouse = 3 -2 1
+//     key_up = KeyW
+//
+// `gamepad` is the pad as it was read during the frame before it, with its axes and then its
+// buttons as pressed:value pairs. Events are raw key codes, so the header keeps the full set of
+// bindings they were recorded with, one `bindings.` line each in the format of `bindings.rs`.
+// Recordings without them replay with whatever bindings are current.
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum InputEvent{
+    KeyDown(String, Modifiers),
+    KeyUp(String),
+    /// Movement in CSS pixels and the buttons held, as in `MouseEvent.buttons`.
+    MouseMove{dx: f32, dy: f32, buttons: u16},
+    /// Scrolled CSS pixels.
+    Wheel(f32),
+    TouchDown{id: i32, x: f32, y: f32},
+    TouchMove{id: i32, x: f32, y: f32},
+    TouchUp(i32),
+    PointerLock(bool),
+    Gamepad(GamepadReading),
+    /// Start of a frame that advanced the clock by this many seconds.
+    Frame(f64),
+}
+
+/// Everything needed to play a session back from the same starting point.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Recording{
+    /// View at the start, encoded like the URL hash.
+    pub view: String,
+    /// Scene time at the start.
+    pub time: f64,
+    /// Fly speed at the start.
+    pub speed: f32,
+    /// Whether scene time was paused, and how fast it ran.
+    pub paused: bool,
+    pub time_scale: f64,
+    /// Bindings the key events were made with.
+    pub bindings: Option<Bindings>,
+    pub events: Vec<InputEvent>,
+}
+
+fn parse_numbers<T: std::str::FromStr>(value: &str, separator: char) -> Option<Vec<T>>{
+    value.split(separator)
+        .map(|part| part.trim())
+        .filter(|part| !part.is_empty())
+        .map(|part| part.parse::<T>().ok())
+        .collect()
+}
+
+impl InputEvent{
+    fn to_line(&self) -> String{
+        match self {
+            InputEvent::KeyDown(code, modifiers) =>
+                format!("key_down = {}", Chord{code: code.clone(), modifiers: *modifiers}),
+            InputEvent::KeyUp(code) => format!("key_up = {}", code),
+            InputEvent::MouseMove{dx, dy, buttons} => format!("mouse = {} {} {}", dx, dy, buttons),
+            InputEvent::Wheel(pixels) => format!("wheel = {}", pixels),
+            InputEvent::TouchDown{id, x, y} => format!("touch_down = {} {} {}", id, x, y),
+            InputEvent::TouchMove{id, x, y} => format!("touch_move = {} {} {}", id, x, y),
+            InputEvent::TouchUp(id) => format!("touch_up = {}", id),
+            InputEvent::PointerLock(locked) => format!("pointer_lock = {}", locked),
+            InputEvent::Gamepad(reading) => {
+                let axes: Vec<String> = reading.axes.iter().map(|axis| axis.to_string()).collect();
+                let buttons: Vec<String> = reading.buttons.iter()
+                    .map(|(pressed, value)| format!("{}:{}", *pressed as u8, value))
+                    .collect();
+                format!("gamepad = {} | {}", axes.join(","), buttons.join(","))
+            }
+            InputEvent::Frame(step) => format!("frame = {}", step),
+        }
+    }
+
+    fn parse(name: &str, value: &str) -> Option<InputEvent>{
+        let event = match name {
+            "key_down" => {
+                let chord = Chord::parse(value).ok()?;
+                InputEvent::KeyDown(chord.code, chord.modifiers)
+            }
+            "key_up" => InputEvent::KeyUp(String::from(value)),
+            "mouse" => match parse_numbers::<f32>(value, ' ')?.as_slice() {
+                [dx, dy, buttons] => InputEvent::MouseMove{dx: *dx, dy: *dy, buttons: *buttons as u16},
+                _ => return None,
+            },
+            "wheel" => InputEvent::Wheel(value.parse().ok()?),
+            "touch_down" | "touch_move" => match parse_numbers::<f32>(value, ' ')?.as_slice() {
+                [id, x, y] if name == "touch_down" => InputEvent::TouchDown{id: *id as i32, x: *x, y: *y},
+                [id, x, y] => InputEvent::TouchMove{id: *id as i32, x: *x, y: *y},
+                _ => return None,
+            },
+            "touch_up" => InputEvent::TouchUp(value.parse().ok()?),
+            "pointer_lock" => InputEvent::PointerLock(value.parse().ok()?),
+            "gamepad" => {
+                let (axes, buttons) = value.split_once('|')?;
+                let buttons = buttons.split(',')
+                    .map(|button| button.trim())
+                    .filter(|button| !button.is_empty())
+                    .map(|button| {
+                        let (pressed, value) = button.split_once(':')?;
+                        Some((pressed == "1", value.parse::<f32>().ok()?))
+                    })
+                    .collect::<Option<Vec<(bool, f32)>>>()?;
+                InputEvent::Gamepad(GamepadReading{axes: parse_numbers::<f32>(axes, ',')?, buttons})
+            }
+            "frame" => InputEvent::Frame(value.parse().ok()?),
+            _ => return None,
+        };
+        Some(event)
+    }
+}
+
+impl Recording{
+    pub fn new(view: String, time: f64, speed: f32) -> Self{
+        Recording{view, time, speed, paused: false, time_scale: 1.0, bindings: None, events: Vec::new()}
+    }
+
+    pub fn to_text(&self) -> String{
+        let mut text = format!("view = {}\ntime = {}\nspeed = {}\npaused = {}\ntime_scale = {}\n", self.view,
+            self.time, self.speed, self.paused, self.time_scale);
+        if let Some(bindings) = &self.bindings {
+            for line in bindings.to_text().lines(){
+                text.push_str(&format!("bindings.{}\n", line));
+            }
+        }
+        for event in &self.events{
+            text.push_str(&event.to_line());
+            text.push('\n');
+        }
+        text
+    }
+
+    pub fn parse(text: &str) -> Result<Recording, String>{
+        let mut recording = Recording::new(String::new(), 0.0, 0.0);
+        let mut bindings = String::new();
+        for (number, line) in text.lines().enumerate(){
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) = line.split_once('=')
+                .ok_or(format!("Line {}: expected 'name = value'", number + 1))?;
+            let (name, value) = (name.trim(), value.trim());
+            let invalid = || format!("Line {}: invalid {} '{}'", number + 1, name, value);
+            match name {
+                "view" => recording.view = String::from(value),
+                "time" => recording.time = value.parse().map_err(|_| invalid())?,
+                "speed" => recording.speed = value.parse().map_err(|_| invalid())?,
+                "paused" => recording.paused = value.parse().map_err(|_| invalid())?,
+                "time_scale" => recording.time_scale = value.parse().map_err(|_| invalid())?,
+                _ => match name.strip_prefix("bindings.") {
+                    Some(binding) => bindings.push_str(&format!("{} = {}\n", binding, value)),
+                    None => recording.events.push(InputEvent::parse(name, value).ok_or_else(invalid)?),
+                },
+            }
+        }
+        if !bindings.is_empty() {
+            let bindings = Bindings::parse(&bindings).map_err(|err| format!("Invalid bindings: {}", err))?;
+            recording.bindings = Some(bindings);
+        }
+        Ok(recording)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn text_round_trips(){
+        let mut recording = Recording::new(String::from("v=1&scene=demo"), 12.5, 8.0);
+        recording.paused = true;
+        recording.time_scale = 0.25;
+        recording.bindings = Some(Bindings::with_overrides("move_forward = KeyZ\nlook.invert_y = true").unwrap());
+        recording.events = vec![
+            InputEvent::KeyDown(String::from("KeyW"), Modifiers{shift: true, ..Modifiers::default()}),
+            InputEvent::Frame(1.0 / 60.0),
+            InputEvent::Gamepad(GamepadReading{axes: vec![0.0, -0.25], buttons: vec![(false, 0.0), (true, 0.75)]}),
+            InputEvent::MouseMove{dx: 3.0, dy: -2.5, buttons: 1},
+            InputEvent::Wheel(-100.0),
+            InputEvent::TouchDown{id: 2, x: 10.5, y: 20.0},
+            InputEvent::TouchMove{id: 2, x: 11.0, y: 21.0},
+            InputEvent::TouchUp(2),
+            InputEvent::PointerLock(true),
+            InputEvent::KeyUp(String::from("KeyW")),
+            InputEvent::Gamepad(GamepadReading::default()),
+        ];
+        assert_eq!(Recording::parse(&recording.to_text()), Ok(recording));
+    }
+
+    #[test]
+    fn rejects_bad_events(){
+        assert!(Recording::parse("mouse = 1 2").is_err());
+        assert!(Recording::parse("jump = Space").is_err());
+        assert!(Recording::parse("gamepad = 0,1 | 1").is_err());
+        assert!(Recording::parse("bindings.fly = KeyF").is_err());
+    }
+}
@@ -1,13 +1,27 @@
+use std::cell::Cell;
 use std::rc::Rc;
 use js_sys::{Float32Array, Uint32Array};
 use wasm_bindgen::JsValue;
 use web_sys::{WebGl2RenderingContext, WebGlUniformLocation, WebGlFramebuffer, WebGlTexture,
     WebGlRenderbuffer};
+use crate::blue_noise::blue_noise;
+use crate::clock::Clock;
+use crate::clouds::CloudParams;
+use crate::debug_view::DebugView;
+use crate::error::RenderError;
+use crate::fractal::FRACTAL_SHAPE_PARAMS;
 use crate::input::InputManager;
-use crate::shaders::{CLOUD_FRAG_SHADER, DEMO_FRAG_SHADER, FRACTAL_FRAG_SHADER, FRAG_SHADER, PIXEL_VERT_SHADER, VERT_SHADER};
+use crate::noise::{bake_cloud_noise, CLOUD_NOISE_SIZE};
+use crate::params::{ParamInfo, ParamKind, ParamStore, ParamValue, Tunable};
+use crate::scenes::SceneContext;
+use crate::shaders::{with_defines, with_includes, CLOUD_COMPOSITE_FRAG_SHADER, CLOUD_FRAG_SHADER, CROSSFADE_FRAG_SHADER, DEMO_FRAG_SHADER, FRACTAL_FRAG_SHADER, FRAG_SHADER,
+    PIXEL_VERT_SHADER, SKY_FRAG_SHADER, VERT_SHADER};
+use crate::sky::{TimeOfDay, SUN_PARAMS};
 use crate::vec_lib::mat4;
 use crate::vec_lib::vec3::Vec3f;
+use crate::webgl_utils::capabilities::ColorBufferFormat;
 use crate::webgl_utils::render_pass::{RenderPass, RenderPassConfig, UniformProvider};
+use crate::webgl_utils::utils::{util_create_texture_3d, util_create_texture_r8};
 use web_sys::WebGl2RenderingContext as gl;
 
 
@@ -18,12 +32,14 @@ pub struct RasterRenderPass{
     framebuffer: WebGlFramebuffer,
     depth_buffer: WebGlRenderbuffer,
     color_texture: WebGlTexture,
+    distance_texture: Option<WebGlTexture>,
 }
 
 pub struct DemoRenderPass{
     ctx: WebGl2RenderingContext,
     render_pass: RenderPass,
     uniform_provider: Rc<FractalUniformProvider>,
+    prepass: ConePrepass,
 }
 
 pub struct FractalRenderPass{
@@ -31,26 +47,124 @@ pub struct FractalRenderPass{
     render_pass: RenderPass,
     uniform_provider: Rc<FractalUniformProvider>,
     framebuffer: WebGlFramebuffer,
+    color_format: ColorBufferFormat,
+    prepass: ConePrepass,
+}
+
+/// Marches cones a pixel wide at a fraction of the resolution, a marcher pass compiled with
+/// `CONE_PREPASS`. The full resolution rays start where their cone stopped instead of at the camera.
+pub struct ConePrepass{
+    ctx: WebGl2RenderingContext,
+    render_pass: RenderPass,
+    framebuffer: WebGlFramebuffer,
+    texture: WebGlTexture,
+    march_params: Rc<ParamStore>,
 }
 
 pub struct CloudRenderPass{
     ctx: WebGl2RenderingContext,
     render_pass: RenderPass,
-    uniform_provider: Rc<FractalUniformProvider>
+    composite_pass: RenderPass,
+    cloud_uniform_provider: Rc<CloudUniformProvider>,
+    framebuffer: WebGlFramebuffer,
+}
+
+/// Index of the `showClouds` uniform, after the ones for the fields of `CloudParams`.
+const SHOW_CLOUDS_UNIFORM: u32 = 5;
+
+struct CloudUniformProvider{
+    params: Rc<Cell<CloudParams>>,
+    // off while a debug view shows the scene underneath on its own
+    show_clouds: Cell<bool>,
+}
+
+/// Renders the sky-view lookup texture for the current sun, and shares the sun with the marcher
+/// passes so their lighting, shadows and clouds follow it.
+pub struct SkyRenderPass{
+    ctx: WebGl2RenderingContext,
+    render_pass: RenderPass,
+    uniform_provider: Rc<SkyUniformProvider>,
+    framebuffer: WebGlFramebuffer,
+    sky_texture: WebGlTexture,
+    rendered_time: Cell<Option<TimeOfDay>>,
+}
+
+struct SkyUniformProvider{
+    time_of_day: Cell<TimeOfDay>,
 }
 
+
+const SKY_LUT_WIDTH: i32 = 128;
+const SKY_LUT_HEIGHT: i32 = 64;
+
+/// Keeps a copy of the last frame of the previous scene and fades it out over the next one.
+pub struct CrossfadeRenderPass{
+    ctx: WebGl2RenderingContext,
+    render_pass: RenderPass,
+    uniform_provider: Rc<CrossfadeUniformProvider>,
+    framebuffer: WebGlFramebuffer,
+}
+
+struct CrossfadeUniformProvider{
+    opacity: Cell<f32>,
+}
+
+/// Blue noise texture shared by the marcher passes. It's shifted by a different offset every frame
+/// so the jitter it drives averages out over time instead of sitting still.
+pub struct BlueNoise{
+    texture: WebGlTexture,
+    uniform_provider: Rc<BlueNoiseUniformProvider>,
+}
+
+struct BlueNoiseUniformProvider{
+    clock: Rc<Clock>,
+}
+
+const BLUE_NOISE_SIZE: usize = 64;
+const BLUE_NOISE_SEED: u32 = 0x2f6b_91c3;
+
 struct RasterUniformProvider{
     input_manager: Rc<InputManager>,
 }
 
 struct FractalUniformProvider{
     input_manager: Rc<InputManager>,
+    clock: Rc<Clock>,
 }
 
+/// How the marcher passes trace their rays, both can be switched off to measure what they save.
+pub static MARCH_PARAMS: [ParamInfo; 2] = [
+    // 1 is plain sphere tracing
+    ParamInfo{name: "relaxation", label: "Over-relaxation", kind: ParamKind::Float{min: 1.0, max: 2.0}, default: ParamValue::Float(1.2)},
+    ParamInfo{name: "prepass", label: "Cone prepass", kind: ParamKind::Toggle, default: ParamValue::Toggle(true)},
+];
+const PREPASS_PARAM: usize = 1;
+
+
+const TARGET_WIDTH: i32 = 1280;
+const TARGET_HEIGHT: i32 = 960;
+/// The cloud noise is offset by the time, it repeats every multiple of both SHAPE_PERIOD and
+/// DETAIL_PERIOD in cloud_frag.glsl, so the time uniform can wrap there without a visible jump.
+const CLOUD_TIME_PERIOD: f64 = 960.0;
+/// The cloud volume is marched at 1/CLOUD_RESOLUTION_DIVISOR of the full resolution on each axis.
+const CLOUD_RESOLUTION_DIVISOR: i32 = 2;
+/// The cone prepass runs at 1/PREPASS_RESOLUTION_DIVISOR of the full resolution on each axis.
+const PREPASS_RESOLUTION_DIVISOR: i32 = 4;
 
 static PIXEL_INDEX_VALS: [u32; 6] = [3,1,0, 0,2,3];
 static PIXEL_VERTS: [f32;8] = [-1.0,1.0,  1.0,1.0,  -1.0,-1.0,  1.0,-1.0];
 
+/// A marcher's fragment shader with its snippets included and the defines for the color format,
+/// the debug views and any `extra` ones.
+fn marcher_shader(source: &str, color_format: ColorBufferFormat, extra: &[&str]) -> String{
+    let debug_defines = DebugView::shader_defines();
+    let defines: Vec<&str> = color_format.shader_defines().iter().copied()
+        .chain(debug_defines.iter().map(String::as_str))
+        .chain(extra.iter().copied())
+        .collect();
+    with_defines(&with_includes(source), &defines)
+}
+
 fn setup_pixel_shader(frag_shader: String) -> RenderPassConfig{
     let indices = Uint32Array::new(&JsValue::from(PIXEL_INDEX_VALS.len()));
     let verts = Float32Array::new(&JsValue::from(PIXEL_VERTS.len()));
@@ -78,12 +192,221 @@ fn setup_pixel_shader(frag_shader: String) -> RenderPassConfig{
     )
 }
 
+fn create_target_texture(ctx: &WebGl2RenderingContext, internal_format: u32, filter: u32,
+                         width: i32, height: i32) -> Result<WebGlTexture, RenderError>{
+    let texture = ctx.create_texture()
+        .ok_or(RenderError::ResourceCreation(String::from("a color texture")))?;
+    ctx.bind_texture(gl::TEXTURE_2D, Some(&texture));
+    ctx.tex_storage_2d(gl::TEXTURE_2D, 1, internal_format, width, height);
+    ctx.tex_parameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, filter as i32);
+    ctx.tex_parameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, filter as i32);
+    ctx.tex_parameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+    ctx.tex_parameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+    ctx.bind_texture(gl::TEXTURE_2D, None);
+    Ok(texture)
+}
+
+/// Binds every param in the store to the uniform of the same name.
+fn attach_params(params: Rc<ParamStore>, render_pass_cfg: RenderPassConfig) -> RenderPassConfig{
+    params.params().iter().enumerate().fold(render_pass_cfg, |cfg, (index, param)| {
+        cfg.add_uniform(param.uniform_name(), params.clone(), index as u32)
+    })
+}
+
+/// Binds the params with the given names, for shaders that only use some of them.
+fn attach_named_params(params: Rc<ParamStore>, names: &[&str], render_pass_cfg: RenderPassConfig) -> RenderPassConfig{
+    params.params().iter().enumerate()
+        .filter(|(_, param)| names.contains(&param.name))
+        .fold(render_pass_cfg, |cfg, (index, param)| {
+            cfg.add_uniform(param.uniform_name(), params.clone(), index as u32)
+        })
+}
+
 static INDEX_VALS: [u32; 3] = [2,1,0];
 static VERTS: [f32; 6] = [-1.0f32,1.0f32,  1.0f32,1.0f32,  1.0f32,-1.0f32];
 
+impl SkyRenderPass{
+    pub fn new(ctx: WebGl2RenderingContext, color_format: ColorBufferFormat) -> Result<Self, RenderError>{
+        let uniform_provider = Rc::new(SkyUniformProvider{time_of_day: Cell::new(TimeOfDay::default())});
+        let render_pass_cfg: RenderPassConfig = setup_pixel_shader(SKY_FRAG_SHADER.to_string())
+            .add_uniform(String::from("sunDir"), uniform_provider.clone(), 0);
+        let render_pass = render_pass_cfg.configure(ctx.clone())?;
+
+        let sky_texture = create_target_texture(&ctx, color_format.hdr_internal_format(), gl::LINEAR,
+            SKY_LUT_WIDTH, SKY_LUT_HEIGHT)?;
+        // the azimuth wraps around
+        ctx.bind_texture(gl::TEXTURE_2D, Some(&sky_texture));
+        ctx.tex_parameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
+        ctx.bind_texture(gl::TEXTURE_2D, None);
+
+        let framebuffer = ctx.create_framebuffer()
+            .ok_or(RenderError::ResourceCreation(String::from("a frame buffer")))?;
+        ctx.bind_framebuffer(gl::FRAMEBUFFER, Some(&framebuffer));
+        ctx.framebuffer_texture_2d(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0,
+                                   gl::TEXTURE_2D, Some(&sky_texture), 0);
+        ctx.bind_framebuffer(gl::FRAMEBUFFER, None);
+
+        Ok(Self{
+            ctx,
+            render_pass,
+            uniform_provider,
+            framebuffer,
+            sky_texture,
+            rendered_time: Cell::new(None),
+        })
+    }
+
+    pub fn time_of_day(&self) -> TimeOfDay{
+        self.uniform_provider.time_of_day.get()
+    }
+
+    pub fn set_time_of_day(&self, time_of_day: TimeOfDay){
+        self.uniform_provider.time_of_day.set(time_of_day);
+    }
+
+    fn attach(&self, render_pass_cfg: RenderPassConfig) -> RenderPassConfig{
+        render_pass_cfg
+            .add_uniform(String::from("sunDir"), self.uniform_provider.clone(), 0)
+            .add_uniform(String::from("sunColor"), self.uniform_provider.clone(), 1)
+            .add_texture(self.sky_texture.clone(), String::from("skyTex"))
+    }
+
+    /// Re-renders the lookup texture if the sun moved since the last draw.
+    pub fn draw(&self){
+        let time_of_day = self.time_of_day();
+        if self.rendered_time.get() == Some(time_of_day){
+            return;
+        }
+        self.rendered_time.set(Some(time_of_day));
+
+        self.ctx.bind_framebuffer(gl::FRAMEBUFFER, Some(&self.framebuffer));
+        self.ctx.viewport(0, 0, SKY_LUT_WIDTH, SKY_LUT_HEIGHT);
+        self.ctx.disable(gl::DEPTH_TEST);
+        self.render_pass.draw();
+        self.ctx.enable(gl::DEPTH_TEST);
+
+        self.ctx.bind_framebuffer(gl::FRAMEBUFFER, None);
+        self.ctx.viewport(0, 0, TARGET_WIDTH, TARGET_HEIGHT);
+    }
+}
+
+impl CrossfadeRenderPass{
+    pub fn new(ctx: WebGl2RenderingContext) -> Result<Self, RenderError>{
+        let uniform_provider = Rc::new(CrossfadeUniformProvider{opacity: Cell::new(0.0)});
+        let snapshot_texture = create_target_texture(&ctx, gl::RGBA8, gl::NEAREST,
+            TARGET_WIDTH, TARGET_HEIGHT)?;
+        let render_pass_cfg: RenderPassConfig = setup_pixel_shader(CROSSFADE_FRAG_SHADER.to_string())
+            .add_uniform(String::from("opacity"), uniform_provider.clone(), 0)
+            .add_texture(snapshot_texture.clone(), String::from("snapshotTex"));
+        let render_pass = render_pass_cfg.configure(ctx.clone())?;
+
+        let framebuffer = ctx.create_framebuffer()
+            .ok_or(RenderError::ResourceCreation(String::from("a frame buffer")))?;
+        ctx.bind_framebuffer(gl::FRAMEBUFFER, Some(&framebuffer));
+        ctx.framebuffer_texture_2d(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0,
+                                   gl::TEXTURE_2D, Some(&snapshot_texture), 0);
+        ctx.bind_framebuffer(gl::FRAMEBUFFER, None);
+
+        Ok(Self{
+            ctx,
+            render_pass,
+            uniform_provider,
+            framebuffer,
+        })
+    }
+
+    /// Copies what has been drawn to the canvas so far this frame.
+    pub fn snapshot(&self){
+        self.ctx.bind_framebuffer(gl::DRAW_FRAMEBUFFER, Some(&self.framebuffer));
+        self.ctx.blit_framebuffer(0, 0, TARGET_WIDTH, TARGET_HEIGHT,
+            0, 0, TARGET_WIDTH, TARGET_HEIGHT, gl::COLOR_BUFFER_BIT, gl::NEAREST);
+        self.ctx.bind_framebuffer(gl::DRAW_FRAMEBUFFER, None);
+    }
+
+    /// Blends the snapshot over the canvas, an opacity of 1 shows only the snapshot.
+    pub fn draw(&self, opacity: f32){
+        self.uniform_provider.opacity.set(opacity);
+        self.ctx.disable(gl::DEPTH_TEST);
+        self.ctx.enable(gl::BLEND);
+        self.ctx.blend_func(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        self.render_pass.draw();
+        self.ctx.disable(gl::BLEND);
+        self.ctx.enable(gl::DEPTH_TEST);
+    }
+}
+
+impl BlueNoise{
+    pub fn new(ctx: &WebGl2RenderingContext, clock: Rc<Clock>) -> Result<Self, RenderError>{
+        let data = blue_noise(BLUE_NOISE_SIZE, BLUE_NOISE_SEED);
+        let texture = util_create_texture_r8(ctx, BLUE_NOISE_SIZE as i32, BLUE_NOISE_SIZE as i32, &data)?;
+        Ok(Self{
+            texture,
+            uniform_provider: Rc::new(BlueNoiseUniformProvider{clock}),
+        })
+    }
+
+    fn attach(&self, render_pass_cfg: RenderPassConfig) -> RenderPassConfig{
+        render_pass_cfg
+            .add_uniform(String::from("blueNoiseOffset"), self.uniform_provider.clone(), 0)
+            .add_texture(self.texture.clone(), String::from("blueNoiseTex"))
+    }
+}
+
+impl ConePrepass{
+    /// `render_pass_cfg` is the marcher pass's own shader compiled with `CONE_PREPASS`.
+    fn new(ctx: WebGl2RenderingContext, render_pass_cfg: RenderPassConfig, color_format: ColorBufferFormat,
+           march_params: Rc<ParamStore>) -> Result<Self, RenderError>{
+        let render_pass = render_pass_cfg.configure(ctx.clone())?;
+        // one distance per pixel, blending them would move the rays past what their cones found
+        let texture = create_target_texture(&ctx, color_format.internal_format(), gl::NEAREST,
+            TARGET_WIDTH / PREPASS_RESOLUTION_DIVISOR, TARGET_HEIGHT / PREPASS_RESOLUTION_DIVISOR)?;
+
+        let framebuffer = ctx.create_framebuffer()
+            .ok_or(RenderError::ResourceCreation(String::from("a frame buffer")))?;
+        ctx.bind_framebuffer(gl::FRAMEBUFFER, Some(&framebuffer));
+        ctx.framebuffer_texture_2d(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0,
+                                   gl::TEXTURE_2D, Some(&texture), 0);
+        ctx.bind_framebuffer(gl::FRAMEBUFFER, None);
+
+        Ok(Self{
+            ctx,
+            render_pass,
+            framebuffer,
+            texture,
+            march_params,
+        })
+    }
+
+    fn enabled(&self) -> bool{
+        self.march_params.get(PREPASS_PARAM) == ParamValue::Toggle(true)
+    }
+
+    /// Binds the distances and the march params to the full resolution pass.
+    fn attach(&self, render_pass_cfg: RenderPassConfig) -> RenderPassConfig{
+        attach_params(self.march_params.clone(), render_pass_cfg)
+            .add_texture(self.texture.clone(), String::from("prepassTex"))
+    }
+
+    /// Marches the cones, unless the prepass is switched off and the rays start at the camera.
+    fn draw(&self){
+        if !self.enabled() {
+            return;
+        }
+        self.ctx.bind_framebuffer(gl::FRAMEBUFFER, Some(&self.framebuffer));
+        self.ctx.viewport(0, 0, TARGET_WIDTH / PREPASS_RESOLUTION_DIVISOR, TARGET_HEIGHT / PREPASS_RESOLUTION_DIVISOR);
+        self.ctx.disable(gl::DEPTH_TEST);
+        self.render_pass.draw();
+        self.ctx.enable(gl::DEPTH_TEST);
+
+        self.ctx.bind_framebuffer(gl::FRAMEBUFFER, None);
+        self.ctx.viewport(0, 0, TARGET_WIDTH, TARGET_HEIGHT);
+    }
+}
+
 impl RasterRenderPass{
-    pub fn new(ctx: WebGl2RenderingContext, input_manager: Rc<InputManager>)
-        -> Result<Self, String>{
+    pub fn new(ctx: WebGl2RenderingContext, input_manager: Rc<InputManager>,
+               color_format: ColorBufferFormat)
+        -> Result<Self, RenderError>{
         let uniform_provider = Rc::new(RasterUniformProvider{input_manager: input_manager.clone()});
 
         let indices = Uint32Array::new(&JsValue::from(INDEX_VALS.len()));
@@ -111,26 +434,27 @@ impl RasterRenderPass{
         .add_uniform(String::from("mvp"), uniform_provider.clone(),0);
         let render_pass = render_pass_cfg.configure(ctx.clone())?;
 
-        let color_texture = ctx.create_texture()
-            .ok_or(String::from("Failed to create color texture."))?;
-        ctx.bind_texture(gl::TEXTURE_2D, Some(&color_texture));
-        ctx.tex_storage_2d(gl::TEXTURE_2D,
-                           1,
-            gl::RGBA32F,
-            1280, 960
-        );
-        ctx.tex_parameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
-        ctx.tex_parameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
-        ctx.tex_parameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+        let color_texture = create_target_texture(&ctx, color_format.internal_format(),
+            gl::LINEAR, TARGET_WIDTH, TARGET_HEIGHT)?;
+        // packed distances can't be interpolated, so they are always sampled with nearest filtering
+        let distance_texture = if color_format.packed_depth() {
+            Some(create_target_texture(&ctx, gl::RGBA8, gl::NEAREST, TARGET_WIDTH, TARGET_HEIGHT)?)
+        }else{
+            None
+        };
 
         let framebuffer = ctx.create_framebuffer()
-            .ok_or(String::from("Failed to create frame buffer."))?;
+            .ok_or(RenderError::ResourceCreation(String::from("a frame buffer")))?;
         ctx.bind_framebuffer(gl::FRAMEBUFFER, Some(&framebuffer));
         ctx.framebuffer_texture_2d(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0,
                                    gl::TEXTURE_2D, Some(&color_texture), 0);
+        if let Some(distance_texture) = &distance_texture {
+            ctx.framebuffer_texture_2d(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT1,
+                                       gl::TEXTURE_2D, Some(distance_texture), 0);
+        }
 
         let depth_buffer = ctx.create_renderbuffer()
-            .ok_or(String::from("Failed to create depth buffer."))?;
+            .ok_or(RenderError::ResourceCreation(String::from("a depth buffer")))?;
         ctx.bind_renderbuffer(gl::RENDERBUFFER, Some(&depth_buffer));
         ctx.renderbuffer_storage(gl::RENDERBUFFER, gl::DEPTH_COMPONENT16, 1280, 960);
         ctx.framebuffer_renderbuffer(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::RENDERBUFFER, Some(&depth_buffer));
@@ -144,7 +468,8 @@ impl RasterRenderPass{
             uniform_provider,
             framebuffer,
             depth_buffer,
-            color_texture
+            color_texture,
+            distance_texture
         })
     }
 
@@ -156,6 +481,10 @@ impl RasterRenderPass{
         &self.color_texture
     }
 
+    pub fn distance_texture(&self) -> Option<&WebGlTexture>{
+        self.distance_texture.as_ref()
+    }
+
     pub fn draw(&self){
         self.ctx.bind_framebuffer(gl::FRAMEBUFFER, Some(&self.framebuffer));
 
@@ -172,24 +501,37 @@ impl RasterRenderPass{
 }
 
 impl DemoRenderPass {
-    pub fn new(ctx: WebGl2RenderingContext, input_manager: Rc<InputManager>)
-        -> Result<Self, String>{
-        let uniform_provider = Rc::new(FractalUniformProvider{input_manager: input_manager.clone()});
-        let render_pass_cfg: RenderPassConfig = setup_pixel_shader(DEMO_FRAG_SHADER.to_string())
+    pub fn new(context: &SceneContext) -> Result<Self, RenderError>{
+        let ctx = context.ctx.clone();
+        let color_format = context.color_format;
+        let uniform_provider = Rc::new(FractalUniformProvider{input_manager: context.input_manager.clone(),
+            clock: context.clock.clone()});
+        let prepass_shader = marcher_shader(DEMO_FRAG_SHADER, color_format, &["CONE_PREPASS"]);
+        let prepass_cfg = setup_pixel_shader(prepass_shader)
+            .add_uniform(String::from("invProjMat"), uniform_provider.clone(), 0)
+            .add_uniform(String::from("invViewMat"), uniform_provider.clone(), 1);
+        let prepass = ConePrepass::new(ctx.clone(), prepass_cfg, color_format, context.march_params.clone())?;
+
+        let frag_shader = marcher_shader(DEMO_FRAG_SHADER, color_format, &[]);
+        let render_pass_cfg: RenderPassConfig = prepass.attach(context.sky_pass.attach(
+            context.blue_noise.attach(setup_pixel_shader(frag_shader))))
             .add_uniform(String::from("invProjMat"), uniform_provider.clone(), 0)
             .add_uniform(String::from("invViewMat"), uniform_provider.clone(), 1)
             .add_uniform(String::from("viewProjMat"), uniform_provider.clone(), 2)
-            .add_uniform(String::from("time"), uniform_provider.clone(), 3);
+            .add_uniform(String::from("time"), uniform_provider.clone(), 3)
+            .add_uniform(String::from("debugView"), context.debug_view.clone(), 0);
         let render_pass = render_pass_cfg.configure(ctx.clone())?;
 
         Ok(Self{
             ctx,
             render_pass,
-            uniform_provider
+            uniform_provider,
+            prepass,
         })
     }
 
     pub fn draw(&self){
+        self.prepass.draw();
         self.ctx.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT
             | WebGl2RenderingContext::DEPTH_BUFFER_BIT);
         self.render_pass.draw();
@@ -197,25 +539,43 @@ impl DemoRenderPass {
 }
 
 impl FractalRenderPass{
-    pub fn new(ctx: WebGl2RenderingContext, input_manager: Rc<InputManager>,
-               color_texture: &WebGlTexture, depth_buffer: &WebGlRenderbuffer)
-        -> Result<Self, String>{
-        let uniform_provider = Rc::new(FractalUniformProvider{input_manager: input_manager.clone()});
-        let render_pass_cfg: RenderPassConfig = setup_pixel_shader(FRACTAL_FRAG_SHADER.to_string())
+    /// Draws into the raster pass's color, distance and depth targets.
+    pub fn new(context: &SceneContext, raster_pass: &RasterRenderPass) -> Result<Self, RenderError>{
+        let ctx = context.ctx.clone();
+        let color_format = context.color_format;
+        let uniform_provider = Rc::new(FractalUniformProvider{input_manager: context.input_manager.clone(),
+            clock: context.clock.clone()});
+        let prepass_shader = marcher_shader(FRACTAL_FRAG_SHADER, color_format, &["CONE_PREPASS"]);
+        // the cones only need the shape of the fractal
+        let prepass_cfg = attach_named_params(context.fractal_params.clone(), &FRACTAL_SHAPE_PARAMS,
+            setup_pixel_shader(prepass_shader))
+            .add_uniform(String::from("invProjMat"), uniform_provider.clone(), 0)
+            .add_uniform(String::from("invViewMat"), uniform_provider.clone(), 1);
+        let prepass = ConePrepass::new(ctx.clone(), prepass_cfg, color_format, context.march_params.clone())?;
+
+        let frag_shader = marcher_shader(FRACTAL_FRAG_SHADER, color_format, &[]);
+        let render_pass_cfg: RenderPassConfig = attach_params(context.fractal_params.clone(),
+            prepass.attach(context.sky_pass.attach(context.blue_noise.attach(setup_pixel_shader(frag_shader)))))
             .add_uniform(String::from("invProjMat"), uniform_provider.clone(), 0)
             .add_uniform(String::from("invViewMat"), uniform_provider.clone(), 1)
             .add_uniform(String::from("viewProjMat"), uniform_provider.clone(), 2)
-            .add_uniform(String::from("time"), uniform_provider.clone(), 3);
+            .add_uniform(String::from("time"), uniform_provider.clone(), 3)
+            .add_uniform(String::from("debugView"), context.debug_view.clone(), 0);
         let render_pass = render_pass_cfg.configure(ctx.clone())?;
 
         let framebuffer = ctx.create_framebuffer()
-            .ok_or(String::from("Failed to create frame buffer."))?;
+            .ok_or(RenderError::ResourceCreation(String::from("a frame buffer")))?;
         ctx.bind_framebuffer(gl::FRAMEBUFFER, Some(&framebuffer));
         ctx.framebuffer_texture_2d(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0,
-                                   gl::TEXTURE_2D, Some(&color_texture), 0);
+                                   gl::TEXTURE_2D, Some(raster_pass.color_texture()), 0);
+        if let Some(distance_texture) = raster_pass.distance_texture() {
+            ctx.framebuffer_texture_2d(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT1,
+                                       gl::TEXTURE_2D, Some(distance_texture), 0);
+        }
 
-        ctx.bind_renderbuffer(gl::RENDERBUFFER, Some(&depth_buffer));
-        ctx.framebuffer_renderbuffer(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::RENDERBUFFER, Some(&depth_buffer));
+        let depth_buffer = raster_pass.depth_buffer();
+        ctx.bind_renderbuffer(gl::RENDERBUFFER, Some(depth_buffer));
+        ctx.framebuffer_renderbuffer(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::RENDERBUFFER, Some(depth_buffer));
 
         ctx.bind_framebuffer(gl::FRAMEBUFFER, None);
 
@@ -223,18 +583,28 @@ impl FractalRenderPass{
             ctx,
             render_pass,
             uniform_provider,
-            framebuffer
+            framebuffer,
+            color_format,
+            prepass,
         })
     }
 
     pub fn draw(&self){
+        self.prepass.draw();
         self.ctx.bind_framebuffer(gl::FRAMEBUFFER, Some(&self.framebuffer));
 
 
         self.ctx.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT
                     | WebGl2RenderingContext::DEPTH_BUFFER_BIT);
-        let buffers = js_sys::Uint32Array::new(&JsValue::from(1));
-        buffers.copy_from(&[gl::COLOR_ATTACHMENT0]);
+        let buffers = if self.color_format.packed_depth() {
+            let buffers = js_sys::Uint32Array::new(&JsValue::from(2));
+            buffers.copy_from(&[gl::COLOR_ATTACHMENT0, gl::COLOR_ATTACHMENT1]);
+            buffers
+        }else{
+            let buffers = js_sys::Uint32Array::new(&JsValue::from(1));
+            buffers.copy_from(&[gl::COLOR_ATTACHMENT0]);
+            buffers
+        };
         self.ctx.draw_buffers(&buffers);
 
         self.render_pass.draw();
@@ -244,34 +614,88 @@ impl FractalRenderPass{
 }
 
 impl CloudRenderPass{
-    pub fn new(ctx: WebGl2RenderingContext, input_manager: Rc<InputManager>,
-               color_texture: &WebGlTexture)
-        -> Result<Self, String>{
-        let fractal_uniform_provider = Rc::new(FractalUniformProvider{input_manager: input_manager.clone()});
-        let render_pass_cfg: RenderPassConfig = setup_pixel_shader(CLOUD_FRAG_SHADER.to_string())
+    /// Marches the clouds over the raster pass's color and distance targets, with the params in
+    /// `context.cloud_params`.
+    pub fn new(context: &SceneContext, raster_pass: &RasterRenderPass) -> Result<Self, RenderError>{
+        let ctx = context.ctx.clone();
+        let color_format = context.color_format;
+        let color_texture = raster_pass.color_texture();
+        let distance_texture = raster_pass.distance_texture();
+        let fractal_uniform_provider = Rc::new(FractalUniformProvider{input_manager: context.input_manager.clone(),
+            clock: context.clock.clone()});
+        let cloud_uniform_provider = Rc::new(CloudUniformProvider{params: context.cloud_params.clone(),
+            show_clouds: Cell::new(true)});
+        let noise_texture = util_create_texture_3d(&ctx, CLOUD_NOISE_SIZE as i32,
+            &bake_cloud_noise(CLOUD_NOISE_SIZE))?;
+
+        let frag_shader = with_defines(&with_includes(CLOUD_FRAG_SHADER), color_format.shader_defines());
+        let mut render_pass_cfg: RenderPassConfig = context.sky_pass.attach(
+            context.blue_noise.attach(setup_pixel_shader(frag_shader)))
             .add_uniform(String::from("invProjMat"), fractal_uniform_provider.clone(), 0)
             .add_uniform(String::from("invViewMat"), fractal_uniform_provider.clone(), 1)
-            .add_uniform(String::from("viewProjMat"), fractal_uniform_provider.clone(), 2)
             .add_uniform(String::from("time"), fractal_uniform_provider.clone(), 3)
-            .add_texture(color_texture.clone(), String::from("colorTex"));
+            .add_uniform(String::from("cloudDensityScale"), cloud_uniform_provider.clone(), 0)
+            .add_uniform(String::from("cloudAbsorption"), cloud_uniform_provider.clone(), 1)
+            .add_uniform(String::from("cloudScattering"), cloud_uniform_provider.clone(), 2)
+            .add_uniform(String::from("cloudCoverage"), cloud_uniform_provider.clone(), 3)
+            .add_uniform(String::from("cloudAnisotropy"), cloud_uniform_provider.clone(), 4)
+            .add_texture(color_texture.clone(), String::from("colorTex"))
+            .add_texture_3d(noise_texture, String::from("noiseTex"));
+        if let Some(distance_texture) = distance_texture {
+            render_pass_cfg = render_pass_cfg.add_texture(distance_texture.clone(), String::from("distTex"));
+        }
         let render_pass = render_pass_cfg.configure(ctx.clone())?;
+
+        let cloud_texture = create_target_texture(&ctx, gl::RGBA8, gl::NEAREST,
+            TARGET_WIDTH / CLOUD_RESOLUTION_DIVISOR, TARGET_HEIGHT / CLOUD_RESOLUTION_DIVISOR)?;
+        let framebuffer = ctx.create_framebuffer()
+            .ok_or(RenderError::ResourceCreation(String::from("a frame buffer")))?;
+        ctx.bind_framebuffer(gl::FRAMEBUFFER, Some(&framebuffer));
+        ctx.framebuffer_texture_2d(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0,
+                                   gl::TEXTURE_2D, Some(&cloud_texture), 0);
+        ctx.bind_framebuffer(gl::FRAMEBUFFER, None);
+
+        let composite_shader = with_defines(&with_includes(CLOUD_COMPOSITE_FRAG_SHADER), color_format.shader_defines());
+        let mut composite_pass_cfg: RenderPassConfig = setup_pixel_shader(composite_shader)
+            .add_uniform(String::from("showClouds"), cloud_uniform_provider.clone(), SHOW_CLOUDS_UNIFORM)
+            .add_texture(color_texture.clone(), String::from("colorTex"))
+            .add_texture(cloud_texture, String::from("cloudTex"));
+        if let Some(distance_texture) = distance_texture {
+            composite_pass_cfg = composite_pass_cfg.add_texture(distance_texture.clone(), String::from("distTex"));
+        }
+        let composite_pass = composite_pass_cfg.configure(ctx.clone())?;
+
         Ok(Self{
             ctx,
             render_pass,
-            uniform_provider: fractal_uniform_provider
+            composite_pass,
+            cloud_uniform_provider,
+            framebuffer,
         })
     }
 
-    pub fn draw(&self){
-        self.render_pass.draw();
+    /// Marches the clouds and composites them over the scene. Without clouds the scene is
+    /// only copied to the canvas.
+    pub fn draw(&self, show_clouds: bool){
+        self.cloud_uniform_provider.show_clouds.set(show_clouds);
+        if show_clouds {
+            self.ctx.bind_framebuffer(gl::FRAMEBUFFER, Some(&self.framebuffer));
+            self.ctx.viewport(0, 0,
+                TARGET_WIDTH / CLOUD_RESOLUTION_DIVISOR, TARGET_HEIGHT / CLOUD_RESOLUTION_DIVISOR);
+            self.render_pass.draw();
+        }
+
+        self.ctx.bind_framebuffer(gl::FRAMEBUFFER, None);
+        self.ctx.viewport(0, 0, TARGET_WIDTH, TARGET_HEIGHT);
+        self.ctx.clear(WebGl2RenderingContext::DEPTH_BUFFER_BIT);
+        self.composite_pass.draw();
     }
 }
 
 impl UniformProvider for FractalUniformProvider{
     fn update(&self, gl: &WebGl2RenderingContext, loc: &WebGlUniformLocation, index: u32) {
         if index == 3 {
-            let time = js_sys::Date::now() / 1000.0 % 10000.0;
-            gl.uniform1fv_with_f32_array(Some(loc), &[time as f32]);
+            gl.uniform1f(Some(loc), self.clock.wrapped(CLOUD_TIME_PERIOD));
             return;
         }
         let mat = match index {
@@ -292,9 +716,92 @@ impl UniformProvider for FractalUniformProvider{
     }
 }
 
+impl UniformProvider for CloudUniformProvider{
+    fn update(&self, gl: &WebGl2RenderingContext, loc: &WebGlUniformLocation, index: u32) {
+        if index == SHOW_CLOUDS_UNIFORM {
+            gl.uniform1i(Some(loc), self.show_clouds.get() as i32);
+            return;
+        }
+        let params = self.params.get();
+        let val = match index {
+            0 => params.density,
+            1 => params.absorption,
+            2 => params.scattering,
+            3 => params.coverage,
+            _ => params.anisotropy,
+        };
+        gl.uniform1f(Some(loc), val);
+    }
+}
+
+impl UniformProvider for SkyUniformProvider{
+    fn update(&self, gl: &WebGl2RenderingContext, loc: &WebGlUniformLocation, index: u32) {
+        let time_of_day = self.time_of_day.get();
+        let vec = match index {
+            0 => time_of_day.sun_direction(),
+            _ => time_of_day.sun_color(),
+        };
+        gl.uniform3f(Some(loc), vec.x(), vec.y(), vec.z());
+    }
+}
+
+impl UniformProvider for ParamStore{
+    fn update(&self, gl: &WebGl2RenderingContext, loc: &WebGlUniformLocation, index: u32) {
+        match self.get(index as usize) {
+            ParamValue::Float(value) => gl.uniform1f(Some(loc), value),
+            ParamValue::Int(value) => gl.uniform1i(Some(loc), value),
+            ParamValue::Color([r, g, b]) => gl.uniform3f(Some(loc), r, g, b),
+            ParamValue::Toggle(value) => gl.uniform1i(Some(loc), value as i32),
+            ParamValue::Vec3([x, y, z]) => gl.uniform3f(Some(loc), x, y, z),
+        }
+    }
+}
+
+impl Tunable for SkyRenderPass{
+    fn group(&self) -> &'static str{
+        "sun"
+    }
+
+    fn params(&self) -> &'static [ParamInfo]{
+        &SUN_PARAMS
+    }
+
+    fn get(&self, index: usize) -> ParamValue{
+        self.time_of_day().param(index)
+    }
+
+    fn set(&self, index: usize, value: ParamValue){
+        let mut time_of_day = self.time_of_day();
+        time_of_day.set_param(index, value);
+        self.set_time_of_day(time_of_day);
+    }
+}
+
+impl UniformProvider for Cell<DebugView>{
+    fn update(&self, gl: &WebGl2RenderingContext, loc: &WebGlUniformLocation, _index: u32) {
+        gl.uniform1i(Some(loc), self.get().index());
+    }
+}
+
+impl UniformProvider for CrossfadeUniformProvider{
+    fn update(&self, gl: &WebGl2RenderingContext, loc: &WebGlUniformLocation, _index: u32) {
+        gl.uniform1f(Some(loc), self.opacity.get());
+    }
+}
+
+impl UniformProvider for BlueNoiseUniformProvider{
+    fn update(&self, gl: &WebGl2RenderingContext, loc: &WebGlUniformLocation, _index: u32) {
+        // R2 low discrepancy sequence, consecutive frames land far apart on the tile
+        let frame = self.clock.frame() as f64;
+        let size = BLUE_NOISE_SIZE as f64;
+        let x = (frame * 0.754_877_666_246_692_7).fract() * size;
+        let y = (frame * 0.569_840_290_998_053_2).fract() * size;
+        gl.uniform2i(Some(loc), x as i32, y as i32);
+    }
+}
+
 impl UniformProvider for RasterUniformProvider{
     fn update(&self, gl: &WebGl2RenderingContext, loc: &WebGlUniformLocation, _index: u32) {
-        let time = js_sys::Date::now() / 1000.0 % 100.0;
         let model = mat4::IDENTITY
             .translate(&Vec3f::new(0.0f32, 0.0f32, 5.0f32))
             .rotate3d(&Vec3f::new(0.0, 1.0, 0.0), 0.0 as f32);
@@ -305,4 +812,4 @@ impl UniformProvider for RasterUniformProvider{
         let vals = mvp.vals();
         gl.uniform_matrix4fv_with_f32_array(Some(loc), false, vals);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,278 @@
+use std::cell::{Cell, RefCell};
+use std::rc::{Rc, Weak};
+use js_sys::{Array, Object, Reflect};
+use wasm_bindgen::prelude::*;
+use web_sys::{HtmlCanvasElement, WebGl2RenderingContext};
+use crate::app::{AppOptions, TestApp};
+use crate::camera_path::CameraPath;
+use crate::error::{show_overlay, RenderError};
+use crate::recording::Recording;
+use crate::log_error;
+use crate::vec_lib::vec3::Vec3f;
+
+// The renderer as seen from JavaScript. A host page makes one per canvas and drives it:
+//
+//     const renderer = new Renderer(canvas, { scene: "fractal", useUrl: false, panel: true });
+//     renderer.set_camera({ position: [0, 1, -4], forward: [0, 0, 1], up: [0, 1, 0], fov: 60 });
+//     renderer.set_param("clouds.coverage", 0.6);
+//     renderer.set_debug_view("steps");
+//     renderer.start_input_recording();
+//     console.log(renderer.frame_time());
+//     const png = renderer.screenshot();
+//     renderer.destroy();
+//
+// Cameras are plain objects with `position`, `forward` and `up` as [x, y, z] arrays and `fov` in
+// degrees. Any of them can be left out of `set_camera` to keep the current value.
+
+fn get(object: &JsValue, key: &str) -> Option<JsValue>{
+    Reflect::get(object, &JsValue::from_str(key)).ok()
+        .filter(|value| !value.is_undefined() && !value.is_null())
+}
+
+fn set(object: &Object, key: &str, value: &JsValue){
+    let _ = Reflect::set(object, &JsValue::from_str(key), value);
+}
+
+fn vec3_from_js(value: &JsValue) -> Option<Vec3f>{
+    let array = value.dyn_ref::<Array>().filter(|array| array.length() == 3)?;
+    let component = |index: u32| array.get(index).as_f64().filter(|value| value.is_finite()).map(|value| value as f32);
+    Some(Vec3f::new(component(0)?, component(1)?, component(2)?))
+}
+
+fn vec3_to_js(vector: &Vec3f) -> JsValue{
+    [vector.x(), vector.y(), vector.z()].iter()
+        .map(|component| JsValue::from_f64(*component as f64))
+        .collect::<Array>()
+        .into()
+}
+
+fn options_from_js(options: &JsValue) -> Result<AppOptions, String>{
+    let scene = match get(options, "scene") {
+        Some(scene) => Some(scene.as_string().ok_or(String::from("Option 'scene' must be a string"))?),
+        None => None,
+    };
+    let use_url = match get(options, "useUrl") {
+        Some(use_url) => use_url.as_bool().ok_or(String::from("Option 'useUrl' must be a boolean"))?,
+        None => false,
+    };
+    let panel = match get(options, "panel") {
+        Some(panel) => panel.as_bool().ok_or(String::from("Option 'panel' must be a boolean"))?,
+        None => false,
+    };
+    Ok(AppOptions{scene, use_url, panel})
+}
+
+fn to_js_error(message: String) -> JsValue{
+    js_sys::Error::new(&message).into()
+}
+
+// Shared with the animation frame callback, which only holds a weak reference so dropping the
+// renderer ends the loop.
+struct Runner{
+    app: TestApp,
+    window: web_sys::Window,
+    frame: RefCell<Option<Closure<dyn FnMut()>>>,
+    frame_id: Cell<Option<i32>>,
+}
+
+impl Runner{
+    fn request_frame(&self){
+        if self.frame_id.get().is_some() {
+            return;
+        }
+        if let Some(frame) = self.frame.borrow().as_ref() {
+            match self.window.request_animation_frame(frame.as_ref().unchecked_ref()) {
+                Ok(id) => self.frame_id.set(Some(id)),
+                Err(_) => {
+                    log_error!("Failed to request an animation frame");
+                }
+            }
+        }
+    }
+
+    fn cancel_frame(&self){
+        if let Some(id) = self.frame_id.take() {
+            let _ = self.window.cancel_animation_frame(id);
+        }
+    }
+}
+
+#[wasm_bindgen]
+pub struct Renderer{
+    runner: Rc<Runner>,
+    canvas: HtmlCanvasElement,
+}
+
+#[wasm_bindgen]
+impl Renderer{
+    /// Starts rendering into `canvas`. `options` may set the starting `scene`, `useUrl`, which
+    /// reads the view from the page's URL and keeps it there to be shared, and `panel`, which
+    /// adds controls for the params next to the canvas.
+    #[wasm_bindgen(constructor)]
+    pub fn new(canvas: HtmlCanvasElement, options: JsValue) -> Result<Renderer, JsValue>{
+        let window = web_sys::window().ok_or_else(|| to_js_error(String::from("No window")))?;
+        let options = options_from_js(&options).map_err(to_js_error)?;
+        let context = canvas.get_context("webgl2")?
+            .and_then(|context| context.dyn_into::<WebGl2RenderingContext>().ok())
+            .ok_or_else(|| RenderError::ContextUnavailable(String::from("WebGL 2")));
+
+        let app = context
+            .and_then(|context| TestApp::new(context, canvas.clone(), window.clone(), &options))
+            .map_err(|err| {
+                log_error!("{}", err);
+                show_overlay(&canvas, &err);
+                JsValue::from(err)
+            })?;
+
+        let runner = Rc::new(Runner{
+            app,
+            window,
+            frame: RefCell::new(None),
+            frame_id: Cell::new(None),
+        });
+        let weak: Weak<Runner> = Rc::downgrade(&runner);
+        *runner.frame.borrow_mut() = Some(Closure::new(move || {
+            if let Some(runner) = weak.upgrade() {
+                runner.frame_id.set(None);
+                runner.app.draw();
+                runner.request_frame();
+            }
+        }));
+        runner.request_frame();
+
+        Ok(Renderer{runner, canvas})
+    }
+
+    /// Switches to the scene with the given name, see `scene_list::SCENE_NAMES`.
+    pub fn set_scene(&self, name: &str) -> Result<(), JsValue>{
+        self.runner.app.set_scene(name).map_err(to_js_error)
+    }
+
+    /// Shows one of the marchers' debug views by name, see `debug_view.rs`. `off` shows the scene.
+    pub fn set_debug_view(&self, name: &str) -> Result<(), JsValue>{
+        self.runner.app.set_debug_view(name).map_err(to_js_error)
+    }
+
+    /// Sets how long scene switches fade for in seconds, 0 switches instantly.
+    pub fn set_scene_crossfade(&self, seconds: f64){
+        self.runner.app.set_crossfade(seconds);
+    }
+
+    /// Replaces the key bindings, see `bindings.rs` for the format. They are saved in localStorage.
+    pub fn set_key_bindings(&self, config: &str) -> Result<(), JsValue>{
+        self.runner.app.set_bindings(config).map_err(to_js_error)
+    }
+
+    /// Replaces the camera path, see `camera_path.rs` for the format.
+    pub fn set_camera_path(&self, path: &str) -> Result<(), JsValue>{
+        let path = CameraPath::parse(path).map_err(to_js_error)?;
+        self.runner.app.set_camera_path(path);
+        Ok(())
+    }
+
+    /// The current camera path as text, to save and load again with `set_camera_path`.
+    pub fn camera_path(&self) -> String{
+        self.runner.app.camera_path()
+    }
+
+    /// Plays the camera path from the start on the scene clock.
+    pub fn play_camera_path(&self){
+        self.runner.app.play_camera_path();
+    }
+
+    pub fn stop_camera_path(&self){
+        self.runner.app.stop_camera_path();
+    }
+
+    /// Starts recording raw input from the current view, see `recording.rs`.
+    pub fn start_input_recording(&self){
+        self.runner.app.start_recording();
+    }
+
+    pub fn stop_input_recording(&self){
+        self.runner.app.stop_recording();
+    }
+
+    /// The last finished input recording as text, to replay with `replay_input`.
+    pub fn input_recording(&self) -> String{
+        self.runner.app.last_recording()
+    }
+
+    /// Replays an input recording from the view it started at. Live input is ignored until it ends.
+    pub fn replay_input(&self, recording: &str) -> Result<(), JsValue>{
+        let recording = Recording::parse(recording).map_err(to_js_error)?;
+        self.runner.app.replay(&recording).map_err(to_js_error)
+    }
+
+    pub fn set_camera(&self, camera: JsValue) -> Result<(), JsValue>{
+        let mut view = self.runner.app.view_state();
+        for (key, vector) in [("position", &mut view.position), ("forward", &mut view.forward), ("up", &mut view.up)]{
+            if let Some(value) = get(&camera, key) {
+                *vector = vec3_from_js(&value)
+                    .ok_or_else(|| to_js_error(format!("Camera '{}' must be an array of 3 numbers", key)))?;
+            }
+        }
+        if view.forward.squared_length() == 0.0 || view.up.squared_length() == 0.0 {
+            return Err(to_js_error(String::from("Camera 'forward' and 'up' can't be zero")));
+        }
+        if let Some(fov) = get(&camera, "fov") {
+            view.fov = fov.as_f64().filter(|fov| *fov > 0.0 && *fov < 180.0)
+                .ok_or_else(|| to_js_error(String::from("Camera 'fov' must be between 0 and 180")))? as f32;
+        }
+        self.runner.app.apply_view(&view);
+        Ok(())
+    }
+
+    pub fn get_camera(&self) -> JsValue{
+        let view = self.runner.app.view_state();
+        let camera = Object::new();
+        set(&camera, "position", &vec3_to_js(&view.position));
+        set(&camera, "forward", &vec3_to_js(&view.forward));
+        set(&camera, "up", &vec3_to_js(&view.up));
+        set(&camera, "fov", &JsValue::from_f64(view.fov as f64));
+        camera.into()
+    }
+
+    /// Sets a tunable value, see `TestApp::set_param` for the names. Takes numbers, booleans
+    /// and colors as `#rrggbb` strings.
+    pub fn set_param(&self, name: &str, value: JsValue) -> Result<(), JsValue>{
+        let text = if let Some(number) = value.as_f64() {
+            number.to_string()
+        }else if let Some(toggle) = value.as_bool() {
+            toggle.to_string()
+        }else{
+            value.as_string().ok_or_else(|| to_js_error(format!("Invalid value for '{}'", name)))?
+        };
+        self.runner.app.set_param(name, &text).map_err(to_js_error)
+    }
+
+    /// Milliseconds per frame averaged over the last few frames, for example to see what the
+    /// `march.relaxation` and `march.prepass` params save.
+    pub fn frame_time(&self) -> f64{
+        self.runner.app.frame_time()
+    }
+
+    /// Stops drawing frames until `resume`.
+    pub fn pause(&self){
+        self.runner.cancel_frame();
+    }
+
+    pub fn resume(&self){
+        self.runner.request_frame();
+    }
+
+    /// The current frame as a PNG data URL.
+    pub fn screenshot(&self) -> Result<String, JsValue>{
+        // the drawing buffer is cleared once a frame is presented, so draw it again first
+        self.runner.app.render();
+        self.canvas.to_data_url_with_type("image/png")
+    }
+
+    /// Stops rendering and releases the canvas's GL context and event listeners. The renderer
+    /// can't be used afterwards.
+    pub fn destroy(self){
+        self.runner.cancel_frame();
+        self.runner.frame.borrow_mut().take();
+        self.runner.app.destroy();
+    }
+}
@@ -0,0 +1,40 @@
+// Names of the scenes, kept apart from the scenes themselves in `scenes.rs` so URLs and the JS
+// API can be checked without WebGL.
+
+/// Every scene in the order of the number keys that select them. The name is used by the JS API
+/// and the `scene` URL parameter.
+pub const SCENE_NAMES: [&str; 2] = ["demo", "fractal"];
+
+pub fn scene_index(name: &str) -> Option<usize>{
+    SCENE_NAMES.iter().position(|scene| *scene == name)
+}
+
+/// Reads the `scene` parameter out of a URL query string like `?scene=fractal`.
+pub fn scene_from_query(query: &str) -> Option<&str>{
+    query.trim_start_matches('?')
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == "scene")
+        .map(|(_, value)| value)
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn finds_scene_in_query(){
+        assert_eq!(scene_from_query("?scene=fractal"), Some("fractal"));
+        assert_eq!(scene_from_query("?debug=1&scene=demo"), Some("demo"));
+        assert_eq!(scene_from_query("?scenes=demo"), None);
+        assert_eq!(scene_from_query(""), None);
+    }
+
+    #[test]
+    fn scene_names_are_unique(){
+        for (index, name) in SCENE_NAMES.iter().enumerate(){
+            assert_eq!(scene_index(name), Some(index));
+        }
+        assert_eq!(scene_index("missing"), None);
+    }
+}
@@ -0,0 +1,229 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use web_sys::WebGl2RenderingContext;
+use crate::clock::Clock;
+use crate::gamepad::SceneStep;
+use crate::controls::SceneRequest;
+use crate::input::InputManager;
+use crate::{log, log_error, log_warn};
+use crate::error::RenderError;
+use crate::scene_list::{scene_index, SCENE_NAMES};
+use crate::params::{ParamStore, Tunable};
+use crate::clouds::CloudParams;
+use crate::debug_view::DebugView;
+use crate::render_passes::{BlueNoise, CloudRenderPass, CrossfadeRenderPass, DemoRenderPass, FractalRenderPass,
+    RasterRenderPass, SkyRenderPass};
+use crate::webgl_utils::capabilities::ColorBufferFormat;
+
+/// A set of passes drawn together. Scenes own their passes, so each one is only compiled the
+/// first time it's shown.
+pub trait Scene{
+    fn draw(&self);
+
+    /// Params of the scene's passes, listed in the params panel while the scene is shown.
+    fn tunables(&self) -> Vec<Rc<dyn Tunable>>{
+        Vec::new()
+    }
+}
+
+/// Everything the scenes share, handed to them when they are built.
+pub struct SceneContext{
+    pub ctx: WebGl2RenderingContext,
+    pub input_manager: Rc<InputManager>,
+    pub clock: Rc<Clock>,
+    pub color_format: ColorBufferFormat,
+    pub blue_noise: Rc<BlueNoise>,
+    pub sky_pass: Rc<SkyRenderPass>,
+    /// Kept outside the scenes, so they can be set before the clouds are first built.
+    pub cloud_params: Rc<Cell<CloudParams>>,
+    pub fractal_params: Rc<ParamStore>,
+    pub march_params: Rc<ParamStore>,
+    pub debug_view: Rc<Cell<DebugView>>,
+}
+
+type SceneBuilder = fn(&SceneContext) -> Result<Box<dyn Scene>, RenderError>;
+
+/// Builds the scenes, in the same order as their names in `SCENE_NAMES`.
+static SCENES: [SceneBuilder; SCENE_NAMES.len()] = [DemoScene::build, FractalScene::build];
+
+/// Crossfade between scenes in seconds, 0 switches instantly.
+const DEFAULT_CROSSFADE: f64 = 0.5;
+
+struct DemoScene{
+    demo_pass: DemoRenderPass,
+    march_params: Rc<ParamStore>,
+}
+
+impl DemoScene{
+    fn build(context: &SceneContext) -> Result<Box<dyn Scene>, RenderError>{
+        let demo_pass = DemoRenderPass::new(context)?;
+        Ok(Box::new(DemoScene{demo_pass, march_params: context.march_params.clone()}))
+    }
+}
+
+impl Scene for DemoScene{
+    fn draw(&self){
+        self.demo_pass.draw();
+    }
+
+    fn tunables(&self) -> Vec<Rc<dyn Tunable>>{
+        vec![self.march_params.clone()]
+    }
+}
+
+struct FractalScene{
+    // only drawn into by the fractal pass, it owns the color and depth targets
+    _raster_pass: RasterRenderPass,
+    fractal_pass: FractalRenderPass,
+    cloud_pass: CloudRenderPass,
+    cloud_params: Rc<Cell<CloudParams>>,
+    fractal_params: Rc<ParamStore>,
+    march_params: Rc<ParamStore>,
+    debug_view: Rc<Cell<DebugView>>,
+}
+
+impl FractalScene{
+    fn build(context: &SceneContext) -> Result<Box<dyn Scene>, RenderError>{
+        let raster_pass = RasterRenderPass::new(context.ctx.clone(), context.input_manager.clone(),
+            context.color_format)?;
+        let fractal_pass = FractalRenderPass::new(context, &raster_pass)?;
+        let cloud_pass = CloudRenderPass::new(context, &raster_pass)?;
+        Ok(Box::new(FractalScene{
+            _raster_pass: raster_pass,
+            fractal_pass,
+            cloud_pass,
+            cloud_params: context.cloud_params.clone(),
+            fractal_params: context.fractal_params.clone(),
+            march_params: context.march_params.clone(),
+            debug_view: context.debug_view.clone(),
+        }))
+    }
+}
+
+impl Scene for FractalScene{
+    fn draw(&self){
+        self.fractal_pass.draw();
+        // clouds would hide what the debug views show
+        self.cloud_pass.draw(self.debug_view.get() == DebugView::Off);
+    }
+
+    fn tunables(&self) -> Vec<Rc<dyn Tunable>>{
+        vec![self.fractal_params.clone(), self.march_params.clone(), self.cloud_params.clone()]
+    }
+}
+
+/// Owns the scenes, builds them on first use and fades between them when switching.
+pub struct SceneRegistry{
+    context: SceneContext,
+    scenes: Vec<RefCell<Option<Box<dyn Scene>>>>,
+    active: Cell<usize>,
+    crossfade_pass: CrossfadeRenderPass,
+    crossfade: Cell<f64>,
+    fade_start: Cell<Option<f64>>,
+    // the last scene that failed to build, until the app shows it
+    error: RefCell<Option<RenderError>>,
+}
+
+impl SceneRegistry{
+    pub fn new(context: SceneContext, initial: usize) -> Result<Self, RenderError>{
+        let crossfade_pass = CrossfadeRenderPass::new(context.ctx.clone())?;
+        let registry = SceneRegistry{
+            context,
+            scenes: SCENES.iter().map(|_| RefCell::new(None)).collect(),
+            active: Cell::new(initial),
+            crossfade_pass,
+            crossfade: Cell::new(DEFAULT_CROSSFADE),
+            fade_start: Cell::new(None),
+            error: RefCell::new(None),
+        };
+        registry.build(initial)?;
+        Ok(registry)
+    }
+
+    fn build(&self, index: usize) -> Result<(), RenderError>{
+        let mut scene = self.scenes[index].borrow_mut();
+        if scene.is_none() {
+            log!("Compiling scene '{}'", SCENE_NAMES[index]);
+            *scene = Some(SCENES[index](&self.context)?);
+        }
+        Ok(())
+    }
+
+    /// Switches to another scene, keeping the current one if the new one fails to build.
+    pub fn activate(&self, index: usize){
+        if index == self.active.get() || index >= SCENES.len() {
+            return;
+        }
+        if let Err(err) = self.build(index) {
+            log_error!("Failed to build scene '{}': {}", SCENE_NAMES[index], err);
+            *self.error.borrow_mut() = Some(err);
+            return;
+        }
+
+        if self.crossfade.get() > 0.0 {
+            self.draw_scene(self.active.get());
+            self.crossfade_pass.snapshot();
+            self.fade_start.set(Some(self.context.clock.elapsed()));
+        }
+        self.active.set(index);
+    }
+
+    /// Why the last scene switch failed, if it did since the last call.
+    pub fn take_error(&self) -> Option<RenderError>{
+        self.error.borrow_mut().take()
+    }
+
+    pub fn active_name(&self) -> &'static str{
+        SCENE_NAMES[self.active.get()]
+    }
+
+    pub fn activate_named(&self, name: &str){
+        match scene_index(name) {
+            Some(index) => self.activate(index),
+            None => {
+                log_warn!("Unknown scene '{}'", name);
+            }
+        }
+    }
+
+    /// Params of the sky and the active scene.
+    pub fn tunables(&self) -> Vec<Rc<dyn Tunable>>{
+        let mut tunables: Vec<Rc<dyn Tunable>> = vec![self.context.sky_pass.clone()];
+        if let Some(scene) = self.scenes[self.active.get()].borrow().as_ref() {
+            tunables.extend(scene.tunables());
+        }
+        tunables
+    }
+
+    fn draw_scene(&self, index: usize){
+        if let Some(scene) = self.scenes[index].borrow().as_ref() {
+            scene.draw();
+        }
+    }
+
+    pub fn set_crossfade(&self, seconds: f64){
+        self.crossfade.set(f64::max(seconds, 0.0));
+    }
+
+    /// Applies scene switches requested from the keyboard or gamepad, then draws the active scene.
+    pub fn draw(&self){
+        match self.context.input_manager.take_scene_request() {
+            Some(SceneRequest::Index(index)) => self.activate(index),
+            Some(SceneRequest::Step(SceneStep::Next)) => self.activate((self.active.get() + 1) % SCENES.len()),
+            Some(SceneRequest::Step(SceneStep::Previous)) =>
+                self.activate((self.active.get() + SCENES.len() - 1) % SCENES.len()),
+            None => (),
+        }
+
+        self.draw_scene(self.active.get());
+
+        if let Some(start) = self.fade_start.get() {
+            let progress = (self.context.clock.elapsed() - start) / self.crossfade.get();
+            if progress < 1.0 {
+                self.crossfade_pass.draw(1.0 - progress as f32);
+            }else{
+                self.fade_start.set(None);
+            }
+        }
+    }
+}
@@ -5,3 +5,73 @@ pub static PIXEL_VERT_SHADER: &'static str = include_str!("../shaders/marcher_ve
 pub static DEMO_FRAG_SHADER: &'static str = include_str!("../shaders/demo_frag.glsl");
 pub static FRACTAL_FRAG_SHADER: &'static str = include_str!("../shaders/fractal_frag.glsl");
 pub static CLOUD_FRAG_SHADER: &'static str = include_str!("../shaders/cloud_frag.glsl");
+pub static SKY_FRAG_SHADER: &str = include_str!("../shaders/sky_frag.glsl");
+pub static CLOUD_COMPOSITE_FRAG_SHADER: &str = include_str!("../shaders/cloud_composite_frag.glsl");
+pub static CROSSFADE_FRAG_SHADER: &str = include_str!("../shaders/crossfade_frag.glsl");
+
+/// GLSL shared between shaders, pulled in with `#include "name"` lines, see `with_includes`.
+static SNIPPETS: [(&str, &str); 3] = [
+    ("march_debug", include_str!("../shaders/march_debug.glsl")),
+    ("packed_distance", include_str!("../shaders/packed_distance.glsl")),
+    ("cone_prepass", include_str!("../shaders/cone_prepass.glsl")),
+];
+
+/// Replaces each `#include "name"` line with the snippet of that name from `SNIPPETS`. Lines
+/// naming an unknown snippet are kept, so compiling the shader reports them.
+pub fn with_includes(source: &str) -> String{
+    let mut out = String::with_capacity(source.len());
+    for line in source.lines(){
+        let name = line.trim().strip_prefix("#include")
+            .map(|name| name.trim().trim_matches('"'));
+        match name.and_then(|name| SNIPPETS.iter().find(|(snippet, _)| *snippet == name)) {
+            Some((_, snippet)) => out.push_str(snippet),
+            None =>{
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+    }
+    out
+}
+
+/// Inserts `#define` lines for each of the given names right after the `#version` directive.
+pub fn with_defines(source: &str, defines: &[&str]) -> String{
+    let (version, body) = source.split_at(source.find('\n').map_or(source.len(), |i| i + 1));
+    let mut out = String::from(version);
+    for define in defines{
+        out.push_str(&format!("#define {}\n", define));
+    }
+    out.push_str(body);
+    out
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::debug_view::DebugView;
+
+    #[test]
+    fn includes_resolve(){
+        for source in [DEMO_FRAG_SHADER, FRACTAL_FRAG_SHADER]{
+            let source = with_includes(source);
+            assert!(source.contains("vec3 debugColor("));
+            assert!(source.contains("float coneMarch("));
+            assert!(source.contains("float unpackDistance("));
+        }
+        for source in [DEMO_FRAG_SHADER, FRACTAL_FRAG_SHADER, CLOUD_FRAG_SHADER, CLOUD_COMPOSITE_FRAG_SHADER]{
+            assert!(!with_includes(source).lines().any(|line| line.trim().starts_with("#include")));
+        }
+        assert_eq!(with_includes("#include \"missing\"\nvoid main(){}"), "#include \"missing\"\nvoid main(){}\n");
+    }
+
+    #[test]
+    fn debug_snippet_only_uses_generated_views(){
+        let defines = DebugView::shader_defines();
+        let snippet = SNIPPETS.iter().find(|(name, _)| *name == "march_debug").unwrap().1;
+        let used = snippet.split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .filter(|word| word.starts_with("DEBUG_"));
+        for name in used{
+            assert!(defines.iter().any(|define| define.split(' ').next() == Some(name)), "{} isn't defined", name);
+        }
+    }
+}
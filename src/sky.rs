@@ -0,0 +1,158 @@
+use std::f32::consts::PI;
+use crate::params::{ParamInfo, ParamKind, ParamValue};
+use crate::vec_lib::vec3::Vec3f;
+
+// Sun position from the time of day, using the usual declination / hour angle approximation.
+// World axes are +y up, +x east and +z north.
+
+const AXIAL_TILT: f32 = 23.44;
+
+// zenith optical depths of the atmosphere, matching the coefficients in sky_frag.glsl
+const RAYLEIGH_DEPTH: [f32; 3] = [5.802e-6 * 8e3, 13.558e-6 * 8e3, 33.1e-6 * 8e3];
+const MIE_DEPTH: f32 = 4.44e-6 * 1.2e3;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TimeOfDay{
+    /// Local solar time in hours, 12 is noon.
+    pub hours: f32,
+    /// Latitude of the viewer in degrees, positive is north.
+    pub latitude: f32,
+    /// Day of the year starting from 0 on January 1st.
+    pub day_of_year: f32,
+}
+
+impl Default for TimeOfDay{
+    fn default() -> Self{
+        TimeOfDay{
+            hours: 15.0,
+            latitude: 40.0,
+            day_of_year: 80.0,
+        }
+    }
+}
+
+/// The fields of `TimeOfDay` in order, which place the sun and so the light direction.
+pub static SUN_PARAMS: [ParamInfo; 3] = [
+    ParamInfo{name: "hours", label: "Time of day", kind: ParamKind::Float{min: 0.0, max: 24.0}, default: ParamValue::Float(15.0)},
+    ParamInfo{name: "latitude", label: "Latitude", kind: ParamKind::Float{min: -90.0, max: 90.0}, default: ParamValue::Float(40.0)},
+    ParamInfo{name: "day_of_year", label: "Day of year", kind: ParamKind::Float{min: 0.0, max: 365.0}, default: ParamValue::Float(80.0)},
+];
+
+impl TimeOfDay{
+    /// The field at `index` into `SUN_PARAMS`.
+    pub fn param(&self, index: usize) -> ParamValue{
+        ParamValue::Float(match index {
+            0 => self.hours,
+            1 => self.latitude,
+            _ => self.day_of_year,
+        })
+    }
+
+    pub fn set_param(&mut self, index: usize, value: ParamValue){
+        if let ParamValue::Float(value) = value {
+            match index {
+                0 => self.hours = value,
+                1 => self.latitude = value,
+                _ => self.day_of_year = value,
+            }
+        }
+    }
+
+    /// Unit vector pointing towards the sun.
+    pub fn sun_direction(&self) -> Vec3f{
+        let declination = (-AXIAL_TILT * f32::cos(2.0 * PI / 365.0 * (self.day_of_year + 10.0))).to_radians();
+        let hour_angle = (15.0 * (self.hours - 12.0)).to_radians();
+        let latitude = self.latitude.to_radians();
+
+        // sun direction in the equatorial frame, rotated down by the latitude into the local frame
+        let east = -declination.cos() * hour_angle.sin();
+        let equator = declination.cos() * hour_angle.cos();
+        let pole = declination.sin();
+
+        Vec3f::new(
+            east,
+            equator * latitude.cos() + pole * latitude.sin(),
+            pole * latitude.cos() - equator * latitude.sin(),
+        ).normalize()
+    }
+
+    /// Color of the sunlight after passing through the atmosphere, fading out as the sun sets.
+    pub fn sun_color(&self) -> Vec3f{
+        let elevation = self.sun_direction().y().asin().to_degrees();
+        if elevation < -2.0 {
+            return Vec3f::new(0.0, 0.0, 0.0);
+        }
+
+        // Kasten-Young relative air mass, stays finite at the horizon
+        let zenith = 90.0 - f32::max(elevation, 0.0);
+        let air_mass = 1.0 / (zenith.to_radians().cos() + 0.50572 * f32::powf(96.07995 - zenith, -1.6364));
+        let horizon_fade = ((elevation + 2.0) / 4.0).clamp(0.0, 1.0);
+
+        let transmittance = |channel: usize| {
+            f32::exp(-(RAYLEIGH_DEPTH[channel] + MIE_DEPTH) * air_mass) * horizon_fade
+        };
+        Vec3f::new(transmittance(0), transmittance(1), transmittance(2))
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    const EPSILON: f32 = 0.001;
+
+    #[test]
+    fn param_defaults_match_the_struct(){
+        let time_of_day = TimeOfDay::default();
+        for (index, param) in SUN_PARAMS.iter().enumerate(){
+            assert_eq!(time_of_day.param(index), param.default, "sun.{}", param.name);
+        }
+        let mut moved = time_of_day;
+        moved.set_param(2, ParamValue::Float(172.0));
+        assert_eq!(moved.day_of_year, 172.0);
+    }
+
+    fn equinox_at_equator(hours: f32) -> TimeOfDay{
+        // declination is 0 where cos(2pi/365 * (day + 10)) is 0
+        TimeOfDay{hours, latitude: 0.0, day_of_year: 365.0 / 4.0 - 10.0}
+    }
+
+    #[test]
+    fn noon_at_equinox_on_equator_is_overhead(){
+        let dir = equinox_at_equator(12.0).sun_direction();
+        assert!((dir.y() - 1.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn sun_rises_east_and_sets_west(){
+        let sunrise = equinox_at_equator(6.0).sun_direction();
+        assert!(sunrise.y().abs() < EPSILON);
+        assert!((sunrise.x() - 1.0).abs() < EPSILON);
+
+        let sunset = equinox_at_equator(18.0).sun_direction();
+        assert!(sunset.y().abs() < EPSILON);
+        assert!((sunset.x() + 1.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn northern_noon_sun_is_south(){
+        let time = TimeOfDay{hours: 12.0, latitude: 50.0, day_of_year: 172.0};
+        let dir = time.sun_direction();
+        assert!(dir.z() < 0.0);
+        assert!(dir.x().abs() < EPSILON);
+        assert!((dir.length() - 1.0).abs() < EPSILON);
+        // summer solstice, elevation is 90 - latitude + tilt
+        assert!((dir.y().asin().to_degrees() - (90.0 - 50.0 + AXIAL_TILT)).abs() < 0.5);
+    }
+
+    #[test]
+    fn sun_reddens_near_horizon_and_is_off_at_night(){
+        let noon = equinox_at_equator(12.0).sun_color();
+        let evening = equinox_at_equator(17.5).sun_color();
+        assert!(noon.z() > evening.z());
+        assert!(evening.x() / evening.z() > noon.x() / noon.z());
+
+        let night = equinox_at_equator(0.0).sun_color();
+        assert_eq!(night.length(), 0.0);
+    }
+}
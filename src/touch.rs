@@ -0,0 +1,159 @@
+// Touch gestures from pointer events. One finger looks around, two fingers pan with their midpoint
+// and pinch to move forward and back. A finger that lands on the virtual joystick drives movement
+// instead, for as long as it stays down.
+//
+// Positions are in CSS pixels relative to the canvas.
+
+/// Circle on the canvas that works as a joystick when touched.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct JoystickZone{
+    pub x: f32,
+    pub y: f32,
+    pub radius: f32,
+}
+
+impl JoystickZone{
+    fn contains(&self, x: f32, y: f32) -> bool{
+        (x - self.x).powi(2) + (y - self.y).powi(2) <= self.radius * self.radius
+    }
+}
+
+/// Change in the gestures since the last pointer event, in CSS pixels.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct TouchGesture{
+    /// One finger drag.
+    pub look: (f32, f32),
+    /// Two finger drag of the midpoint.
+    pub pan: (f32, f32),
+    /// Growth of the distance between two fingers, positive when spreading them apart.
+    pub pinch: f32,
+}
+
+#[derive(Default)]
+pub struct TouchTracker{
+    // fingers used for gestures, in the order they landed
+    pointers: Vec<(i32, f32, f32)>,
+    joystick_pointer: Option<i32>,
+    joystick_offset: (f32, f32),
+}
+
+impl TouchTracker{
+    pub fn new() -> Self{
+        TouchTracker::default()
+    }
+
+    pub fn down(&mut self, id: i32, x: f32, y: f32, joystick: Option<&JoystickZone>){
+        if self.joystick_pointer.is_none() && joystick.is_some_and(|zone| zone.contains(x, y)) {
+            self.joystick_pointer = Some(id);
+            self.joystick_offset = (0.0, 0.0);
+            return;
+        }
+        self.pointers.retain(|pointer| pointer.0 != id);
+        self.pointers.push((id, x, y));
+    }
+
+    pub fn moved(&mut self, id: i32, x: f32, y: f32, joystick: Option<&JoystickZone>) -> TouchGesture{
+        if self.joystick_pointer == Some(id) {
+            if let Some(zone) = joystick {
+                let (dx, dy) = (x - zone.x, y - zone.y);
+                let length = f32::sqrt(dx * dx + dy * dy);
+                let scale = if length > zone.radius { zone.radius / length } else { 1.0 };
+                self.joystick_offset = (dx * scale, dy * scale);
+            }
+            return TouchGesture::default();
+        }
+
+        let index = match self.pointers.iter().position(|pointer| pointer.0 == id) {
+            Some(index) => index,
+            None => return TouchGesture::default(),
+        };
+        let (_, old_x, old_y) = self.pointers[index];
+        let before = self.pair();
+        self.pointers[index] = (id, x, y);
+
+        match (before, self.pair()) {
+            (Some((mid_before, dist_before)), Some((mid_after, dist_after))) => TouchGesture{
+                pan: (mid_after.0 - mid_before.0, mid_after.1 - mid_before.1),
+                pinch: dist_after - dist_before,
+                ..TouchGesture::default()
+            },
+            _ if self.pointers.len() == 1 => TouchGesture{
+                look: (x - old_x, y - old_y),
+                ..TouchGesture::default()
+            },
+            _ => TouchGesture::default(),
+        }
+    }
+
+    pub fn up(&mut self, id: i32){
+        if self.joystick_pointer == Some(id) {
+            self.joystick_pointer = None;
+            self.joystick_offset = (0.0, 0.0);
+        }
+        self.pointers.retain(|pointer| pointer.0 != id);
+    }
+
+    /// Midpoint and distance of the first two fingers.
+    fn pair(&self) -> Option<((f32, f32), f32)>{
+        if self.pointers.len() < 2 {
+            return None;
+        }
+        let (_, ax, ay) = self.pointers[0];
+        let (_, bx, by) = self.pointers[1];
+        let distance = f32::sqrt((bx - ax).powi(2) + (by - ay).powi(2));
+        Some((((ax + bx) * 0.5, (ay + by) * 0.5), distance))
+    }
+
+    /// Knob offset from the joystick center in CSS pixels, at most the zone radius.
+    pub fn joystick_offset(&self) -> (f32, f32){
+        self.joystick_offset
+    }
+
+    /// Joystick deflection with x to the right and y forward, each in [-1, 1].
+    pub fn joystick(&self, zone: &JoystickZone) -> (f32, f32){
+        (self.joystick_offset.0 / zone.radius, -self.joystick_offset.1 / zone.radius)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    const ZONE: JoystickZone = JoystickZone{x: 50.0, y: 400.0, radius: 40.0};
+
+    #[test]
+    fn one_finger_looks(){
+        let mut tracker = TouchTracker::new();
+        tracker.down(1, 200.0, 200.0, Some(&ZONE));
+        let gesture = tracker.moved(1, 210.0, 195.0, Some(&ZONE));
+        assert_eq!(gesture.look, (10.0, -5.0));
+        assert_eq!(gesture.pinch, 0.0);
+    }
+
+    #[test]
+    fn two_fingers_pan_and_pinch(){
+        let mut tracker = TouchTracker::new();
+        tracker.down(1, 100.0, 100.0, None);
+        tracker.down(2, 200.0, 100.0, None);
+        let gesture = tracker.moved(2, 220.0, 100.0, None);
+        assert_eq!(gesture.look, (0.0, 0.0));
+        assert_eq!(gesture.pan, (10.0, 0.0));
+        assert_eq!(gesture.pinch, 20.0);
+
+        // lifting one finger goes back to looking
+        tracker.up(1);
+        assert_eq!(tracker.moved(2, 230.0, 100.0, None).look, (10.0, 0.0));
+    }
+
+    #[test]
+    fn joystick_finger_moves_and_is_clamped(){
+        let mut tracker = TouchTracker::new();
+        tracker.down(3, 55.0, 400.0, Some(&ZONE));
+        let gesture = tracker.moved(3, 50.0, 300.0, Some(&ZONE));
+        assert_eq!(gesture, TouchGesture::default());
+        assert_eq!(tracker.joystick(&ZONE), (0.0, 1.0));
+
+        tracker.up(3);
+        assert_eq!(tracker.joystick(&ZONE), (0.0, 0.0));
+    }
+}
@@ -1,22 +1,32 @@
+use wasm_bindgen::closure::{Closure, WasmClosure};
+use wasm_bindgen::JsCast;
+use web_sys::EventTarget;
+use crate::error::RenderError;
 
+/// Event listeners added on behalf of something that can be torn down again, so they can all be
+/// removed together. The closures are handed to JS and freed once it lets go of them.
+#[derive(Default)]
+pub struct Listeners{
+    added: Vec<(EventTarget, &'static str, js_sys::Function)>,
+}
 
-#[macro_export]
-macro_rules! log {
-    ( $( $t:tt )* ) => {
-        web_sys::console::log_1(&format!( $( $t )* ).into());
+impl Listeners{
+    pub fn new() -> Self{
+        Listeners::default()
     }
-}
 
-#[macro_export]
-macro_rules! log_warn {
-    ( $( $t:tt )* ) => {
-        web_sys::console::warn_1(&format!( $( $t )* ).into());
+    pub fn add<T: ?Sized + WasmClosure>(&mut self, target: &EventTarget, event: &'static str, closure: Closure<T>)
+        -> Result<(), RenderError>{
+        let function: js_sys::Function = closure.into_js_value().unchecked_into();
+        target.add_event_listener_with_callback(event, &function)
+            .map_err(|_| RenderError::EventListener(String::from(event)))?;
+        self.added.push((target.clone(), event, function));
+        Ok(())
     }
-}
 
-#[macro_export]
-macro_rules! log_error {
-    ( $( $t:tt )* ) => {
-        web_sys::console::error_1(&format!( $( $t )* ).into());
+    pub fn remove_all(&mut self){
+        for (target, event, function) in self.added.drain(..){
+            let _ = target.remove_event_listener_with_callback(event, &function);
+        }
     }
 }
@@ -3,7 +3,7 @@ use auto_ops::*;
 use crate::vec_lib::vec2::Vec2f;
 
 /// Matrix layout
-/// ```
+/// ```text
 ///  +-------+
 ///  | 0 | 1 |
 ///  +-------+
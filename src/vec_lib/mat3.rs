@@ -4,7 +4,7 @@ use crate::vec_lib::mat4::Mat4f;
 use crate::vec_lib::vec3::Vec3f;
 
 /// Matrix layout
-/// ```
+/// ```text
 ///  +---+---+---+
 ///  | 0 | 1 | 2 |
 ///  +---+---+---+
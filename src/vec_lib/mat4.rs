@@ -5,7 +5,7 @@ use crate::vec_lib::vec3::Vec3f;
 use crate::vec_lib::vec4::Vec4f;
 
 /// Matrix layout
-/// ```
+/// ```text
 ///  +----+----+----+----+
 ///  | 0  | 1  | 2  | 3  |
 ///  +----+----+----+----+
@@ -1,6 +1,7 @@
 use std::ops::{Index, IndexMut};
 use crate::vec_lib::vec3::Vec3f;
 
+#[derive(Clone, Copy, PartialEq)]
 pub struct Quat{
     vals: [f32;4]
 }
@@ -23,6 +24,25 @@ impl Quat{
         ])
     }
 
+    /// Rotation that takes the x, y and z axes to the given orthonormal axes.
+    pub fn from_basis(x: &Vec3f, y: &Vec3f, z: &Vec3f) -> Self{
+        let trace = x.x() + y.y() + z.z();
+        let vals = if trace > 0.0 {
+            let s = 0.5 / f32::sqrt(trace + 1.0);
+            [(y.z() - z.y()) * s, (z.x() - x.z()) * s, (x.y() - y.x()) * s, 0.25 / s]
+        }else if x.x() > y.y() && x.x() > z.z() {
+            let s = 2.0 * f32::sqrt(1.0 + x.x() - y.y() - z.z());
+            [0.25 * s, (y.x() + x.y()) / s, (z.x() + x.z()) / s, (y.z() - z.y()) / s]
+        }else if y.y() > z.z() {
+            let s = 2.0 * f32::sqrt(1.0 + y.y() - x.x() - z.z());
+            [(y.x() + x.y()) / s, 0.25 * s, (z.y() + y.z()) / s, (z.x() - x.z()) / s]
+        }else{
+            let s = 2.0 * f32::sqrt(1.0 + z.z() - x.x() - y.y());
+            [(z.x() + x.z()) / s, (z.y() + y.z()) / s, 0.25 * s, (x.y() - y.x()) / s]
+        };
+        Self::new(vals).normalize()
+    }
+
     pub fn add(&self, other: &Self) -> Self{
         Self::new([
             self[0] + other[0],
@@ -90,13 +110,35 @@ impl Quat{
     }
 
     pub fn mult_vec3(&self, vec: &Vec3f) -> Vec3f{
-        todo!()
+        let axis = Vec3f::new(self[0], self[1], self[2]);
+        let t = axis.cross(vec).scale(2.0);
+        vec + t.scale(self[3]) + axis.cross(&t)
     }
 
+    /// Interpolates along the shorter arc between two unit quaternions.
+    pub fn slerp(&self, other: &Self, time: f32) -> Self{
+        let mut cos = self.dot(other);
+        let mut other = *other;
+        if cos < 0.0 {
+            cos = -cos;
+            other = Self::new([-other[0], -other[1], -other[2], -other[3]]);
+        }
 
+        // nearly the same rotation, the sine below would divide by almost 0
+        let (from, to) = if cos > 0.9995 {
+            (1.0 - time, time)
+        }else{
+            let angle = cos.acos();
+            let sin = angle.sin();
+            (f32::sin((1.0 - time) * angle) / sin, f32::sin(time * angle) / sin)
+        };
 
-    pub fn slerp(&self, other: &Self, time: f32) -> Self{
-        todo!()
+        Self::new([
+            self[0] * from + other[0] * to,
+            self[1] * from + other[1] * to,
+            self[2] * from + other[2] * to,
+            self[3] * from + other[3] * to,
+        ]).normalize()
     }
 
 }
@@ -0,0 +1,220 @@
+use crate::clouds::CloudParams;
+use crate::fractal::FRACTAL_PARAMS;
+use crate::params::{ParamInfo, ParamValue};
+use crate::sky::TimeOfDay;
+use crate::vec_lib::vec3::Vec3f;
+
+// The view encoded in the URL hash, so a link opens the exact same viewpoint:
+//
+//     #v=1&scene=fractal&pos=1,2.5,-3&fwd=0,0,1&up=0,1,0&fov=45&sun=15,40,80&clouds=0.03,0.1,0.9,0.4,0.5
+//         &fractal=exponent:8;iterations:4;bulb_pos:11,0,11;...
+//
+// Params given by name, like `fractal`, may leave some out, and those keep their current value
+// too.
+// `v` comes first and is bumped whenever the meaning of a field changes, so links from other
+// versions are recognised instead of misread. Fields that are left out keep their current value,
+// unknown ones are skipped.
+
+pub const VERSION: u32 = 1;
+
+#[derive(Clone, PartialEq)]
+pub struct ViewState{
+    pub scene: String,
+    pub position: Vec3f,
+    pub forward: Vec3f,
+    pub up: Vec3f,
+    /// Vertical field of view in degrees.
+    pub fov: f32,
+    pub time_of_day: TimeOfDay,
+    pub clouds: CloudParams,
+    /// Values of `FRACTAL_PARAMS` in order.
+    pub fractal: Vec<ParamValue>,
+}
+
+fn parse_floats<const N: usize>(value: &str) -> Option<[f32; N]>{
+    let mut floats = [0.0; N];
+    let mut parts = value.split(',');
+    for float in floats.iter_mut(){
+        *float = parts.next()?.trim().parse::<f32>().ok().filter(|value| value.is_finite())?;
+    }
+    match parts.next() {
+        Some(_) => None,
+        None => Some(floats),
+    }
+}
+
+fn join(values: &[f32]) -> String{
+    values.iter().map(|value| value.to_string()).collect::<Vec<String>>().join(",")
+}
+
+// `name:value` pairs separated by `;`, spaces are left out so they don't get escaped in the URL
+fn join_params(params: &[ParamInfo], values: &[ParamValue]) -> String{
+    params.iter().zip(values)
+        .map(|(param, value)| format!("{}:{}", param.name, value.to_text().replace(' ', "")))
+        .collect::<Vec<String>>()
+        .join(";")
+}
+
+// Sets the named values, skipping names that aren't in `params`.
+fn parse_params(params: &[ParamInfo], text: &str, values: &mut [ParamValue]) -> Option<()>{
+    for pair in text.split(';').filter(|pair| !pair.is_empty()){
+        let (name, value) = pair.split_once(':')?;
+        if let Some(index) = params.iter().position(|param| param.name == name) {
+            values[index] = params[index].parse(value)?;
+        }
+    }
+    Some(())
+}
+
+impl ViewState{
+    pub fn encode(&self) -> String{
+        let (p, f, u) = (&self.position, &self.forward, &self.up);
+        let (t, c) = (&self.time_of_day, &self.clouds);
+        format!("v={}&scene={}&pos={}&fwd={}&up={}&fov={}&sun={}&clouds={}&fractal={}",
+            VERSION,
+            self.scene,
+            join(&[p.x(), p.y(), p.z()]),
+            join(&[f.x(), f.y(), f.z()]),
+            join(&[u.x(), u.y(), u.z()]),
+            self.fov,
+            join(&[t.hours, t.latitude, t.day_of_year]),
+            join(&[c.density, c.absorption, c.scattering, c.coverage, c.anisotropy]),
+            join_params(&FRACTAL_PARAMS, &self.fractal))
+    }
+
+    /// Reads a URL hash, with or without the leading `#`. Fields it doesn't set are taken from
+    /// `current`.
+    pub fn decode(hash: &str, current: &ViewState) -> Result<ViewState, String>{
+        let mut fields = hash.trim_start_matches('#')
+            .split('&')
+            .filter(|field| !field.is_empty())
+            .map(|field| field.split_once('=').ok_or(format!("Malformed field '{}'", field)));
+
+        let version = match fields.next() {
+            Some(Ok(("v", version))) => version.parse::<u32>()
+                .map_err(|_| format!("Invalid version '{}'", version))?,
+            _ => return Err(String::from("Missing version")),
+        };
+        if version != VERSION {
+            return Err(format!("Unsupported version {}, expected {}", version, VERSION));
+        }
+
+        let mut state = current.clone();
+        for field in fields{
+            let (key, value) = field?;
+            let invalid = || format!("Invalid {} '{}'", key, value);
+            match key {
+                "scene" => state.scene = String::from(value),
+                "pos" | "fwd" | "up" => {
+                    let [x, y, z] = parse_floats::<3>(value).ok_or_else(invalid)?;
+                    let vector = Vec3f::new(x, y, z);
+                    match key {
+                        "pos" => state.position = vector,
+                        _ if vector.squared_length() == 0.0 => return Err(invalid()),
+                        "fwd" => state.forward = vector,
+                        _ => state.up = vector,
+                    }
+                }
+                "fov" => {
+                    let [fov] = parse_floats::<1>(value).filter(|[fov]| *fov > 0.0 && *fov < 180.0)
+                        .ok_or_else(invalid)?;
+                    state.fov = fov;
+                }
+                "sun" => {
+                    let [hours, latitude, day_of_year] = parse_floats::<3>(value).ok_or_else(invalid)?;
+                    state.time_of_day = TimeOfDay{hours, latitude, day_of_year};
+                }
+                "clouds" => {
+                    let [density, absorption, scattering, coverage, anisotropy] =
+                        parse_floats::<5>(value).ok_or_else(invalid)?;
+                    state.clouds = CloudParams{density, absorption, scattering, coverage, anisotropy};
+                }
+                "fractal" => {
+                    let mut fractal = state.fractal.clone();
+                    fractal.resize(FRACTAL_PARAMS.len(), ParamValue::Toggle(false));
+                    parse_params(&FRACTAL_PARAMS, value, &mut fractal).ok_or_else(invalid)?;
+                    state.fractal = fractal;
+                }
+                _ => (),
+            }
+        }
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::fractal::default_fractal_params;
+
+    fn state() -> ViewState{
+        ViewState{
+            scene: String::from("demo"),
+            position: Vec3f::new(0.0, 1.0, 0.0),
+            forward: Vec3f::new(0.0, 0.0, 1.0),
+            up: Vec3f::new(0.0, 1.0, 0.0),
+            fov: 45.0,
+            time_of_day: TimeOfDay::default(),
+            clouds: CloudParams::default(),
+            fractal: default_fractal_params(),
+        }
+    }
+
+    #[test]
+    fn round_trips_exactly(){
+        let mut view = state();
+        view.scene = String::from("fractal");
+        view.position = Vec3f::new(1.0 / 3.0, -2.5, 1e-7);
+        view.forward = Vec3f::new(0.6, 0.0, 0.8);
+        view.fov = 60.5;
+        view.time_of_day.hours = 6.25;
+        view.clouds.coverage = 0.75;
+        assert!(ViewState::decode(&format!("#{}", view.encode()), &state()) == Ok(view));
+    }
+
+    #[test]
+    fn fractal_params_round_trip(){
+        let mut view = state();
+        view.fractal[0] = ParamValue::Float(5.5);
+        view.fractal[2] = ParamValue::Vec3([-3.25, 1.0, 40.0]);
+        view.fractal[4] = ParamValue::Int(3);
+        view.fractal[6] = ParamValue::Color([1.0, 0.0, 0.2]);
+        view.fractal[7] = ParamValue::Toggle(false);
+        let hash = view.encode();
+        assert!(!hash.contains(' '));
+        let decoded = ViewState::decode(&hash, &state()).unwrap();
+        assert_eq!(decoded.fractal[..2], view.fractal[..2]);
+        assert_eq!(decoded.fractal[2..6], view.fractal[2..6]);
+        assert_eq!(decoded.fractal[7], view.fractal[7]);
+        // colors go through 8 bits a channel
+        assert_eq!(decoded.fractal[6], ParamValue::Color([1.0, 0.0, 51.0 / 255.0]));
+
+        let partial = ViewState::decode("v=1&fractal=iterations:6;glow:2", &view).unwrap();
+        assert_eq!(partial.fractal[1], ParamValue::Int(6));
+        assert_eq!(partial.fractal[0], view.fractal[0]);
+        assert!(ViewState::decode("v=1&fractal=exponent", &state()).is_err());
+        assert!(ViewState::decode("v=1&fractal=exponent:lots", &state()).is_err());
+        // `power` meant something else before it was renamed
+        let stale = ViewState::decode("v=1&fractal=power:3", &view).unwrap();
+        assert_eq!(stale.fractal[0], view.fractal[0]);
+    }
+
+    #[test]
+    fn missing_and_unknown_fields_keep_current(){
+        let view = ViewState::decode("v=1&scene=fractal&bloom=2", &state()).unwrap();
+        assert_eq!(view.scene, "fractal");
+        assert_eq!(view.fov, 45.0);
+        assert_eq!(view.clouds, CloudParams::default());
+    }
+
+    #[test]
+    fn rejects_other_versions_and_bad_values(){
+        assert!(ViewState::decode("scene=fractal", &state()).is_err());
+        assert!(ViewState::decode("v=2&scene=fractal", &state()).is_err());
+        assert!(ViewState::decode("v=1&pos=1,2", &state()).is_err());
+        assert!(ViewState::decode("v=1&fwd=0,0,0", &state()).is_err());
+        assert!(ViewState::decode("v=1&fov=NaN", &state()).is_err());
+        assert!(ViewState::decode("v=1&scene", &state()).is_err());
+        assert!(ViewState::decode("#v=1", &state()) == Ok(state()));
+    }
+}
@@ -1,98 +1,380 @@
-use crate::vec_lib::mat4;
 use crate::vec_lib::mat4::Mat4f;
 use crate::vec_lib::vec3::Vec3f;
 
+/// Closest an orbit camera gets to its target.
+const MIN_ORBIT_DISTANCE: f32 = 0.01;
+
+/// A view the input manager can steer. Amounts for `zoom` and `pan` are world units at a focus
+/// distance of 1, cameras that focus on a point scale them with the distance to it.
+pub trait Camera{
+    fn view_matrix(&self) -> Mat4f;
+    fn proj_matrix(&self) -> Mat4f;
+    fn projection(&self) -> Projection;
+    fn set_projection(&mut self, projection: Projection);
+    fn position(&self) -> Vec3f;
+    /// Point the camera looks at.
+    fn target(&self) -> Vec3f;
+    fn forward(&self) -> Vec3f;
+    fn right(&self) -> Vec3f;
+    fn up(&self) -> Vec3f;
+    fn translate(&mut self, vec: &Vec3f);
+    /// Turns by `yaw` around the world up axis and tilts by `pitch`, keeping the pitch within
+    /// `max_pitch` so the view can't flip over the top.
+    fn look(&mut self, yaw: f32, pitch: f32, max_pitch: f32);
+    /// Moves towards the target, negative amounts back off.
+    fn zoom(&mut self, amount: f32);
+    /// Slides the view sideways and up without turning it.
+    fn pan(&mut self, right: f32, up: f32);
+    /// Tilts the horizon, cameras that keep it level ignore this.
+    fn roll(&mut self, _radians: f32){}
+    /// Jumps to a pose, for playing back recorded paths.
+    fn set_pose(&mut self, position: Vec3f, forward: Vec3f, up: Vec3f);
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Projection{
+    pub fov: f32,
+    pub aspect: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Projection{
+    pub fn matrix(&self) -> Mat4f{
+        Mat4f::perspective(
+            self.fov,
+            self.aspect,
+            self.near,
+            self.far
+        )
+    }
+}
+
+/// View direction for a heading and an angle above the horizon. Yaw is measured from +z towards
+/// +x and the world up axis is +y.
+fn direction(yaw: f32, pitch: f32) -> Vec3f{
+    let (sin_yaw, cos_yaw) = yaw.sin_cos();
+    let (sin_pitch, cos_pitch) = pitch.sin_cos();
+    Vec3f::new(cos_pitch * sin_yaw, sin_pitch, cos_pitch * cos_yaw)
+}
+
+/// Yaw and pitch of a view direction, the inverse of [direction].
+fn angles(forward: &Vec3f) -> (f32, f32){
+    let forward = forward.normalize();
+    (forward.x().atan2(forward.z()), forward.y().clamp(-1.0, 1.0).asin())
+}
+
+/// Right and up vectors of a view with a level horizon.
+fn level_basis(forward: &Vec3f) -> (Vec3f, Vec3f){
+    let right = Vec3f::new(0.0, 1.0, 0.0).cross(forward).normalize().negate();
+    (right, right.cross(forward))
+}
+
+/// First person camera. The orientation is kept as angles and the basis vectors are rebuilt from
+/// them, so no matter how long it's turned around the axes stay orthonormal.
 pub struct FPSCamera{
     eye: Vec3f,
-    forward: Vec3f,
-    up: Vec3f,
-    right: Vec3f,
+    yaw: f32,
+    /// Angle of the view above the horizon.
+    pitch: f32,
+    /// Rotation around the view direction, 0 keeps the horizon level.
+    roll: f32,
 
     target_dist: f32,
     fov: f32,
     aspect: f32,
     near: f32,
     far: f32,
-
-    forward_initial: Vec3f,
-    up_initial: Vec3f,
 }
 
 
 impl FPSCamera{
     pub fn new(pos: Vec3f, target: Vec3f, up_dir:Vec3f, fov: f32, aspect: f32, near: f32, far:f32)
     -> Self{
-        let eye = pos;
-        let forward = (pos - target).normalize().negate();
-        let right = up_dir.cross(&forward).normalize().negate();
-        let target_dist = (eye - target).length();
-        FPSCamera{
-            eye,
-            forward,
-            up: up_dir.normalize(),
-            right,
-            target_dist,
+        let mut camera = FPSCamera{
+            eye: pos,
+            yaw: 0.0,
+            pitch: 0.0,
+            roll: 0.0,
+            target_dist: (pos - target).length(),
             fov,
             aspect,
             near,
             far,
-
-            forward_initial: forward.clone(),
-            up_initial: up_dir.normalize(),
-        }
+        };
+        camera.set_pose(pos, target - pos, up_dir);
+        camera
     }
+}
 
-    pub fn target(&self) -> Vec3f{
-        self.eye + self.forward.scale(self.target_dist)
+impl Camera for FPSCamera{
+    fn target(&self) -> Vec3f{
+        self.eye + self.forward().scale(self.target_dist)
     }
 
-    pub fn translate(&mut self, vec: &Vec3f){
+    fn translate(&mut self, vec: &Vec3f){
         self.eye = self.eye + vec;
     }
 
-    pub fn rotate(&mut self, axis: &Vec3f, radians: f32){
-        let norm_axis = axis.normalize();
-        let rot_mat = mat4::IDENTITY.rotate3d(&norm_axis, radians);
+    /// While rolled the turn follows the screen, so dragging up still tilts towards the top of
+    /// the screen.
+    fn look(&mut self, yaw: f32, pitch: f32, max_pitch: f32){
+        let (sin_roll, cos_roll) = self.roll.sin_cos();
+        self.yaw += yaw * cos_roll - pitch * sin_roll;
+        self.pitch = (self.pitch + pitch * cos_roll + yaw * sin_roll).clamp(-max_pitch, max_pitch);
+    }
 
-        self.forward = rot_mat.multiply_vec3(&self.forward);
-        self.up = rot_mat.multiply_vec3(&self.up);
-        self.right = rot_mat.multiply_vec3(&self.right);
+    fn roll(&mut self, radians: f32){
+        self.roll = (self.roll + radians).rem_euclid(2.0 * std::f32::consts::PI);
     }
 
-    pub fn position(&self) -> Vec3f{
-        self.eye
+    fn zoom(&mut self, amount: f32){
+        self.translate(&self.forward().scale(amount));
     }
 
-    pub fn forward(&self) -> Vec3f{
-        self.forward
+    fn pan(&mut self, right: f32, up: f32){
+        self.translate(&(self.right().scale(right) + self.up().scale(up)));
     }
 
-    pub fn right(&self) -> Vec3f{
-        self.right
+    fn set_pose(&mut self, position: Vec3f, forward: Vec3f, up: Vec3f){
+        self.eye = position;
+        let (yaw, pitch) = angles(&forward);
+        self.yaw = yaw;
+        self.pitch = pitch;
+        let (level_right, level_up) = level_basis(&self.forward());
+        self.roll = up.dot(&level_right).atan2(up.dot(&level_up)).rem_euclid(2.0 * std::f32::consts::PI);
     }
 
-    pub fn up(&self) -> Vec3f{
-        self.up
+    fn position(&self) -> Vec3f{
+        self.eye
+    }
+
+    fn forward(&self) -> Vec3f{
+        direction(self.yaw, self.pitch)
     }
 
-    pub fn up_initial(&self) -> Vec3f{
-        self.up_initial
+    fn right(&self) -> Vec3f{
+        let (right, up) = level_basis(&self.forward());
+        let (sin_roll, cos_roll) = self.roll.sin_cos();
+        right.scale(cos_roll) - up.scale(sin_roll)
     }
 
-    pub fn view_matrix(&self) -> Mat4f{
+    fn up(&self) -> Vec3f{
+        let (right, up) = level_basis(&self.forward());
+        let (sin_roll, cos_roll) = self.roll.sin_cos();
+        up.scale(cos_roll) + right.scale(sin_roll)
+    }
+
+    fn view_matrix(&self) -> Mat4f{
         Mat4f::look_at(&self.eye,
         &self.target(),
-            &self.up
+            &self.up()
         )
     }
 
-    pub fn proj_matrix(&self) -> Mat4f{
-        Mat4f::perspective(
-            self.fov,
-            self.aspect,
-            self.near,
-            self.far
-        )
+    fn proj_matrix(&self) -> Mat4f{
+        self.projection().matrix()
+    }
+
+    fn projection(&self) -> Projection{
+        Projection{
+            fov: self.fov,
+            aspect: self.aspect,
+            near: self.near,
+            far: self.far,
+        }
+    }
+
+    fn set_projection(&mut self, projection: Projection){
+        self.fov = projection.fov;
+        self.aspect = projection.aspect;
+        self.near = projection.near;
+        self.far = projection.far;
+    }
+}
+
+/// Circles around a target point, for inspecting a single object.
+pub struct OrbitCamera{
+    target: Vec3f,
+    distance: f32,
+    // direction from the eye to the target
+    yaw: f32,
+    pitch: f32,
+    projection: Projection,
+}
+
+impl OrbitCamera{
+    /// Orbits the point `distance` ahead of an existing view, so switching to it doesn't move
+    /// the picture.
+    pub fn from_view(eye: Vec3f, forward: Vec3f, distance: f32, projection: Projection) -> Self{
+        let distance = f32::max(distance, MIN_ORBIT_DISTANCE);
+        let (yaw, pitch) = angles(&forward);
+        OrbitCamera{
+            target: eye + forward.normalize().scale(distance),
+            distance,
+            yaw,
+            pitch,
+            projection,
+        }
+    }
+}
+
+impl Camera for OrbitCamera{
+    fn target(&self) -> Vec3f{
+        self.target
     }
 
-}
\ No newline at end of file
+    /// Moves the target, the camera keeps circling it from the same angle.
+    fn translate(&mut self, vec: &Vec3f){
+        self.target += vec;
+    }
+
+    fn look(&mut self, yaw: f32, pitch: f32, max_pitch: f32){
+        self.yaw += yaw;
+        self.pitch = (self.pitch + pitch).clamp(-max_pitch, max_pitch);
+    }
+
+    fn zoom(&mut self, amount: f32){
+        self.distance = f32::max(self.distance * (-amount).exp(), MIN_ORBIT_DISTANCE);
+    }
+
+    fn pan(&mut self, right: f32, up: f32){
+        let movement = self.right().scale(right) + self.up().scale(up);
+        self.translate(&movement.scale(self.distance));
+    }
+
+    /// Orbits the point ahead of the pose at the current distance. The up vector is ignored,
+    /// orbiting always keeps the horizon level.
+    fn set_pose(&mut self, position: Vec3f, forward: Vec3f, _up: Vec3f){
+        *self = OrbitCamera::from_view(position, forward, self.distance, self.projection);
+    }
+
+    fn position(&self) -> Vec3f{
+        self.target - self.forward().scale(self.distance)
+    }
+
+    fn forward(&self) -> Vec3f{
+        direction(self.yaw, self.pitch)
+    }
+
+    fn right(&self) -> Vec3f{
+        level_basis(&self.forward()).0
+    }
+
+    fn up(&self) -> Vec3f{
+        level_basis(&self.forward()).1
+    }
+
+    fn view_matrix(&self) -> Mat4f{
+        Mat4f::look_at(&self.position(), &self.target, &self.up())
+    }
+
+    fn proj_matrix(&self) -> Mat4f{
+        self.projection.matrix()
+    }
+
+    fn projection(&self) -> Projection{
+        self.projection
+    }
+
+    fn set_projection(&mut self, projection: Projection){
+        self.projection = projection;
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    const EPSILON: f32 = 0.0001;
+    const PROJECTION: Projection = Projection{fov: 45.0, aspect: 1.0, near: 0.1, far: 100.0};
+
+    fn assert_same_view(a: &dyn Camera, b: &dyn Camera){
+        let (view_a, view_b) = (a.view_matrix(), b.view_matrix());
+        for i in 0..16{
+            assert!((view_a[i] - view_b[i]).abs() < EPSILON, "{} != {} at {}", view_a[i], view_b[i], i);
+        }
+        assert!((a.position() - b.position()).length() < EPSILON);
+    }
+
+    #[test]
+    fn orbit_from_fps_keeps_the_view(){
+        let mut fps = FPSCamera::new(Vec3f::new(1.0, 2.0, 3.0), Vec3f::new(0.0, 0.0, 1.0),
+            Vec3f::new(0.0, 1.0, 0.0), PROJECTION.fov, PROJECTION.aspect, PROJECTION.near, PROJECTION.far);
+        fps.look(0.7, 0.3, 1.5);
+        let orbit = OrbitCamera::from_view(fps.position(), fps.forward(), 4.0, fps.projection());
+        assert_same_view(&fps, &orbit);
+        assert!((orbit.target() - (fps.position() + fps.forward().scale(4.0))).length() < EPSILON);
+    }
+
+    #[test]
+    fn fps_from_orbit_keeps_the_view(){
+        let mut orbit = OrbitCamera::from_view(Vec3f::new(0.0, 0.0, -5.0), Vec3f::new(0.0, 0.0, 1.0), 5.0,
+            PROJECTION);
+        orbit.look(-2.0, -0.4, 1.5);
+        let fps = FPSCamera::new(orbit.position(), orbit.target(), Vec3f::new(0.0, 1.0, 0.0),
+            PROJECTION.fov, PROJECTION.aspect, PROJECTION.near, PROJECTION.far);
+        assert_same_view(&orbit, &fps);
+    }
+
+    fn fps() -> FPSCamera{
+        FPSCamera::new(Vec3f::new(0.0, 1.0, 0.0), Vec3f::new(0.0, 0.0, 1.0), Vec3f::new(0.0, 1.0, 0.0),
+            PROJECTION.fov, PROJECTION.aspect, PROJECTION.near, PROJECTION.far)
+    }
+
+    #[test]
+    fn fps_basis_stays_orthonormal(){
+        let mut camera = fps();
+        camera.roll(0.3);
+        for i in 0..100_000{
+            camera.look(0.013 * (i % 7) as f32, 0.021 * ((i % 5) as f32 - 2.0), 1.5);
+        }
+        let (forward, right, up) = (camera.forward(), camera.right(), camera.up());
+        for vector in [forward, right, up].iter(){
+            assert!((vector.length() - 1.0).abs() < EPSILON);
+        }
+        assert!(forward.dot(&right).abs() < EPSILON);
+        assert!(forward.dot(&up).abs() < EPSILON);
+        assert!(right.dot(&up).abs() < EPSILON);
+    }
+
+    #[test]
+    fn rolled_look_follows_the_screen(){
+        let mut camera = fps();
+        camera.set_pose(Vec3f::new(0.0, 0.0, 0.0), Vec3f::new(0.0, 0.0, 1.0), Vec3f::new(0.0, 1.0, 0.0));
+        camera.roll(std::f32::consts::FRAC_PI_2);
+        let (up, pitch) = (camera.up(), camera.pitch);
+        camera.look(0.0, 0.1, 1.5);
+        // tilting up moves the view towards the rolled up vector, even though it's now sideways
+        assert!(camera.forward().dot(&up) > 0.09);
+        assert!((camera.pitch - pitch).abs() < EPSILON);
+    }
+
+    #[test]
+    fn fps_pose_keeps_roll(){
+        let mut camera = fps();
+        camera.look(1.0, 0.2, 1.5);
+        camera.roll(0.5);
+        let mut other = fps();
+        other.set_pose(camera.position(), camera.forward(), camera.up());
+        assert_same_view(&camera, &other);
+    }
+
+    #[test]
+    fn orbit_circles_and_zooms_on_target(){
+        let target = Vec3f::new(1.0, 0.0, 0.0);
+        let mut orbit = OrbitCamera::from_view(Vec3f::new(1.0, 0.0, -2.0), Vec3f::new(0.0, 0.0, 1.0), 2.0,
+            PROJECTION);
+        assert!((orbit.position() - Vec3f::new(1.0, 0.0, -2.0)).length() < EPSILON);
+
+        // half a turn puts the eye on the other side, still looking at the target
+        orbit.look(std::f32::consts::PI, 0.0, 1.5);
+        assert!((orbit.position() - Vec3f::new(1.0, 0.0, 2.0)).length() < EPSILON);
+        orbit.look(0.0, 10.0, 1.5);
+        assert!((orbit.forward().y() - f32::sin(1.5)).abs() < EPSILON);
+
+        orbit.zoom(std::f32::consts::LN_2);
+        assert!(((orbit.target() - orbit.position()).length() - 1.0).abs() < EPSILON);
+        assert!((orbit.target() - target).length() < EPSILON);
+    }
+}
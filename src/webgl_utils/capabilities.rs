@@ -0,0 +1,88 @@
+use web_sys::WebGl2RenderingContext;
+use web_sys::WebGl2RenderingContext as gl;
+
+/// Format used for the intermediate color texture the marchers render into.
+///
+/// The fractal pass stores the ray distance in the alpha channel, which needs a float format.
+/// When float color buffers can't be rendered to, the distance is packed into a separate
+/// RGBA8 texture instead.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColorBufferFormat{
+    Rgba32F,
+    Rgba16F,
+    Rgba8PackedDepth,
+}
+
+pub struct Capabilities{
+    color_buffer_float: bool,
+    color_buffer_half_float: bool,
+    texture_float_linear: bool,
+}
+
+fn has_extension(ctx: &WebGl2RenderingContext, name: &str) -> bool{
+    matches!(ctx.get_extension(name), Ok(Some(_)))
+}
+
+impl Capabilities{
+    pub fn detect(ctx: &WebGl2RenderingContext) -> Self{
+        Capabilities{
+            color_buffer_float: has_extension(ctx, "EXT_color_buffer_float"),
+            color_buffer_half_float: has_extension(ctx, "EXT_color_buffer_half_float"),
+            texture_float_linear: has_extension(ctx, "OES_texture_float_linear"),
+        }
+    }
+
+    pub fn color_format(&self) -> ColorBufferFormat{
+        if self.color_buffer_float && self.texture_float_linear {
+            ColorBufferFormat::Rgba32F
+        }else if self.color_buffer_float || self.color_buffer_half_float {
+            ColorBufferFormat::Rgba16F
+        }else{
+            ColorBufferFormat::Rgba8PackedDepth
+        }
+    }
+}
+
+impl ColorBufferFormat{
+    pub fn internal_format(&self) -> u32{
+        match self {
+            ColorBufferFormat::Rgba32F => gl::RGBA32F,
+            ColorBufferFormat::Rgba16F => gl::RGBA16F,
+            ColorBufferFormat::Rgba8PackedDepth => gl::RGBA8,
+        }
+    }
+
+    /// Format for small lookup textures holding light values above 1.
+    pub fn hdr_internal_format(&self) -> u32{
+        match self {
+            ColorBufferFormat::Rgba8PackedDepth => gl::RGBA8,
+            _ => gl::RGBA16F,
+        }
+    }
+
+    /// Whether the ray distance lives in its own packed RGBA8 texture rather than in the color alpha.
+    pub fn packed_depth(&self) -> bool{
+        *self == ColorBufferFormat::Rgba8PackedDepth
+    }
+
+    /// Preprocessor defines the marcher shaders need to read and write this format.
+    pub fn shader_defines(&self) -> &'static [&'static str]{
+        match self {
+            ColorBufferFormat::Rgba8PackedDepth => &["PACKED_DEPTH"],
+            _ => &[],
+        }
+    }
+
+    /// Message to show the user when a fallback format had to be picked.
+    pub fn warning(&self) -> Option<&'static str>{
+        match self {
+            ColorBufferFormat::Rgba32F => None,
+            ColorBufferFormat::Rgba16F =>
+                Some("32-bit float color buffers are unavailable, using half float buffers. \
+                      Distant geometry may lose some precision."),
+            ColorBufferFormat::Rgba8PackedDepth =>
+                Some("Float color buffers are unavailable, using 8-bit buffers with packed depth. \
+                      Colors may show banding."),
+        }
+    }
+}
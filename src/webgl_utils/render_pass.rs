@@ -4,6 +4,7 @@ use web_sys::{WebGl2RenderingContext, WebGlBuffer, WebGlProgram, WebGlTexture, W
 use js_sys::{Uint32Array, ArrayBuffer};
 use wasm_bindgen::{JsValue};
 use crate::{log, log_warn};
+use crate::error::RenderError;
 use crate::webgl_utils::utils::util_create_program;
 
 struct UnloadedTextureConfig{
@@ -13,12 +14,14 @@ struct UnloadedTextureConfig{
 
 struct LoadedTextureConfig{
     name: String,
-    texture: WebGlTexture
+    texture: WebGlTexture,
+    target: u32,
 }
 
 struct TextureInstance{
     name: String,
     texture: WebGlTexture,
+    target: u32,
     location: WebGlUniformLocation,
     unit: u32
 }
@@ -133,7 +136,12 @@ impl RenderPassConfig{
     }
 
     pub fn add_texture(mut self, texture: WebGlTexture, name: String) -> Self{
-        self.textures_loaded.push(LoadedTextureConfig{name, texture});
+        self.textures_loaded.push(LoadedTextureConfig{name, texture, target: WebGl2RenderingContext::TEXTURE_2D});
+        self
+    }
+
+    pub fn add_texture_3d(mut self, texture: WebGlTexture, name: String) -> Self{
+        self.textures_loaded.push(LoadedTextureConfig{name, texture, target: WebGl2RenderingContext::TEXTURE_3D});
         self
     }
 
@@ -143,16 +151,16 @@ impl RenderPassConfig{
     }
 
     pub fn configure(self, gl: WebGl2RenderingContext)
-                         -> Result<RenderPass, String>{
+                         -> Result<RenderPass, RenderError>{
         let shader_program = util_create_program(&gl, &self.v_shader, &self.f_shader)?;
         gl.use_program(Some(&shader_program));
 
         let vao = gl.create_vertex_array()
-            .ok_or(String::from("Failed to create vertex array object."))?;
+            .ok_or(RenderError::ResourceCreation(String::from("a vertex array object")))?;
         gl.bind_vertex_array(Some(&vao));
 
         let index_buffer = gl.create_buffer()
-            .ok_or(String::from("Failed to create index buffer."))?;
+            .ok_or(RenderError::ResourceCreation(String::from("an index buffer")))?;
         gl.bind_buffer(WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER, Some(&index_buffer));
         gl.buffer_data_with_opt_array_buffer(
             WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER,
@@ -169,7 +177,7 @@ impl RenderPassConfig{
             }
 
             let buffer = gl.create_buffer()
-                .ok_or(format!("Failed to create buffer for attribute '{}'", attr_config.name))?;
+                .ok_or(RenderError::ResourceCreation(format!("a buffer for attribute '{}'", attr_config.name)))?;
             gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&buffer));
             gl.buffer_data_with_opt_array_buffer(
               WebGl2RenderingContext::ARRAY_BUFFER,
@@ -197,7 +205,7 @@ impl RenderPassConfig{
         for uniform_config in self.uniforms{
             let loc_res =
                 gl.get_uniform_location(&shader_program,uniform_config.name.as_str())
-                .ok_or(format!("Failed to find uniform '{}'", uniform_config.name));
+                .ok_or(RenderError::ResourceCreation(format!("uniform '{}'", uniform_config.name)));
 
             if loc_res.is_err(){
                 log_warn!("Uniform '{}' doesn't exist or was optimized out, Skipping.", uniform_config.name);
@@ -218,7 +226,7 @@ impl RenderPassConfig{
         for texture_config in self.textures_loaded{
             let loc_res =
                 gl.get_uniform_location(&shader_program,texture_config.name.as_str())
-                .ok_or(format!("Failed to find texture '{}'", texture_config.name));
+                .ok_or(RenderError::ResourceCreation(format!("texture '{}'", texture_config.name)));
 
             if loc_res.is_err(){
                 log_warn!("Texture '{}' doesn't exist or was optimized out, Skipping.", texture_config.name);
@@ -233,6 +241,7 @@ impl RenderPassConfig{
             textures.push(TextureInstance{
                 name: texture_config.name,
                 texture: texture_config.texture,
+                target: texture_config.target,
                 location: loc,
                 unit: unit,
             })
@@ -277,7 +286,7 @@ impl RenderPass{
         for texture in &self.textures{
             gl.uniform1i(Some(&texture.location), texture.unit as i32);
             gl.active_texture(WebGl2RenderingContext::TEXTURE0 + texture.unit);
-            gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&texture.texture));
+            gl.bind_texture(texture.target, Some(&texture.texture));
         }
 
         if instances == 0{
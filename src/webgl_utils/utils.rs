@@ -1,15 +1,15 @@
-use web_sys::{WebGl2RenderingContext, WebGlProgram, WebGlShader};
-use crate::log_error;
+use web_sys::{WebGl2RenderingContext, WebGlProgram, WebGlShader, WebGlTexture};
+use crate::error::RenderError;
 
 pub fn util_create_shader(ctx: &WebGl2RenderingContext, shader_type:u32, source: &str)
-    -> Result<WebGlShader, String>{
+    -> Result<WebGlShader, RenderError>{
     let shader : WebGlShader;
     match ctx.create_shader(shader_type) {
         Some(res) => {
             shader = res;
         },
         None => {
-            return Err(String::from("Failed to create shader."));
+            return Err(RenderError::ResourceCreation(String::from("a shader")));
         }
     };
     ctx.shader_source(&shader, source);
@@ -17,23 +17,18 @@ pub fn util_create_shader(ctx: &WebGl2RenderingContext, shader_type:u32, source:
 
     let compile_status = ctx.get_shader_parameter(&shader, WebGl2RenderingContext::COMPILE_STATUS);
     if compile_status.is_falsy() {
-        return match ctx.get_shader_info_log(&shader) {
-            Some(res) => {
-                Err(format!("Error compiling shader:\n{}", res))
-            },
-            _ => {
-                Err(String::from("Error compiling shader."))
-            }
-        };
+        let stage = if shader_type == WebGl2RenderingContext::VERTEX_SHADER { "vertex" } else { "fragment" };
+        let log = ctx.get_shader_info_log(&shader).unwrap_or_default();
+        return Err(RenderError::shader_compile(stage, source, &log));
     }
 
     Ok(shader)
 }
 
 pub fn util_create_program(ctx: &WebGl2RenderingContext, v_shader: &String, f_shader: &String)
-    -> Result<WebGlProgram, String>{
+    -> Result<WebGlProgram, RenderError>{
     let shader_program: WebGlProgram = ctx.create_program()
-        .ok_or("Context failed to create shader program.")?;
+        .ok_or(RenderError::ResourceCreation(String::from("a shader program")))?;
 
     let vertex_shader: WebGlShader =
         util_create_shader(ctx, WebGl2RenderingContext::VERTEX_SHADER, v_shader)?;
@@ -47,28 +42,79 @@ pub fn util_create_program(ctx: &WebGl2RenderingContext, v_shader: &String, f_sh
 
     let link_status = ctx.get_program_parameter(&shader_program, WebGl2RenderingContext::LINK_STATUS);
     if link_status.is_falsy() {
-        return match ctx.get_program_info_log(&shader_program)  {
-            Some(res) =>{
-                Err(format!("Error linking program.\n{}", res))
-            },
-            _ => {
-                Err(String::from("Error linking program."))
-            }
-        } ;
+        let log = ctx.get_program_info_log(&shader_program).unwrap_or_default();
+        return Err(RenderError::ShaderLink{log: String::from(log.trim())});
     }
 
     ctx.validate_program(&shader_program);
     let validate_status = ctx.get_program_parameter(&shader_program, WebGl2RenderingContext::VALIDATE_STATUS);
     if validate_status.is_falsy() {
-        return match ctx.get_program_info_log(&shader_program)  {
-            Some(res) =>{
-                Err(format!("Error validating program.\n{}", res))
-            },
-            _ => {
-                Err(String::from("Error validating program."))
-            }
-        };
+        let log = ctx.get_program_info_log(&shader_program).unwrap_or_default();
+        return Err(RenderError::ShaderLink{log: format!("Validation failed. {}", log.trim())});
     }
 
     Ok(shader_program)
 }
+
+
+/// Creates a repeating, linearly filtered RGBA8 3D texture from `size`³ voxels of RGBA data.
+pub fn util_create_texture_3d(ctx: &WebGl2RenderingContext, size: i32, data: &[u8])
+    -> Result<WebGlTexture, RenderError>{
+    let texture = ctx.create_texture()
+        .ok_or(RenderError::ResourceCreation(String::from("a 3D texture")))?;
+    ctx.bind_texture(WebGl2RenderingContext::TEXTURE_3D, Some(&texture));
+    ctx.tex_image_3d_with_opt_u8_array(
+        WebGl2RenderingContext::TEXTURE_3D,
+        0,
+        WebGl2RenderingContext::RGBA8 as i32,
+        size, size, size,
+        0,
+        WebGl2RenderingContext::RGBA,
+        WebGl2RenderingContext::UNSIGNED_BYTE,
+        Some(data)
+    ).map_err(|_| RenderError::ResourceCreation(String::from("the 3D texture data")))?;
+    ctx.generate_mipmap(WebGl2RenderingContext::TEXTURE_3D);
+
+    for wrap in [WebGl2RenderingContext::TEXTURE_WRAP_S, WebGl2RenderingContext::TEXTURE_WRAP_T,
+        WebGl2RenderingContext::TEXTURE_WRAP_R]{
+        ctx.tex_parameteri(WebGl2RenderingContext::TEXTURE_3D, wrap, WebGl2RenderingContext::REPEAT as i32);
+    }
+    ctx.tex_parameteri(WebGl2RenderingContext::TEXTURE_3D, WebGl2RenderingContext::TEXTURE_MIN_FILTER,
+        WebGl2RenderingContext::LINEAR_MIPMAP_LINEAR as i32);
+    ctx.tex_parameteri(WebGl2RenderingContext::TEXTURE_3D, WebGl2RenderingContext::TEXTURE_MAG_FILTER,
+        WebGl2RenderingContext::LINEAR as i32);
+    ctx.bind_texture(WebGl2RenderingContext::TEXTURE_3D, None);
+
+    Ok(texture)
+}
+
+/// Creates a repeating, nearest filtered single channel R8 texture.
+pub fn util_create_texture_r8(ctx: &WebGl2RenderingContext, width: i32, height: i32, data: &[u8])
+    -> Result<WebGlTexture, RenderError>{
+    let texture = ctx.create_texture()
+        .ok_or(RenderError::ResourceCreation(String::from("a texture")))?;
+    ctx.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&texture));
+    ctx.pixel_storei(WebGl2RenderingContext::UNPACK_ALIGNMENT, 1);
+    ctx.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+        WebGl2RenderingContext::TEXTURE_2D,
+        0,
+        WebGl2RenderingContext::R8 as i32,
+        width, height,
+        0,
+        WebGl2RenderingContext::RED,
+        WebGl2RenderingContext::UNSIGNED_BYTE,
+        Some(data)
+    ).map_err(|_| RenderError::ResourceCreation(String::from("the texture data")))?;
+    ctx.pixel_storei(WebGl2RenderingContext::UNPACK_ALIGNMENT, 4);
+
+    for wrap in [WebGl2RenderingContext::TEXTURE_WRAP_S, WebGl2RenderingContext::TEXTURE_WRAP_T]{
+        ctx.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, wrap, WebGl2RenderingContext::REPEAT as i32);
+    }
+    ctx.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_MIN_FILTER,
+        WebGl2RenderingContext::NEAREST as i32);
+    ctx.tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::TEXTURE_MAG_FILTER,
+        WebGl2RenderingContext::NEAREST as i32);
+    ctx.bind_texture(WebGl2RenderingContext::TEXTURE_2D, None);
+
+    Ok(texture)
+}
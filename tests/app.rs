@@ -1,41 +1,85 @@
-use wasm_bindgen_test::{wasm_bindgen_test_configure, wasm_bindgen_test};
-use wasm_bindgen::JsValue;
-use wasm_bindgen_futures::JsFuture;
+use webgl_thing::bindings::{Action, ActionState, Bindings, Modifiers};
+use webgl_thing::clouds::CloudParams;
+use webgl_thing::fractal::default_fractal_params;
+use webgl_thing::shaders::with_defines;
+use webgl_thing::sky::TimeOfDay;
 use webgl_thing::vec_lib::{
-    mat4::Mat4f
+    mat4::Mat4f,
+    vec3::Vec3f,
+    vec4::Vec4f,
 };
+use webgl_thing::view_state::ViewState;
 
-wasm_bindgen_test_configure!(run_in_browser);
+// These run natively against the core, `cargo test --no-default-features` doesn't need a browser.
 
-
-// This runs a unit test in native Rust, so it can only use Rust APIs.
 #[test]
-fn rust_test() {
-    assert_eq!(1, 1);
+fn perspective_maps_near_and_far_to_the_clip_range(){
+    let (near, far) = (0.5f32, 100.0f32);
+    let projection = Mat4f::perspective(60.0f32, 1.5f32, near, far);
+    let ndc_depth = |distance: f32| {
+        let clip = projection.multiply_vec4(&Vec4f::new(0.0, 0.0, -distance, 1.0));
+        clip.z() / clip.w()
+    };
+    assert!((ndc_depth(near) + 1.0).abs() < 1e-4);
+    assert!((ndc_depth(far) - 1.0).abs() < 1e-4);
+    assert!(ndc_depth(1.0) < ndc_depth(10.0));
 }
 
+#[test]
+fn overridden_bindings_drive_the_action_state(){
+    let bindings = Bindings::with_overrides("move_forward = ArrowUp, Ctrl+KeyW").unwrap();
+    let mut actions = ActionState::new(bindings);
+    let ctrl = Modifiers{ctrl: true, ..Modifiers::default()};
 
-// This runs a unit test in the browser, so it can use browser APIs.
-#[wasm_bindgen_test]
-fn web_test() {
-    assert_eq!(1, 1);
+    assert_eq!(actions.press("KeyW", &Modifiers::default()), None);
+    assert_eq!(actions.press("KeyW", &ctrl), Some(Action::MoveForward));
+    assert!(actions.held(Action::MoveForward));
+    actions.release("KeyW");
+    assert!(!actions.held(Action::MoveForward));
+    assert_eq!(actions.press("KeyS", &Modifiers::default()), Some(Action::MoveBackward));
 }
 
 #[test]
-fn mat4_multiply(){
-    let mat1 = Mat4f::perspective(10.0f32, 1.0f32, 2.0f32, 1000f32);
-    panic!();
+fn view_state_survives_the_url_hash(){
+    let view = ViewState{
+        scene: String::from("fractal"),
+        position: Vec3f::new(1.0, 2.5, -3.0),
+        forward: Vec3f::new(0.0, 0.0, 1.0),
+        up: Vec3f::new(0.0, 1.0, 0.0),
+        fov: 45.0,
+        time_of_day: TimeOfDay{hours: 6.5, ..TimeOfDay::default()},
+        clouds: CloudParams{coverage: 0.8, ..CloudParams::default()},
+        fractal: default_fractal_params(),
+    };
+    let hash = format!("#{}", view.encode());
+    assert!(ViewState::decode(&hash, &view).unwrap() == view);
 }
 
-// This runs a unit test in the browser, and in addition it supports asynchronous Future APIs.
-#[wasm_bindgen_test(async)]
-async fn async_test(){
-    // Creates a JavaScript Promise which will asynchronously resolve with the value 42.
-    let promise = js_sys::Promise::resolve(&JsValue::from(42));
+#[test]
+fn defines_go_after_the_version(){
+    let source = with_defines("#version 300 es\nvoid main(){}\n", &["SHADOWS", "STEPS 64"]);
+    assert_eq!(source, "#version 300 es\n#define SHADOWS\n#define STEPS 64\nvoid main(){}\n");
+}
+
+// These run in the browser with `wasm-pack test`, so they can use browser APIs.
+#[cfg(all(target_arch = "wasm32", feature = "browser"))]
+mod browser{
+    use wasm_bindgen::JsValue;
+    use wasm_bindgen_futures::JsFuture;
+    use wasm_bindgen_test::{wasm_bindgen_test_configure, wasm_bindgen_test};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    // This runs a unit test in the browser, and in addition it supports asynchronous Future APIs.
+    #[wasm_bindgen_test(async)]
+    async fn async_test(){
+        // Creates a JavaScript Promise which will asynchronously resolve with the value 42.
+        let promise = js_sys::Promise::resolve(&JsValue::from(42));
 
-    // Converts that Promise into a Future.
-    // The unit test will wait for the Future to resolve.
-    let x = JsFuture::from(promise).await.unwrap();
+        // Converts that Promise into a Future.
+        // The unit test will wait for the Future to resolve.
+        let x = JsFuture::from(promise).await.unwrap();
 
-    assert_eq!(x, 42);
+        assert_eq!(x, 42);
+    }
 }